@@ -10,7 +10,7 @@ use tokio::stream::StreamExt;
 use pin_utils::pin_mut;
 use tmi_rs::client_messages::ClientMessage;
 use tmi_rs::event::*;
-use tmi_rs::pool::PoolConfig;
+use tmi_rs::pool::{OverflowBehavior, PoolConfig};
 use tmi_rs::{pool::connect, TwitchClientConfig, TwitchClientConfigBuilder};
 
 /// To run this example, the TWITCH_CHANNEL, TWITCH_USERNAME and TWITCH_AUTH environment variables
@@ -32,6 +32,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             init_connections: 2,
             connection_limit: 10,
             threshold: 3,
+            overflow_behavior: OverflowBehavior::JoinLeastLoaded,
         },
     )
     .await?;
@@ -1,9 +1,13 @@
 use derive_builder::Builder;
+use rand::Rng;
 use smallvec::SmallVec;
+use std::sync::Arc;
 
+use crate::event::{ConnectMessageEventData, Event};
+use crate::irc_constants::RPL_ENDOFMOTD;
 use crate::stream::rate_limits::RateLimiterConfig;
 use crate::stream::{RecvMiddleware, SendMiddleware};
-use crate::Capability;
+use crate::{Capability, ClientMessage};
 use tokio::time::Duration;
 use url::Url;
 
@@ -33,6 +37,11 @@ pub struct TwitchClientConfig {
     #[builder(default = "true")]
     pub cap_tags: bool,
 
+    /// Additional capabilities to request, e.g. ones not covered by the options above
+    /// (default: none)
+    #[builder(default)]
+    pub custom_capabilities: Vec<&'static str>,
+
     /// Receiver middlewares
     #[builder(default = "None", setter(strip_option))]
     pub recv_middleware: Option<RecvMiddleware>,
@@ -41,13 +50,29 @@ pub struct TwitchClientConfig {
     #[builder(default = "None", setter(strip_option))]
     pub send_middleware: Option<SendMiddleware>,
 
+    /// Called with every message right before it is written to the connection, after rate
+    /// limiting and in the order the messages are sent. Useful for audit logging (default: none)
+    #[builder(default = "None", setter(strip_option))]
+    pub sent_observer: Option<SentObserver>,
+
     /// Rate limiting configuration
     #[builder(default = "RateLimiterConfig::default()")]
     pub rate_limiter: RateLimiterConfig,
 
-    /// Maximum number of retries to reconnect to Twitch
-    #[builder(default = "20")]
-    pub max_reconnects: u32,
+    /// Maximum number of retries to reconnect to Twitch, `None` keeps retrying indefinitely
+    #[builder(default = "Some(20)")]
+    pub max_reconnects: Option<u32>,
+
+    /// How long a connection has to stay connected before it is considered stable. The number of
+    /// reconnect attempts is only reset once a connection was stable, connections that keep
+    /// dropping shortly after connecting count towards `max_reconnects`.
+    #[builder(default = "Duration::from_secs(60)")]
+    pub stability_threshold: Duration,
+
+    /// Maximum number of channels joined on a single connection. Twitch doesn't reliably join
+    /// more than about 90 channels per connection, use a connection pool to join more.
+    #[builder(default = "90")]
+    pub channel_limit: usize,
 
     /// Buffer size
     #[builder(default = "20")]
@@ -57,12 +82,99 @@ pub struct TwitchClientConfig {
     #[builder(default = "true")]
     pub heartbeat: bool,
 
-    /// Delay until reconnect after loss of connection
-    #[builder(default = "Duration::from_secs(5)")]
-    pub reconnect_delay: Duration,
+    /// Delay until the first reconnect after loss of connection. The delay doubles with every
+    /// further failed attempt, up to `reconnect_max_delay`, and is reset once a connection stayed
+    /// active for `stability_threshold`.
+    #[builder(default = "Duration::from_secs(1)")]
+    pub reconnect_base_delay: Duration,
+
+    /// Upper limit for the delay between reconnect attempts
+    #[builder(default = "Duration::from_secs(60)")]
+    pub reconnect_max_delay: Duration,
+
+    /// Randomly shorten each reconnect delay by up to half, so that many clients disconnected at
+    /// the same time don't all reconnect at once (default: true)
+    #[builder(default = "true")]
+    pub reconnect_jitter: bool,
+
+    /// Label to identify connections in log output. Each connection is additionally numbered,
+    /// e.g. `bot-0`, `bot-1` for the connections of a pool labeled `bot` (default: `conn`)
+    #[builder(default = "None", setter(strip_option))]
+    pub connection_label: Option<String>,
+
+    /// Decides which received event marks the connection as active, so messages can be sent
+    /// (default: the end of the welcome message, `RPL_ENDOFMOTD`)
+    #[builder(default = "Arc::new(is_end_of_motd)")]
+    pub ready_predicate: ReadyPredicate,
+
+    /// Collect messages that become ready to send within this time window after the first one and
+    /// send them together in a single websocket frame. Rate limits still apply to every single
+    /// message. Reduces the overhead at very high message volumes, at the cost of delaying every
+    /// message by up to the window. (default: disabled, every message is sent on its own)
+    #[builder(default = "None", setter(strip_option))]
+    pub batch_window: Option<Duration>,
+
+    /// Maximum time to wait for the connection to become active when connecting before giving up
+    /// with an error (default: wait indefinitely)
+    #[builder(default = "None", setter(strip_option))]
+    pub ready_timeout: Option<Duration>,
+
+    /// Keep the IRC line every event was parsed from, available through `Event::raw`. Useful for
+    /// debugging or relaying lines unmodified, costs an allocation per event (default: false)
+    #[builder(default = "false")]
+    pub keep_raw_lines: bool,
+
+    /// Return IRC commands that are not known to this library as `Error::UnknownIrcCommand`
+    /// instead of `Event::Unknown` events (default: false)
+    #[builder(default = "false")]
+    pub strict_parsing: bool,
+
+    /// Lowercase the tag keys of received messages, so tags sent with unusual case are found by
+    /// their lowercase names. Keys are kept as they were sent by default, which is required to
+    /// look up camel case tags like `msg-param-viewerCount` by their original name
+    /// (default: false)
+    #[builder(default = "false")]
+    pub lowercase_tag_keys: bool,
+}
+
+/// Function that is called with every received event while connecting and returns true once the
+/// connection is ready
+pub type ReadyPredicate = Arc<dyn Fn(&Event) -> bool + Send + Sync + 'static>;
+
+/// Function that is called with every message that is about to be written to the connection
+pub type SentObserver = Arc<dyn Fn(&ClientMessage) + Send + Sync + 'static>;
+
+fn is_end_of_motd(event: &Event) -> bool {
+    match event {
+        Event::ConnectMessage(msg) => msg.command() == RPL_ENDOFMOTD,
+        _ => false,
+    }
 }
 
 impl TwitchClientConfig {
+    pub(crate) fn connection_label(&self, connection_id: usize) -> String {
+        format!(
+            "{}-{}",
+            self.connection_label.as_deref().unwrap_or("conn"),
+            connection_id
+        )
+    }
+
+    /// Delay before a reconnect, given the number of reconnects since the connection was last
+    /// stable
+    pub(crate) fn reconnect_delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .reconnect_base_delay
+            .checked_mul(2u32.saturating_pow(attempt))
+            .unwrap_or(self.reconnect_max_delay)
+            .min(self.reconnect_max_delay);
+        if self.reconnect_jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+        } else {
+            delay
+        }
+    }
+
     pub(crate) fn get_capabilities(&self) -> SmallVec<[Capability; 3]> {
         let mut capabilities = SmallVec::new();
         if self.cap_commands {
@@ -74,6 +186,42 @@ impl TwitchClientConfig {
         if self.cap_membership {
             capabilities.push(Capability::Membership)
         }
+        capabilities.extend(
+            self.custom_capabilities
+                .iter()
+                .map(|&capability| Capability::Custom(capability)),
+        );
         capabilities
     }
 }
+
+#[cfg(test)]
+mod test {
+    use tokio::time::Duration;
+
+    use crate::TwitchClientConfigBuilder;
+
+    #[test]
+    fn test_reconnect_delay() {
+        let mut builder = TwitchClientConfigBuilder::default();
+        builder
+            .username("tester".to_string())
+            .token("oauth:tester".to_string())
+            .reconnect_base_delay(Duration::from_secs(1))
+            .reconnect_max_delay(Duration::from_secs(30))
+            .reconnect_jitter(false);
+        let cfg = builder.build().unwrap();
+        let delays = (0..7)
+            .map(|attempt| cfg.reconnect_delay(attempt).as_secs())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![1, 2, 4, 8, 16, 30, 30]);
+        assert_eq!(cfg.reconnect_delay(u32::MAX), Duration::from_secs(30));
+
+        let cfg = builder.reconnect_jitter(true).build().unwrap();
+        for attempt in 0..10 {
+            let delay = cfg.reconnect_delay(attempt);
+            let full = Duration::from_secs(1 << attempt.min(5)).min(Duration::from_secs(30));
+            assert!(delay >= full / 2 && delay <= full, "{:?}", delay);
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Message sender that records messages instead of sending them, for testing without a connection
+
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::runtime;
+use crate::stream::SentClientMessage;
+use crate::{ClientMessage, MessageResponse, MessageSender};
+
+/// Messages recorded by a dry run [`MessageSender`](crate::MessageSender), see
+/// [`MessageSender::dry_run`](crate::MessageSender::dry_run).
+#[derive(Clone, Debug, Default)]
+pub struct RecordedMessages {
+    messages: Arc<Mutex<Vec<ClientMessage>>>,
+}
+
+impl RecordedMessages {
+    /// All messages sent so far, in the order they were sent
+    pub fn messages(&self) -> Vec<ClientMessage> {
+        self.messages.lock().clone()
+    }
+
+    /// Remove and return all messages sent so far
+    pub fn take(&self) -> Vec<ClientMessage> {
+        std::mem::take(&mut *self.messages.lock())
+    }
+}
+
+impl MessageSender {
+    /// Create a sender that doesn't connect anywhere and just records the messages sent through
+    /// it. Sending always succeeds. Must be called from within a tokio runtime.
+    ///
+    /// Only available with the `test-util` feature.
+    pub fn dry_run() -> (MessageSender, RecordedMessages) {
+        let (sender, mut receiver) = mpsc::channel::<SentClientMessage>(20);
+        let recorded = RecordedMessages::default();
+        runtime::spawn({
+            let recorded = recorded.clone();
+            async move {
+                while let Some(SentClientMessage { message, responder }) = receiver.recv().await {
+                    recorded.messages.lock().push(message);
+                    responder.send(Ok(MessageResponse::Ok)).ok();
+                }
+            }
+        });
+        (MessageSender::from(sender), recorded)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{ClientMessage, MessageSendError, MessageSender};
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let (mut sender, recorded) = MessageSender::dry_run();
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        sender.send(("#channel", "first")).await.unwrap();
+        sender.send(("#channel", "second")).await.unwrap();
+        assert_eq!(
+            recorded.take(),
+            vec![
+                ClientMessage::join("#channel"),
+                ClientMessage::message("#channel", "first"),
+                ClientMessage::message("#channel", "second"),
+            ]
+        );
+        assert!(recorded.messages().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_empty_message() {
+        let (mut sender, recorded) = MessageSender::dry_run();
+        for message in &["", "  \t ", "\u{1}ACTION \u{1}"] {
+            assert!(matches!(
+                sender.send(("#channel", *message)).await,
+                Err(MessageSendError::EmptyMessage)
+            ));
+        }
+        assert!(matches!(
+            sender.send(ClientMessage::whisper("someone", " ")).await,
+            Err(MessageSendError::EmptyMessage)
+        ));
+        assert!(recorded.messages().is_empty());
+
+        sender.allow_empty_messages(true);
+        sender.send(("#channel", " ")).await.unwrap();
+        assert_eq!(
+            recorded.take(),
+            vec![ClientMessage::message("#channel", " ")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_invalid_channel() {
+        let (mut sender, recorded) = MessageSender::dry_run();
+        sender.send(("channel", "hi")).await.unwrap();
+        assert_eq!(
+            recorded.take(),
+            vec![ClientMessage::message("channel", "hi")]
+        );
+
+        sender.validate_channels(true);
+        for channel in &["", "#", "channel", "#some channel"] {
+            match sender.send((*channel, "hi")).await {
+                Err(MessageSendError::InvalidChannel(invalid)) => assert_eq!(&invalid, channel),
+                other => panic!("expected invalid channel error, got {:?}", other),
+            }
+        }
+        assert!(matches!(
+            sender.send(ClientMessage::join("channel")).await,
+            Err(MessageSendError::InvalidChannel(_))
+        ));
+        assert!(recorded.messages().is_empty());
+
+        sender.validate_channels(false);
+        sender.send(("channel", "hi")).await.unwrap();
+        assert_eq!(
+            recorded.take(),
+            vec![ClientMessage::message("channel", "hi")]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Minimal websocket chat server used to test connection handling
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{accept_async, WebSocketStream};
+use url::Url;
+
+use crate::TwitchClientConfigBuilder;
+
+/// Local websocket server that accepts client connections
+pub(crate) struct MockServer {
+    listener: TcpListener,
+    /// URL for clients to connect to
+    pub url: Url,
+}
+
+impl MockServer {
+    /// Bind to a random local port
+    pub async fn bind() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("ws://{}", listener.local_addr().unwrap())).unwrap();
+        MockServer { listener, url }
+    }
+
+    /// Client configuration pointing at this server, with heartbeats disabled
+    pub fn config_builder(&self) -> TwitchClientConfigBuilder {
+        let mut builder = TwitchClientConfigBuilder::default();
+        builder
+            .url(self.url.clone())
+            .username("tester".to_string())
+            .token("oauth:tester".to_string())
+            .heartbeat(false);
+        builder
+    }
+
+    /// Wait for the next client connection and complete the websocket handshake
+    pub async fn next_connection(&mut self) -> MockConnection {
+        let (stream, _) = self.listener.accept().await.unwrap();
+        MockConnection {
+            ws: accept_async(stream).await.unwrap(),
+        }
+    }
+
+    /// Accept all incoming connections in the background, logging each of them in
+    pub fn accept_all(mut self) {
+        tokio::spawn(async move {
+            loop {
+                let mut connection = self.next_connection().await;
+                tokio::spawn(async move {
+                    connection.accept_login().await;
+                    while connection.recv_line().await.is_some() {}
+                });
+            }
+        });
+    }
+}
+
+/// Server side of a single client connection
+pub(crate) struct MockConnection {
+    ws: WebSocketStream<TcpStream>,
+}
+
+impl MockConnection {
+    /// Receive the next text line sent by the client, `None` if the connection was closed
+    pub async fn recv_line(&mut self) -> Option<String> {
+        while let Some(msg) = self.ws.next().await {
+            match msg {
+                Ok(Message::Text(text)) => return Some(text),
+                Ok(Message::Close(_)) | Err(_) => return None,
+                Ok(_) => {}
+            }
+        }
+        None
+    }
+
+    /// Send raw IRC lines to the client
+    pub async fn send(&mut self, lines: &str) {
+        self.ws
+            .send(Message::Text(lines.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// Start the websocket close handshake without closing the TCP connection. The client can't
+    /// write to the connection anymore, but doesn't notice anything when reading.
+    pub async fn send_close(&mut self) {
+        self.ws.send(Message::Close(None)).await.unwrap();
+    }
+
+    /// Abort the TCP connection without closing the websocket, the client sees a connection
+    /// reset
+    pub fn reset(self) {
+        self.ws
+            .get_ref()
+            .set_linger(Some(Duration::from_secs(0)))
+            .unwrap();
+    }
+
+    /// Read the client's capability requests and login, then respond with the welcome messages
+    pub async fn accept_login(&mut self) {
+        while let Some(line) = self.recv_line().await {
+            // the login may arrive in a single frame when messages are batched
+            if line.lines().any(|line| line.starts_with("NICK ")) {
+                break;
+            }
+        }
+        self.send_welcome().await;
+    }
+
+    /// Send the messages that mark the connection as ready
+    pub async fn send_welcome(&mut self) {
+        self.send(
+            ":tmi.twitch.tv 001 tester :Welcome, GLHF!\r\n\
+             :tmi.twitch.tv 002 tester :Your host is tmi.twitch.tv\r\n\
+             :tmi.twitch.tv 003 tester :This server is rather new\r\n\
+             :tmi.twitch.tv 004 tester :-\r\n\
+             :tmi.twitch.tv 375 tester :-\r\n\
+             :tmi.twitch.tv 372 tester :You are in a maze of twisty passages.\r\n\
+             :tmi.twitch.tv 376 tester :>",
+        )
+        .await;
+    }
+}
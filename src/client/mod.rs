@@ -1,14 +1,32 @@
 //! Client module, includes websocket connection handling, listener and handler registration
 
+use std::borrow::Borrow;
+use std::sync::Arc;
+
 use tokio::sync::{mpsc, oneshot};
+use tokio::time::Duration;
 
-use crate::ClientMessage;
 use crate::MessageSendError;
+use crate::{Capability, ClientMessage};
 
+use crate::client::responses::{
+    clear_chat_response, join_response, room_state_response, ResponseRegistry,
+};
+use crate::client::single::{CapStatus, ConnectedState, ConnectionContext, EmoteOnlyChannels};
+use crate::event::tags::Badge;
+use crate::runtime::timeout;
+use crate::stream::pause::{is_pausable, PauseHandle};
 use crate::stream::{message_responder_channel, SentClientMessage};
 pub use config::*;
+#[cfg(any(test, feature = "test-util"))]
+pub use dry_run::RecordedMessages;
 
 mod config;
+#[cfg(any(test, feature = "test-util"))]
+mod dry_run;
+#[cfg(test)]
+mod mock_server;
+mod responses;
 
 /// Managed connection pools
 pub mod pool;
@@ -26,33 +44,266 @@ type InnerMessageSender = mpsc::Sender<SentClientMessage>;
 pub enum MessageResponse {
     /// Message sent successfully with no response data
     Ok,
+    /// Message sent, but text beyond the maximum number of chunks of
+    /// [`split_oversize`](stream/trait.SendStreamExt.html#method.split_oversize) was dropped
+    Truncated {
+        /// Number of bytes of the message text that were not sent
+        dropped_bytes: usize,
+    },
 }
 
 /// Handle to a connection that can be used to send messages
 #[derive(Debug, Clone)]
 pub struct MessageSender {
     sender: InnerMessageSender,
+    /// responses from Twitch that messages sent with this sender are waiting for
+    responses: Arc<ResponseRegistry>,
+    /// channels in emote-only mode, tracked by the connection
+    emote_only: Arc<EmoteOnlyChannels>,
+    /// switch to pause sending chat messages
+    pause: Arc<PauseHandle>,
+    /// whether messages without visible text are sent anyway
+    allow_empty: bool,
+    /// whether messages to malformed channel names are rejected
+    validate_channels: bool,
+    /// whether chat messages to channels in emote-only mode are rejected
+    guard_emote_only: bool,
 }
 
 impl From<InnerMessageSender> for MessageSender {
     fn from(sender: InnerMessageSender) -> Self {
-        MessageSender { sender }
+        MessageSender::new(
+            sender,
+            Default::default(),
+            Default::default(),
+            Default::default(),
+        )
     }
 }
 
 impl MessageSender {
+    pub(crate) fn new(
+        sender: InnerMessageSender,
+        responses: Arc<ResponseRegistry>,
+        emote_only: Arc<EmoteOnlyChannels>,
+        pause: Arc<PauseHandle>,
+    ) -> Self {
+        MessageSender {
+            sender,
+            responses,
+            emote_only,
+            pause,
+            allow_empty: false,
+            validate_channels: false,
+            guard_emote_only: false,
+        }
+    }
+
+    /// Set whether channel messages and whispers without any visible text are sent. By default
+    /// they are rejected with `MessageSendError::EmptyMessage` before reaching Twitch, which would
+    /// reject them anyway. Applies to this sender and clones made from it afterwards.
+    pub fn allow_empty_messages(&mut self, allow: bool) {
+        self.allow_empty = allow;
+    }
+
+    /// Set whether channel names are checked before sending. When enabled, channel messages, joins
+    /// and parts to malformed channels (empty, missing the leading `#` or containing spaces) are
+    /// rejected with `MessageSendError::InvalidChannel`, because Twitch silently ignores them.
+    /// Disabled by default. Applies to this sender and clones made from it afterwards.
+    pub fn validate_channels(&mut self, validate: bool) {
+        self.validate_channels = validate;
+    }
+
+    /// Set whether chat messages to channels in emote-only mode are rejected with
+    /// `MessageSendError::EmoteOnly` instead of being sent. The mode is tracked from the ROOMSTATEs
+    /// and NOTICEs received on the connection. Since the client doesn't know which words are
+    /// emotes, every message that isn't a command is rejected, including those of moderators who
+    /// are exempt from the mode. Disabled by default. Applies to this sender and clones made from
+    /// it afterwards.
+    pub fn guard_emote_only(&mut self, guard: bool) {
+        self.guard_emote_only = guard;
+    }
+
+    /// Stop sending channel messages and whispers until `resume` is called. `send` calls for them
+    /// wait until sending is resumed. Other messages, like joins, are still sent. Affects all
+    /// clones of this sender.
+    pub fn pause(&self) {
+        self.pause.pause()
+    }
+
+    /// Resume sending messages after `pause`, starting with the queued ones in their original order
+    pub fn resume(&self) {
+        self.pause.resume()
+    }
+
     /// Send a message
-    pub async fn send(&mut self, msg: ClientMessage) -> Result<MessageResponse, MessageSendError> {
+    pub async fn send(
+        &mut self,
+        msg: impl Into<ClientMessage>,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let message = msg.into();
+        if !self.allow_empty && message.has_empty_text() {
+            return Err(MessageSendError::EmptyMessage);
+        }
+        if self.validate_channels {
+            if let Some(channel) = message.invalid_channel() {
+                return Err(MessageSendError::InvalidChannel(channel.to_string()));
+            }
+        }
+        if self.guard_emote_only {
+            if let Some(channel) = message.chat_text_channel() {
+                if self.emote_only.read().contains(channel) {
+                    return Err(MessageSendError::EmoteOnly(channel.to_string()));
+                }
+            }
+        }
+        // wait here instead of in the connection, so other messages can pass in the meantime
+        if is_pausable(&message) {
+            self.pause.resumed().await;
+        }
         let (tx, rx) = message_responder_channel();
         self.sender
             .send(SentClientMessage {
-                message: msg,
+                message,
                 responder: tx,
             })
             .await
             .map_err(|e| MessageSendError::Closed(e.0.message))?;
-        rx.await.expect("message send result")?;
-        Ok(MessageResponse::Ok)
+        rx.await.expect("message send result")
+    }
+
+    /// Send an action (`/me`) message to a channel
+    pub async fn action<S1: Into<String> + Borrow<str>, S2: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S1,
+        message: S2,
+    ) -> Result<MessageResponse, MessageSendError> {
+        self.send(ClientMessage::me(channel, message)).await
+    }
+
+    /// Join a channel and wait until Twitch confirms that it was joined. Fails with
+    /// `MessageSendError::JoinFailed` if Twitch rejects the join (for example because the channel
+    /// is suspended) and with `MessageSendError::ResponseTimeout` if there is no response within
+    /// `wait_timeout`.
+    pub async fn join_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let response = self.responses.wait_for({
+            let channel = channel.clone();
+            move |event| join_response(&channel, event)
+        });
+        self.send(ClientMessage::Join(channel.clone())).await?;
+        match timeout(wait_timeout, response).await {
+            Ok(Ok(result)) => result.map(|_| MessageResponse::Ok),
+            Ok(Err(_)) => Err(MessageSendError::Closed(ClientMessage::Join(channel))),
+            Err(_) => Err(MessageSendError::ResponseTimeout),
+        }
+    }
+
+    /// Clear the chat of a channel and wait until Twitch confirms it with a CLEARCHAT for the whole
+    /// channel. Fails with `MessageSendError::ResponseTimeout` if there is no confirmation within
+    /// `wait_timeout`.
+    pub async fn clear_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let message = ClientMessage::clear(channel.clone());
+        let response = self
+            .responses
+            .wait_for(move |event| clear_chat_response(&channel, event));
+        self.send(message.clone()).await?;
+        match timeout(wait_timeout, response).await {
+            Ok(Ok(())) => Ok(MessageResponse::Ok),
+            Ok(Err(_)) => Err(MessageSendError::Closed(message)),
+            Err(_) => Err(MessageSendError::ResponseTimeout),
+        }
+    }
+
+    /// Enable slow mode and wait until Twitch confirms the change with a ROOMSTATE. Fails with
+    /// `MessageSendError::ResponseTimeout` if there is no confirmation within `wait_timeout`.
+    pub async fn slow_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        seconds: usize,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let expected = seconds.to_string();
+        let message = ClientMessage::slow(channel.clone(), seconds);
+        self.send_and_wait_room_state(message, channel, "slow", wait_timeout, move |value| {
+            value == expected
+        })
+        .await
+    }
+
+    /// Disable slow mode and wait until Twitch confirms the change with a ROOMSTATE
+    pub async fn slow_off_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let message = ClientMessage::slow_off(channel.clone());
+        self.send_and_wait_room_state(message, channel, "slow", wait_timeout, |value| value == "0")
+            .await
+    }
+
+    /// Enable followers only mode and wait until Twitch confirms the change with a ROOMSTATE.
+    /// Fails with `MessageSendError::ResponseTimeout` if there is no confirmation within
+    /// `wait_timeout`.
+    pub async fn followers_only_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        duration: Option<&str>,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let message = ClientMessage::followers_only(channel.clone(), duration);
+        self.send_and_wait_room_state(message, channel, "followers-only", wait_timeout, |value| {
+            value != "-1"
+        })
+        .await
+    }
+
+    /// Disable followers only mode and wait until Twitch confirms the change with a ROOMSTATE
+    pub async fn followers_off_and_wait<S: Into<String> + Borrow<str>>(
+        &mut self,
+        channel: S,
+        wait_timeout: Duration,
+    ) -> Result<MessageResponse, MessageSendError> {
+        let channel = channel.into();
+        let message = ClientMessage::followers_off(channel.clone());
+        self.send_and_wait_room_state(message, channel, "followers-only", wait_timeout, |value| {
+            value == "-1"
+        })
+        .await
+    }
+
+    async fn send_and_wait_room_state<F>(
+        &mut self,
+        message: ClientMessage,
+        channel: String,
+        tag: &'static str,
+        wait_timeout: Duration,
+        accept: F,
+    ) -> Result<MessageResponse, MessageSendError>
+    where
+        F: Fn(&str) -> bool + Send + 'static,
+    {
+        let response = self
+            .responses
+            .wait_for(move |event| room_state_response(&channel, tag, &accept, event));
+        self.send(message.clone()).await?;
+        match timeout(wait_timeout, response).await {
+            Ok(Ok(())) => Ok(MessageResponse::Ok),
+            Ok(Err(_)) => Err(MessageSendError::Closed(message)),
+            Err(_) => Err(MessageSendError::ResponseTimeout),
+        }
     }
 }
 
@@ -62,6 +313,8 @@ pub struct TwitchClient<St> {
     sender: MessageSender,
     /// stream of chat events
     stream: St,
+    /// state of the underlying connection
+    context: Arc<ConnectionContext>,
 }
 
 impl<St> TwitchClient<St> {
@@ -79,4 +332,34 @@ impl<St> TwitchClient<St> {
     pub fn stream_mut(&mut self) -> &mut St {
         &mut self.stream
     }
+
+    /// Close the connection and connect again, for example to apply changed settings. Channels
+    /// are joined again once the new connection is established.
+    pub fn reconnect(&self) {
+        self.context.reconnect()
+    }
+
+    /// Whether Twitch granted a capability on the current connection
+    pub fn cap_status(&self, capability: Capability) -> CapStatus {
+        self.context.cap_status(capability)
+    }
+
+    /// Badges of the logged in user in a channel, as of the last USERSTATE Twitch sent for it.
+    /// `None` if no USERSTATE was received for the channel since it was joined.
+    pub fn own_badges(&self, channel: &str) -> Option<Vec<Badge<String>>> {
+        self.context.own_badges(channel)
+    }
+
+    /// Close the connection for good instead of reconnecting. Completes once the websocket was
+    /// closed and the background task maintaining the connection has stopped.
+    pub async fn close(&mut self) -> Result<(), MessageSendError> {
+        self.sender.send(ClientMessage::Close).await?;
+        let mut connected_state = self.context.connected_state.clone();
+        while let Some(state) = connected_state.recv().await {
+            if state == ConnectedState::Closed {
+                break;
+            }
+        }
+        Ok(())
+    }
 }
@@ -1,16 +1,21 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Weak};
 
 use fnv::FnvHashMap;
 use futures_core::Stream;
-use tokio::stream;
-use tokio::sync::broadcast::RecvError;
-use tokio::sync::{broadcast, mpsc, RwLock};
-use tokio::time::{interval_at, Duration, Instant};
+use futures_util::future::join_all;
 use tokio::select;
+use tokio::sync::broadcast::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot, RwLock};
+use tokio::time::Duration;
 
-use crate::client::single::{connect_internal, ConnectionContext};
+use crate::client::responses::ResponseRegistry;
+use crate::client::single::{connect_internal, ConnectionContext, EmoteOnlyChannels};
 use crate::client::MessageSender;
 use crate::event::Event;
+use crate::runtime::{self, interval_at, Instant};
+use crate::stream::pause::PauseHandle;
 use crate::stream::rate_limits::RateLimiter;
 use crate::stream::{MessageResponder, RespondWithErrors, SentClientMessage};
 use crate::util::InternalSender;
@@ -26,7 +31,12 @@ pub async fn connect(
     let (message_sender, mut message_receiver) =
         mpsc::channel::<SentClientMessage>(cfg.channel_buffer);
     let rate_limiter = Arc::new(RateLimiter::from(&cfg.rate_limiter));
+    let responses = Arc::new(ResponseRegistry::default());
+    let emote_only = Arc::new(EmoteOnlyChannels::default());
+    let pause = Arc::new(PauseHandle::default());
     let (event_sender, event_receiver) = broadcast::channel(cfg.channel_buffer);
+    let (control_sender, mut control_receiver) = mpsc::channel::<PoolControl>(1);
+    let connection_counter = AtomicUsize::new(0);
 
     let mut default_connections = vec![];
 
@@ -34,7 +44,11 @@ pub async fn connect(
     let whisper_connection = new_connection(&ConnectionConfig {
         cfg: &cfg,
         rate_limiter: &rate_limiter,
+        responses: &responses,
+        emote_only: &emote_only,
+        pause: &pause,
         event_sender: &event_sender,
+        connection_counter: &connection_counter,
         handle_whispers: true,
     })
     .await?;
@@ -45,32 +59,44 @@ pub async fn connect(
         let conn = new_connection(&ConnectionConfig {
             cfg: &cfg,
             rate_limiter: &rate_limiter,
+            responses: &responses,
+            emote_only: &emote_only,
+            pause: &pause,
             event_sender: &event_sender,
+            connection_counter: &connection_counter,
             handle_whispers: false,
         })
         .await?;
         default_connections.push(Arc::new(conn));
     }
 
+    let connections = Arc::new(RwLock::new(default_connections));
     let pool = ConnectionPool {
-        whisper_connection: default_connections[0].clone(),
+        whisper_connection: connections.read().await[0].clone(),
         channel_connections_map: Default::default(),
         event_sender: event_sender.clone(),
         event_receiver,
-        connections: RwLock::new(default_connections),
+        connections: connections.clone(),
     };
 
     {
         // capture variables for spawned task
         let cfg = cfg.clone();
         let event_sender = event_sender.clone();
-        tokio::spawn(async move {
+        let responses = responses.clone();
+        let emote_only = emote_only.clone();
+        let pause = pause.clone();
+        runtime::spawn(async move {
             let mut pool = pool;
 
             let connection_cfg = ConnectionConfig {
                 cfg: &cfg,
                 rate_limiter: &rate_limiter,
+                responses: &responses,
+                emote_only: &emote_only,
+                pause: &pause,
                 event_sender: &event_sender,
+                connection_counter: &connection_counter,
                 handle_whispers: false,
             };
 
@@ -103,6 +129,14 @@ pub async fn connect(
                     _ = connection_cleanup_interval.tick() => {
                         pool.close_stale_connections().await;
                     }
+                    // requests from pool handles
+                    Some(control) = control_receiver.recv() => {
+                        match control {
+                            PoolControl::Stats(responder) => {
+                                responder.send(pool.stats().await).ok();
+                            }
+                        }
+                    }
                 }
             }
         });
@@ -110,7 +144,9 @@ pub async fn connect(
 
     let pool_handle = ConnectionPoolHandle {
         event_sender,
-        message_sender: MessageSender::from(message_sender),
+        control_sender,
+        connections,
+        message_sender: MessageSender::new(message_sender, responses, emote_only, pause),
     };
 
     Ok(pool_handle)
@@ -124,20 +160,20 @@ async fn send_message(
     responder: MessageResponder,
     connection_cfg: &ConnectionConfig<'_>,
 ) {
-    use futures_util::stream::StreamExt;
-
     match &client_message {
         ClientMessage::Whisper { .. } => {
             pool.whisper_connection
                 .handle_client_message(client_message, responder)
                 .await;
         }
-        ClientMessage::Ping | ClientMessage::Pong => {
+        ClientMessage::Ping | ClientMessage::Pong(_) => {
             pool.whisper_connection
                 .handle_client_message(client_message, responder)
                 .await;
         }
-        ClientMessage::PrivMsg { channel, .. } => {
+        ClientMessage::PrivMsg { channel, .. }
+        | ClientMessage::Reply { channel, .. }
+        | ClientMessage::CustomPrivMsg { channel, .. } => {
             if let Some(handle) = pool.get_channel_connection(channel) {
                 handle
                     .handle_client_message(client_message, responder)
@@ -150,68 +186,122 @@ async fn send_message(
         }
         ClientMessage::Part(channel) => {
             if let Some(handle) = pool.get_channel_connection(channel) {
-                pool.channel_connections_map.remove(channel);
-                handle
-                    .handle_client_message(client_message, responder)
-                    .await;
+                let channel = channel.clone();
+                let result = handle.send(client_message).await;
+                if result.is_ok() {
+                    pool.channel_connections_map.remove(&channel);
+                }
+                result.respond_with_errors(responder);
             } else {
                 responder
                     .send(Err(MessageSendError::ChannelNotJoined(client_message)))
                     .ok();
             }
         }
+        // one PART line for each connection the channels are joined on
+        ClientMessage::PartAll(channels) => {
+            let mut groups = ChannelGroups::default();
+            for channel in channels {
+                match pool.get_channel_connection(channel) {
+                    Some(handle) => groups.add(handle, channel),
+                    None => {
+                        responder
+                            .send(Err(MessageSendError::ChannelNotJoined(client_message)))
+                            .ok();
+                        return;
+                    }
+                }
+            }
+            let mut result = Ok(MessageResponse::Ok);
+            for (_, channels, group_result) in groups.send(ClientMessage::PartAll).await {
+                match group_result {
+                    // the channels stay mapped to their connection if it didn't send the PART
+                    Ok(_) => {
+                        for channel in channels {
+                            pool.channel_connections_map.remove(&channel);
+                        }
+                    }
+                    Err(e) => result = result.and(Err(e)),
+                }
+            }
+            responder.send(result).ok();
+        }
         ClientMessage::Join(channel) => {
-            // already joined this channel
-            if let Some(connection) = pool.get_channel_connection(channel) {
-                connection
-                    .handle_client_message(client_message, responder)
-                    .await;
-            } else {
-                // get connection with the lowest amount of joined channels
-                let connections = pool.connections.read().await.clone();
-                let handle = stream::iter(&connections)
-                    .filter_map(|handle| {
-                        let threshold = pool_cfg.threshold;
-                        async move {
-                            let count = handle.context.joined_channels.read().await.len();
-                            if count <= threshold as usize {
-                                Some((handle, count))
-                            } else {
-                                None
+            match connection_for_join(channel, pool, pool_cfg, connection_cfg, &[]).await {
+                Ok(handle) => {
+                    let channel = channel.clone();
+                    let result = handle.send(client_message).await;
+                    if result.is_ok() {
+                        pool.channel_connections_map
+                            .insert(channel, Arc::downgrade(&handle));
+                    }
+                    result.respond_with_errors(responder);
+                }
+                Err(e) => {
+                    responder.send(Err(e)).ok();
+                }
+            }
+        }
+        // one JOIN line for each connection the channels end up on. Either all of the channels
+        // are joined or none of the ones that weren't joined before.
+        ClientMessage::JoinAll(channels) => {
+            let mut groups = ChannelGroups::default();
+            for channel in channels {
+                if groups.contains(channel) {
+                    continue;
+                }
+                let joining =
+                    connection_for_join(channel, pool, pool_cfg, connection_cfg, &groups.groups)
+                        .await;
+                match joining {
+                    Ok(handle) => groups.add(handle, channel),
+                    Err(e) => {
+                        // nothing was sent yet
+                        responder.send(Err(e)).ok();
+                        return;
+                    }
+                }
+            }
+
+            // channels that were joined before are left alone if the join has to be rolled back
+            let joined_before = groups
+                .groups
+                .iter()
+                .flat_map(|(_, channels)| channels)
+                .filter(|channel| pool.get_channel_connection(channel).is_some())
+                .cloned()
+                .collect::<Vec<_>>();
+
+            let results = groups.send(ClientMessage::JoinAll).await;
+            match results
+                .iter()
+                .find_map(|(_, _, result)| result.clone().err())
+            {
+                Some(e) => {
+                    debug!("Joining channels failed, leaving the channels joined so far");
+                    let mut rollback = ChannelGroups::default();
+                    for (handle, channels, result) in &results {
+                        for channel in channels {
+                            if result.is_ok() && !joined_before.contains(channel) {
+                                rollback.add(handle.clone(), channel);
                             }
                         }
-                    })
-                    .collect::<Vec<_>>()
-                    .await
-                    .into_iter()
-                    .min_by_key(|(_handle, joined_count)| *joined_count)
-                    .map(|(handle, _)| handle);
-
-                if let Some(channel_handle) = handle {
-                    debug!("Joining channel on existing connection.");
-                    pool.channel_connections_map
-                        .insert(channel.clone(), Arc::downgrade(channel_handle));
-                    channel_handle
-                        .handle_client_message(client_message, responder)
-                        .await;
-                } else {
-                    debug!("Adding new connection to the pool.");
-                    let conn_result = new_connection(connection_cfg)
-                        .await
-                        .map_err(|e| MessageSendError::NewConnectionFailed(format!("{}", e)));
-                    match conn_result {
-                        Ok(conn) => {
-                            let channel = channel.clone();
-                            conn.handle_client_message(client_message, responder).await;
-                            let arc = Arc::new(conn);
-                            let weak = Arc::downgrade(&arc);
-                            pool.connections.write().await.push(arc);
-                            pool.channel_connections_map.insert(channel.clone(), weak);
+                    }
+                    for (_, _, result) in rollback.send(ClientMessage::PartAll).await {
+                        if let Err(e) = result {
+                            warn!("Failed to leave channels after a failed join: {}", e);
                         }
-                        Err(error) => {
-                            responder.send(Err(error)).ok();
+                    }
+                    responder.send(Err(e)).ok();
+                }
+                None => {
+                    for (handle, channels, _) in results {
+                        for channel in channels {
+                            pool.channel_connections_map
+                                .insert(channel, Arc::downgrade(&handle));
                         }
                     }
+                    responder.send(Ok(MessageResponse::Ok)).ok();
                 }
             }
         }
@@ -248,11 +338,139 @@ async fn send_message(
     }
 }
 
+/// The connection to join a channel on: the connection it is already joined on, or the connection
+/// with the least joined channels. Creates a new connection if all of them reached the threshold.
+/// Channels in `pending` are about to be joined and count towards their connection's channels.
+/// The channel is only mapped to the connection once the JOIN was sent.
+async fn connection_for_join(
+    channel: &str,
+    pool: &mut ConnectionPool,
+    pool_cfg: &PoolConfig,
+    connection_cfg: &ConnectionConfig<'_>,
+    pending: &[(Arc<ConnectionHandle>, Vec<String>)],
+) -> Result<Arc<ConnectionHandle>, MessageSendError> {
+    // already joined this channel
+    if let Some(connection) = pool.get_channel_connection(channel) {
+        return Ok(connection);
+    }
+
+    // get connection with the lowest amount of joined channels
+    let connections = pool.connections.read().await.clone();
+    let joined_counts = connections
+        .iter()
+        .map(|handle| {
+            let joined_channels = handle.context.joined_channels.read();
+            let pending_count = pending
+                .iter()
+                .filter(|(pending_handle, _)| Arc::ptr_eq(pending_handle, handle))
+                .flat_map(|(_, channels)| channels)
+                .filter(|channel| !joined_channels.contains(channel))
+                .count();
+            (handle, joined_channels.len() + pending_count)
+        })
+        .collect::<Vec<_>>();
+    let threshold = pool_cfg.threshold as usize;
+    let channel_limit = connection_cfg.cfg.channel_limit;
+    let mut handle = joined_counts
+        .iter()
+        .filter(|(_handle, count)| *count <= threshold && *count < channel_limit)
+        .min_by_key(|(_handle, joined_count)| *joined_count)
+        .map(|(handle, _)| *handle);
+
+    if handle.is_none() && connections.len() >= pool_cfg.connection_limit as usize {
+        match pool_cfg.overflow_behavior {
+            OverflowBehavior::JoinLeastLoaded => {
+                handle = joined_counts
+                    .iter()
+                    .min_by_key(|(_handle, joined_count)| *joined_count)
+                    .map(|(handle, _)| *handle);
+            }
+            OverflowBehavior::Reject => {
+                return Err(MessageSendError::ConnectionLimitReached(
+                    pool_cfg.connection_limit as usize,
+                ));
+            }
+        }
+    }
+
+    let handle = match handle {
+        Some(channel_handle) => {
+            debug!(
+                "[{}] Joining channel on existing connection.",
+                channel_handle.context.label
+            );
+            channel_handle.clone()
+        }
+        None => {
+            debug!("Adding new connection to the pool.");
+            let conn = new_connection(connection_cfg)
+                .await
+                .map_err(|e| MessageSendError::NewConnectionFailed(format!("{}", e)))?;
+            let arc = Arc::new(conn);
+            pool.connections.write().await.push(arc.clone());
+            arc
+        }
+    };
+    Ok(handle)
+}
+
+/// Channels of a JOIN or PART grouped by the connection they are joined on
+#[derive(Default)]
+struct ChannelGroups {
+    groups: Vec<(Arc<ConnectionHandle>, Vec<String>)>,
+}
+
+impl ChannelGroups {
+    fn add(&mut self, handle: Arc<ConnectionHandle>, channel: &str) {
+        match self
+            .groups
+            .iter_mut()
+            .find(|(group_handle, _)| Arc::ptr_eq(group_handle, &handle))
+        {
+            Some((_, channels)) => channels.push(channel.to_string()),
+            None => self.groups.push((handle, vec![channel.to_string()])),
+        }
+    }
+
+    fn contains(&self, channel: &str) -> bool {
+        self.groups.iter().any(|(_, channels)| {
+            channels
+                .iter()
+                .any(|group_channel| group_channel == channel)
+        })
+    }
+
+    /// Send one message for each connection, returns the result for each group
+    async fn send(
+        self,
+        to_message: fn(Vec<String>) -> ClientMessage,
+    ) -> Vec<(
+        Arc<ConnectionHandle>,
+        Vec<String>,
+        Result<MessageResponse, MessageSendError>,
+    )> {
+        join_all(
+            self.groups
+                .into_iter()
+                .map(|(handle, channels)| async move {
+                    let result = handle.send(to_message(channels.clone())).await;
+                    (handle, channels, result)
+                }),
+        )
+        .await
+    }
+}
+
 /// Configures a new connection for use inside the pool
 struct ConnectionConfig<'a> {
     cfg: &'a Arc<TwitchClientConfig>,
     rate_limiter: &'a Arc<RateLimiter>,
+    responses: &'a Arc<ResponseRegistry>,
+    emote_only: &'a Arc<EmoteOnlyChannels>,
+    pause: &'a Arc<PauseHandle>,
     event_sender: &'a broadcast::Sender<Result<Event, Error>>,
+    /// source of connection ids, incremented for each new connection
+    connection_counter: &'a AtomicUsize,
     handle_whispers: bool,
 }
 
@@ -260,7 +478,13 @@ struct ConnectionConfig<'a> {
 async fn new_connection(connection_cfg: &ConnectionConfig<'_>) -> Result<ConnectionHandle, Error> {
     let (sender, context) = connect_internal(
         connection_cfg.cfg,
+        connection_cfg
+            .connection_counter
+            .fetch_add(1, Ordering::Relaxed),
         connection_cfg.rate_limiter.clone(),
+        connection_cfg.responses.clone(),
+        connection_cfg.emote_only.clone(),
+        connection_cfg.pause.clone(),
         InternalSender(connection_cfg.event_sender.clone()),
         connection_cfg.handle_whispers,
     )
@@ -279,6 +503,19 @@ pub struct PoolConfig {
     /// When all connections reach this number of joined channels, a new connection
     /// will be created
     pub threshold: u32,
+    /// How to join channels once all connections reached the threshold and no more connections
+    /// can be created because of `connection_limit`
+    pub overflow_behavior: OverflowBehavior,
+}
+
+/// How a connection pool handles joins when it can't create more connections
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowBehavior {
+    /// Join the channel on the connection with the fewest joined channels anyway. Joins still
+    /// fail once that connection reaches the configured `channel_limit`.
+    JoinLeastLoaded,
+    /// Reject the join with `MessageSendError::ConnectionLimitReached`
+    Reject,
 }
 
 struct ConnectionHandle {
@@ -303,20 +540,27 @@ impl ConnectionHandle {
 
     /// Returns true when a connection is no longer required in a pool because it doesn't handle
     /// whispers and has no joined channels.
-    pub async fn is_stale(&self) -> bool {
-        !self.context.whisper_enabled && self.context.joined_channels.read().await.len() == 0
+    pub fn is_stale(&self) -> bool {
+        !self.context.whisper_enabled && self.context.joined_channels.read().is_empty()
     }
 }
 
 /// Handle to a connection pool
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct ConnectionPoolHandle {
     event_sender: broadcast::Sender<Result<Event, Error>>,
+    control_sender: mpsc::Sender<PoolControl>,
+    connections: Arc<RwLock<Vec<Arc<ConnectionHandle>>>>,
     message_sender: MessageSender,
 }
 
 impl ConnectionPoolHandle {
-    /// Subscribe to a receiver for messages
+    /// Subscribe to a receiver for messages.
+    ///
+    /// Events from all connections of the pool are merged in the order they arrive, so events
+    /// from different channels can be interleaved in any order. Each channel is only joined on a
+    /// single connection though, so the events of one channel are always received in the order
+    /// Twitch sent them.
     pub fn subscribe_events(&self) -> impl Stream<Item = Result<Event, Error>> {
         use tokio::stream::StreamExt;
         self.event_sender.subscribe().map(|result| match result {
@@ -338,6 +582,52 @@ impl ConnectionPoolHandle {
     pub fn sender(&self) -> &MessageSender {
         &self.message_sender
     }
+
+    /// Close all connections of the pool and connect them again. Channels are joined again on
+    /// the same connections once they are re-established.
+    pub async fn reconnect_all(&self) {
+        for connection in self.connections.read().await.iter() {
+            connection.context.reconnect();
+        }
+    }
+
+    /// Number of connections and how the joined channels are spread across them. Returns `None`
+    /// if the pool has shut down.
+    pub async fn stats(&self) -> Option<PoolStats> {
+        let (tx, rx) = oneshot::channel();
+        self.control_sender
+            .clone()
+            .send(PoolControl::Stats(tx))
+            .await
+            .ok()?;
+        rx.await.ok()
+    }
+}
+
+/// Requests to the task that manages the pool
+enum PoolControl {
+    Stats(oneshot::Sender<PoolStats>),
+}
+
+/// Snapshot of a connection pool's state
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Number of open connections
+    pub connection_count: usize,
+    /// Number of joined channels on each connection, in the order the connections were created
+    pub channels_per_connection: Vec<usize>,
+    /// Number of channels joined through the pool
+    pub total_channels: usize,
+}
+
+impl fmt::Debug for ConnectionPoolHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionPoolHandle")
+            .field("event_sender", &self.event_sender)
+            .field("control_sender", &self.control_sender)
+            .field("message_sender", &self.message_sender)
+            .finish()
+    }
 }
 
 struct ConnectionPool {
@@ -345,8 +635,8 @@ struct ConnectionPool {
     event_sender: broadcast::Sender<Result<Event<String>, Error>>,
     /// receiver for the event broadcast channel
     event_receiver: broadcast::Receiver<Result<Event<String>, Error>>,
-    /// default connections as specified in `init_connections`
-    connections: RwLock<Vec<Arc<ConnectionHandle>>>,
+    /// all open connections, shared with the pool handle
+    connections: Arc<RwLock<Vec<Arc<ConnectionHandle>>>>,
     /// connection for whispers
     whisper_connection: Arc<ConnectionHandle>,
     /// weak connection handles for individual channels
@@ -360,12 +650,28 @@ impl ConnectionPool {
             .and_then(|weak| weak.upgrade())
     }
 
+    async fn stats(&self) -> PoolStats {
+        let connections = self.connections.read().await;
+        PoolStats {
+            connection_count: connections.len(),
+            channels_per_connection: connections
+                .iter()
+                .map(|connection| connection.context.joined_channels.read().len())
+                .collect(),
+            total_channels: self
+                .channel_connections_map
+                .values()
+                .filter(|weak| weak.strong_count() > 0)
+                .count(),
+        }
+    }
+
     async fn close_stale_connections(&self) {
         let mut lock = self.connections.write().await;
         let mut live_connections = Vec::new();
         let mut stale_connections = Vec::new();
         for connection in lock.iter().cloned() {
-            if connection.is_stale().await {
+            if connection.is_stale() {
                 stale_connections.push(connection);
             } else {
                 live_connections.push(connection);
@@ -373,7 +679,7 @@ impl ConnectionPool {
         }
 
         debug!("Closing {} stale connections", stale_connections.len());
-        tokio::spawn(async move {
+        runtime::spawn(async move {
             for stale_connection in stale_connections {
                 stale_connection.close().await
             }
@@ -382,3 +688,329 @@ impl ConnectionPool {
         std::mem::swap(lock.as_mut(), &mut live_connections);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use fnv::FnvHashMap;
+    use futures_util::StreamExt;
+    use tokio::sync::Barrier;
+    use tokio::time::{delay_for, timeout, Duration};
+
+    use crate::client::mock_server::MockServer;
+    use crate::client::pool::{connect, OverflowBehavior, PoolConfig};
+    use crate::event::*;
+    use crate::util::test_logger;
+    use crate::{ClientMessage, MessageSendError};
+
+    #[tokio::test]
+    async fn test_pool_connection_labels() {
+        test_logger::init();
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .connection_label("pool-label".to_string())
+                .build()
+                .unwrap(),
+        );
+        server.accept_all();
+
+        let _pool = connect(
+            &cfg,
+            PoolConfig {
+                init_connections: 2,
+                connection_limit: 10,
+                threshold: 50,
+                overflow_behavior: OverflowBehavior::JoinLeastLoaded,
+            },
+        )
+        .await
+        .unwrap();
+
+        let lines = test_logger::lines();
+        for label in &["pool-label-0", "pool-label-1"] {
+            assert!(lines
+                .iter()
+                .any(|line| line.starts_with(&format!("[{}] Connecting to", label))));
+            assert!(lines.contains(&format!("[{}] Connection established.", label)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_per_channel_order() {
+        const MESSAGES: usize = 20;
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            // both connections start sending once both channels are joined, so their messages
+            // are interleaved
+            let joined = Arc::new(Barrier::new(2));
+            for _ in 0..2 {
+                let mut connection = server.next_connection().await;
+                connection.accept_login().await;
+                let joined = joined.clone();
+                tokio::spawn(async move {
+                    while let Some(line) = connection.recv_line().await {
+                        if let Some(channel) = line.strip_prefix("JOIN ") {
+                            let channel = channel.to_string();
+                            joined.wait().await;
+                            for i in 0..MESSAGES {
+                                connection
+                                    .send(&format!(
+                                        ":someone!someone@someone.tmi.twitch.tv PRIVMSG {} :{}",
+                                        channel, i
+                                    ))
+                                    .await;
+                                delay_for(Duration::from_millis(1)).await;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let pool = connect(
+            &cfg,
+            PoolConfig {
+                init_connections: 2,
+                connection_limit: 10,
+                threshold: 0,
+                overflow_behavior: OverflowBehavior::JoinLeastLoaded,
+            },
+        )
+        .await
+        .unwrap();
+        let mut events = pool.subscribe_events();
+        let mut sender = pool.clone_sender();
+        sender.send(ClientMessage::join("#first")).await.unwrap();
+        sender.send(ClientMessage::join("#second")).await.unwrap();
+
+        let mut received: FnvHashMap<String, Vec<usize>> = FnvHashMap::default();
+        while received.values().map(Vec::len).sum::<usize>() < 2 * MESSAGES {
+            let event = timeout(Duration::from_secs(5), events.next())
+                .await
+                .unwrap()
+                .unwrap()
+                .unwrap();
+            if let Event::PrivMsg(event) = event {
+                received
+                    .entry(event.channel().clone())
+                    .or_default()
+                    .push(event.message().parse().unwrap());
+            }
+        }
+
+        let expected = (0..MESSAGES).collect::<Vec<_>>();
+        assert_eq!(received["#first"], expected);
+        assert_eq!(received["#second"], expected);
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        server.accept_all();
+
+        for &overflow_behavior in &[OverflowBehavior::Reject, OverflowBehavior::JoinLeastLoaded] {
+            let pool = connect(
+                &cfg,
+                PoolConfig {
+                    init_connections: 1,
+                    connection_limit: 1,
+                    threshold: 0,
+                    overflow_behavior,
+                },
+            )
+            .await
+            .unwrap();
+            let mut sender = pool.clone_sender();
+            sender.send(ClientMessage::join("#first")).await.unwrap();
+            let second = sender.send(ClientMessage::join("#second")).await;
+            match overflow_behavior {
+                OverflowBehavior::Reject => assert!(matches!(
+                    second,
+                    Err(MessageSendError::ConnectionLimitReached(1))
+                )),
+                OverflowBehavior::JoinLeastLoaded => assert!(second.is_ok()),
+            }
+            assert_eq!(pool.connections.read().await.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pool_stats() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        server.accept_all();
+
+        let pool = connect(
+            &cfg,
+            PoolConfig {
+                init_connections: 2,
+                connection_limit: 10,
+                threshold: 1,
+                overflow_behavior: OverflowBehavior::JoinLeastLoaded,
+            },
+        )
+        .await
+        .unwrap();
+        let mut sender = pool.clone_sender();
+        for channel in &["#first", "#second", "#third"] {
+            sender.send(ClientMessage::join(*channel)).await.unwrap();
+        }
+
+        let stats = pool.stats().await.unwrap();
+        assert_eq!(stats.connection_count, 2);
+        assert_eq!(stats.total_channels, 3);
+        let mut channels_per_connection = stats.channels_per_connection;
+        channels_per_connection.sort();
+        assert_eq!(channels_per_connection, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_join_all() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (line_sender, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for connection_index in 0.. {
+                let mut connection = server.next_connection().await;
+                connection.accept_login().await;
+                let line_sender = line_sender.clone();
+                tokio::spawn(async move {
+                    while let Some(line) = connection.recv_line().await {
+                        if line.starts_with("JOIN ") || line.starts_with("PART ") {
+                            line_sender.send((connection_index, line)).ok();
+                        }
+                    }
+                });
+            }
+        });
+
+        let pool = connect(
+            &cfg,
+            PoolConfig {
+                init_connections: 1,
+                connection_limit: 10,
+                threshold: 1,
+                overflow_behavior: OverflowBehavior::JoinLeastLoaded,
+            },
+        )
+        .await
+        .unwrap();
+        let mut sender = pool.clone_sender();
+        sender
+            .send(ClientMessage::join_all(vec!["#first", "#second", "#third"]))
+            .await
+            .unwrap();
+
+        let stats = pool.stats().await.unwrap();
+        assert_eq!(stats.total_channels, 3);
+        assert_eq!(stats.channels_per_connection, vec![2, 1]);
+
+        sender
+            .send(ClientMessage::part_all(vec!["#first", "#third"]))
+            .await
+            .unwrap();
+        let mut received = Vec::new();
+        while received.len() < 4 {
+            let line = timeout(Duration::from_secs(5), lines.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(line);
+        }
+        received.sort();
+        assert_eq!(
+            received,
+            vec![
+                (0, "JOIN #first,#second".to_string()),
+                (0, "PART #first".to_string()),
+                (1, "JOIN #third".to_string()),
+                (1, "PART #third".to_string()),
+            ]
+        );
+        assert!(matches!(
+            sender.send(ClientMessage::part_all(vec!["#first"])).await,
+            Err(MessageSendError::ChannelNotJoined(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_join_all_rollback() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().channel_limit(2).build().unwrap());
+        let (line_sender, mut lines) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            for connection_index in 0.. {
+                let mut connection = server.next_connection().await;
+                connection.accept_login().await;
+                let line_sender = line_sender.clone();
+                tokio::spawn(async move {
+                    while let Some(line) = connection.recv_line().await {
+                        if line.starts_with("JOIN ") || line.starts_with("PART ") {
+                            line_sender.send((connection_index, line)).ok();
+                        }
+                    }
+                });
+            }
+        });
+
+        for &overflow_behavior in &[OverflowBehavior::Reject, OverflowBehavior::JoinLeastLoaded] {
+            let pool = connect(
+                &cfg,
+                PoolConfig {
+                    init_connections: 2,
+                    connection_limit: 2,
+                    threshold: 0,
+                    overflow_behavior,
+                },
+            )
+            .await
+            .unwrap();
+            let mut sender = pool.clone_sender();
+            sender.send(ClientMessage::join("#a")).await.unwrap();
+            sender.send(ClientMessage::join("#b")).await.unwrap();
+
+            let result = sender
+                .send(ClientMessage::join_all(vec!["#c", "#d", "#e", "#b"]))
+                .await;
+            match overflow_behavior {
+                // no connection could be found for the channels, nothing is sent
+                OverflowBehavior::Reject => assert!(matches!(
+                    result,
+                    Err(MessageSendError::ConnectionLimitReached(2))
+                )),
+                // #c and #e exceed the channel limit of the first connection, #d is joined on the
+                // second connection and left again
+                OverflowBehavior::JoinLeastLoaded => assert!(matches!(
+                    result,
+                    Err(MessageSendError::ChannelLimitReached(2))
+                )),
+            }
+            let stats = pool.stats().await.unwrap();
+            assert_eq!(stats.total_channels, 2);
+            assert_eq!(stats.channels_per_connection, vec![1, 1]);
+            sender.send(ClientMessage::Close).await.unwrap();
+        }
+
+        let mut received = Vec::new();
+        while let Ok(Some(line)) = timeout(Duration::from_millis(500), lines.recv()).await {
+            received.push(line);
+        }
+        received.sort();
+        assert_eq!(
+            received,
+            vec![
+                (0, "JOIN #a".to_string()),
+                (1, "JOIN #b".to_string()),
+                (2, "JOIN #a".to_string()),
+                (3, "JOIN #b".to_string()),
+                (3, "JOIN #d,#b".to_string()),
+                (3, "PART #d".to_string()),
+            ]
+        );
+    }
+}
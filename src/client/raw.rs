@@ -1,10 +1,17 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_core::task::{Context, Poll};
 use futures_core::Stream;
+use futures_util::{FutureExt, StreamExt};
 use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::tungstenite::Error as WsError;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::event::TwitchChatStream;
+use crate::event::{IrcMessageStream, TwitchChatStream};
+use crate::runtime::{delay_for, Delay};
 use crate::Error;
 
 /// Creates a connection to Twitch chat without any additional handling logic. The
@@ -15,3 +22,161 @@ pub async fn connect(
     let (ws, _respone) = connect_async(url).await?;
     Ok(TwitchChatStream::new(ws))
 }
+
+/// Creates a connection to Twitch chat that yields the parsed IRC messages without converting
+/// them to events
+pub async fn connect_irc(
+    url: impl IntoClientRequest + Unpin,
+) -> Result<IrcMessageStream<impl Stream<Item = Result<Message, WsError>> + Unpin>, Error> {
+    let (ws, _response) = connect_async(url).await?;
+    Ok(IrcMessageStream::new(ws))
+}
+
+/// Wraps a connection in a stream that reconnects by calling `connect` again whenever the
+/// connection ends or fails with a websocket error, so that it yields a continuous stream of
+/// events. Errors, including failed connection attempts, are passed on before reconnecting.
+/// Other errors, like events that failed to parse, don't cause a reconnect.
+///
+/// Unlike the connections in [`single`](../single/index.html), nothing is sent automatically
+/// after reconnecting, so `connect` has to take care of logging in and joining channels.
+///
+/// ```no_run
+/// # use futures_util::StreamExt;
+/// use tmi_rs::raw::{connect, reconnecting_stream};
+///
+/// # async fn example() {
+/// let mut events = reconnecting_stream(|| connect("wss://irc-ws.chat.twitch.tv:443"));
+/// while let Some(event) = events.next().await {
+///     println!("{:?}", event);
+/// }
+/// # }
+/// ```
+pub fn reconnecting_stream<F, Fut, St, T>(connect: F) -> ReconnectingStream<F, Fut, St>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<St, Error>>,
+    St: Stream<Item = Result<T, Error>> + Unpin,
+{
+    ReconnectingStream::new(connect)
+}
+
+enum ConnectionState<Fut, St> {
+    Connecting(Pin<Box<Fut>>),
+    Connected(St),
+    Waiting(Delay),
+}
+
+/// Stream that reconnects when the connection ends. See
+/// [`reconnecting_stream`](fn.reconnecting_stream.html).
+pub struct ReconnectingStream<F, Fut, St> {
+    connect: F,
+    state: ConnectionState<Fut, St>,
+    retry_delay: Duration,
+}
+
+impl<F, Fut, St: Unpin> Unpin for ReconnectingStream<F, Fut, St> {}
+
+impl<F, Fut, St> ReconnectingStream<F, Fut, St>
+where
+    F: FnMut() -> Fut,
+{
+    fn new(mut connect: F) -> Self {
+        let connecting = Box::pin(connect());
+        ReconnectingStream {
+            connect,
+            state: ConnectionState::Connecting(connecting),
+            retry_delay: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the time to wait before each reconnect attempt. Defaults to one second.
+    pub fn retry_delay(mut self, retry_delay: Duration) -> Self {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    fn wait_for_retry(&mut self) {
+        self.state = ConnectionState::Waiting(delay_for(self.retry_delay));
+    }
+}
+
+impl<F, Fut, St, T> Stream for ReconnectingStream<F, Fut, St>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<St, Error>>,
+    St: Stream<Item = Result<T, Error>> + Unpin,
+{
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match &mut self.state {
+                ConnectionState::Waiting(delay) => match delay.poll_unpin(cx) {
+                    Poll::Ready(()) => {
+                        debug!("Reconnecting");
+                        let connecting = Box::pin((self.connect)());
+                        self.state = ConnectionState::Connecting(connecting);
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                ConnectionState::Connecting(connecting) => match connecting.as_mut().poll(cx) {
+                    Poll::Ready(Ok(stream)) => self.state = ConnectionState::Connected(stream),
+                    Poll::Ready(Err(e)) => {
+                        self.wait_for_retry();
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    Poll::Pending => return Poll::Pending,
+                },
+                ConnectionState::Connected(stream) => match stream.poll_next_unpin(cx) {
+                    Poll::Ready(Some(Err(e @ Error::WebsocketError(_)))) => {
+                        self.wait_for_retry();
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    Poll::Ready(Some(item)) => return Poll::Ready(Some(item)),
+                    Poll::Ready(None) => self.wait_for_retry(),
+                    Poll::Pending => return Poll::Pending,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use futures_util::StreamExt;
+
+    use crate::client::mock_server::MockServer;
+    use crate::client::raw::{connect, reconnecting_stream};
+    use crate::event::*;
+
+    #[tokio::test]
+    async fn test_reconnecting_stream() {
+        let mut server = MockServer::bind().await;
+        let url = server.url.clone();
+        let mut events = reconnecting_stream(move || connect(url.clone()))
+            .retry_delay(Duration::from_millis(10));
+
+        tokio::spawn(async move {
+            for text in &["first", "second"] {
+                let mut connection = server.next_connection().await;
+                connection
+                    .send(&format!(
+                        ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :{}",
+                        text
+                    ))
+                    .await;
+                // dropping the connection ends the stream on the client side
+            }
+        });
+
+        let mut messages = vec![];
+        while messages.len() < 2 {
+            if let Some(Ok(Event::PrivMsg(event))) = events.next().await {
+                messages.push(event.message().to_string());
+            }
+        }
+        assert_eq!(messages, vec!["first", "second"]);
+    }
+}
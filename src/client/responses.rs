@@ -0,0 +1,124 @@
+//! Matching of incoming events to messages that wait for a response from Twitch
+
+use std::fmt;
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::event::tags::*;
+use crate::event::*;
+use crate::MessageSendError;
+
+/// NOTICE `msg-id`s that Twitch responds with when joining a channel is not possible
+const JOIN_FAILURE_NOTICES: &[&str] = &["msg_channel_suspended", "msg_channel_blocked", "tos_ban"];
+
+type PendingResponse = Box<dyn FnMut(&Event<String>) -> bool + Send>;
+
+/// Keeps track of sent messages that are waiting for a response. Incoming events are passed to
+/// all pending matchers until one of them accepts the event as its response.
+#[derive(Default)]
+pub(crate) struct ResponseRegistry {
+    pending: Mutex<Vec<PendingResponse>>,
+}
+
+impl ResponseRegistry {
+    /// Wait for the first event that `matcher` returns a response for. The matcher is removed
+    /// once it found a response or when the returned receiver is dropped.
+    pub(crate) fn wait_for<R, F>(&self, mut matcher: F) -> oneshot::Receiver<R>
+    where
+        R: Send + 'static,
+        F: FnMut(&Event<String>) -> Option<R> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let mut tx = Some(tx);
+        self.pending.lock().push(Box::new(move |event| {
+            // the waiting side gave up, nothing left to match
+            match &tx {
+                Some(tx) if !tx.is_closed() => {}
+                _ => return true,
+            }
+            match matcher(event) {
+                Some(response) => {
+                    if let Some(tx) = tx.take() {
+                        tx.send(response).ok();
+                    }
+                    true
+                }
+                None => false,
+            }
+        }));
+        rx
+    }
+
+    /// Pass an incoming event to all pending matchers
+    pub(crate) fn handle_event(&self, event: &Event<String>) {
+        let mut pending = self.pending.lock();
+        let mut i = 0;
+        while i < pending.len() {
+            if (pending[i])(event) {
+                drop(pending.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ResponseRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseRegistry")
+            .field("pending", &self.pending.lock().len())
+            .finish()
+    }
+}
+
+/// Matches the events that confirm or reject joining `channel`. A join is confirmed by the end
+/// of the NAMES list or the ROOMSTATE for the channel and rejected by NOTICEs like
+/// `msg_channel_suspended`.
+pub(crate) fn join_response(
+    channel: &str,
+    event: &Event<String>,
+) -> Option<Result<(), MessageSendError>> {
+    match event {
+        Event::EndOfNames(event) if event.channel() == channel => Some(Ok(())),
+        Event::RoomState(event) if event.channel() == channel => Some(Ok(())),
+        Event::Notice(event) if event.channel() == channel => event
+            .msg_id()
+            .ok()
+            .filter(|msg_id| JOIN_FAILURE_NOTICES.contains(msg_id))
+            .map(|msg_id| {
+                Err(MessageSendError::JoinFailed {
+                    channel: channel.to_string(),
+                    msg_id: msg_id.to_string(),
+                })
+            }),
+        _ => None,
+    }
+}
+
+/// Matches the CLEARCHAT without a user that Twitch sends when the chat of `channel` was cleared
+pub(crate) fn clear_chat_response(channel: &str, event: &Event<String>) -> Option<()> {
+    match event {
+        Event::ClearChat(event) if event.channel() == channel && event.user().is_none() => Some(()),
+        _ => None,
+    }
+}
+
+/// Matches a ROOMSTATE for `channel` that contains `tag` with a value accepted by `accept`.
+/// Twitch sends a ROOMSTATE containing only the changed tag when a room setting is changed.
+pub(crate) fn room_state_response<F>(
+    channel: &str,
+    tag: &str,
+    accept: F,
+    event: &Event<String>,
+) -> Option<()>
+where
+    F: Fn(&str) -> bool,
+{
+    match event {
+        Event::RoomState(event) if event.channel() == channel => {
+            event.tag(tag).filter(|value| accept(value)).map(|_| ())
+        }
+        _ => None,
+    }
+}
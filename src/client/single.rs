@@ -1,24 +1,39 @@
+use std::iter;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use fnv::{FnvHashMap, FnvHashSet};
 use futures_sink::Sink;
-use futures_util::future::FutureExt;
-use futures_util::{pin_mut, select, SinkExt, StreamExt, TryStreamExt};
+use futures_util::future::{abortable, AbortHandle, FutureExt};
+use futures_util::{pin_mut, select, stream, SinkExt, StreamExt, TryStreamExt};
 use tokio::pin;
-use tokio::sync::{mpsc, oneshot, watch, RwLock};
-use tokio::time::{delay_for, delay_until, Duration, Instant};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tokio::sync::{mpsc, oneshot, watch, Notify, RwLock};
+use tokio::time::Duration;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
 
-use crate::client::{MessageSender, TimeoutReceiver, TwitchClient};
-use crate::client_messages::ClientMessage;
+use crate::client::responses::ResponseRegistry;
+use crate::client::{InnerMessageSender, MessageSender, TimeoutReceiver, TwitchClient};
+use crate::client_messages::{Capability, ClientMessage};
 use crate::event::tags::*;
 use crate::event::*;
 use crate::event::{Event, TwitchChatStream};
-use crate::irc_constants::RPL_ENDOFMOTD;
-use crate::stream::rate_limits::RateLimiter;
-use crate::stream::{ClientMessageStream, EventStream, SendStreamExt, SentClientMessage};
+use crate::runtime::{self, delay_for, delay_until, timeout, timeout_at, Instant};
+use crate::stream::pause::PauseHandle;
+use crate::stream::rate_limits::{ChannelPrivilege, RateLimiter, SlowModeLimit};
+use crate::stream::{
+    ClientMessageStream, EventStream, MessageResponder, SendStreamExt, SentClientMessage,
+};
 use crate::util::InternalSender;
-use crate::{Error, MessageResponse, TwitchClientConfig};
+use crate::{
+    Error, MessageResponse, MessageSendError, SentObserver, StringRef, TwitchClientConfig,
+};
+
+/// NOTICE `msg-id`s that tell that a channel is in emote-only mode
+const EMOTE_ONLY_NOTICES: &[&str] = &["emote_only_on", "msg_emoteonly"];
+
+/// Channels that are known to be in emote-only mode
+pub(crate) type EmoteOnlyChannels = parking_lot::RwLock<FnvHashSet<String>>;
 
 /// Connects to the Twitch servers, authenticates and listens for messages. Await the returned future
 /// to block until the connection is closed.
@@ -27,9 +42,13 @@ pub async fn connect(
 ) -> Result<TwitchClient<impl EventStream>, Error> {
     let (event_sender, event_stream) = mpsc::channel(cfg.channel_buffer);
 
-    let (sender, _) = connect_internal(
+    let (sender, context) = connect_internal(
         cfg,
+        0,
         Arc::new(RateLimiter::from(&cfg.rate_limiter)),
+        Default::default(),
+        Default::default(),
+        Default::default(),
         InternalSender(event_sender),
         true,
     )
@@ -37,54 +56,229 @@ pub async fn connect(
     Ok(TwitchClient {
         sender,
         stream: event_stream,
+        context,
     })
 }
 
+/// Like [`connect`](fn.connect.html), but returns immediately instead of waiting until the
+/// connection is established. Messages sent in the meantime are held back until the connection is
+/// active and then sent in order. Their `send` calls only complete once that happened. If the
+/// client gives up reconnecting before the connection became active, they fail with
+/// `MessageSendError::Closed`. Holding back messages is limited by `channel_buffer`, sending more
+/// messages waits for space to become available.
+/// Must be called from within a tokio runtime.
+pub fn connect_nowait(cfg: &Arc<TwitchClientConfig>) -> TwitchClient<impl EventStream> {
+    let (event_sender, event_stream) = mpsc::channel(cfg.channel_buffer);
+    let pause = Arc::new(PauseHandle::default());
+
+    let (connection_sender, context, _) = start_connection(
+        cfg,
+        0,
+        Arc::new(RateLimiter::from(&cfg.rate_limiter)),
+        Default::default(),
+        Default::default(),
+        pause.clone(),
+        InternalSender(event_sender),
+        true,
+    );
+
+    // forward messages to the connection once it is active
+    let (sender, mut held_messages) = mpsc::channel::<SentClientMessage>(cfg.channel_buffer);
+    runtime::spawn({
+        let mut connection_sender = connection_sender;
+        let mut connected_state = context.connected_state.clone();
+        async move {
+            loop {
+                match connected_state.next().await {
+                    Some(ConnectedState::Active) => break,
+                    // gave up reconnecting before the connection became active
+                    Some(ConnectedState::Closed) | None => {
+                        held_messages.close();
+                        while let Some(held) = held_messages.recv().await {
+                            held.responder
+                                .send(Err(MessageSendError::Closed(held.message)))
+                                .ok();
+                        }
+                        return;
+                    }
+                    _ => {}
+                }
+            }
+            while let Some(message) = held_messages.recv().await {
+                if connection_sender.send(message).await.is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    TwitchClient {
+        sender: MessageSender::new(
+            sender,
+            context.responses.clone(),
+            context.emote_only.clone(),
+            pause,
+        ),
+        stream: event_stream,
+        context,
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn connect_internal(
     cfg: &Arc<TwitchClientConfig>,
+    connection_id: usize,
     rate_limiter: Arc<RateLimiter>,
+    responses: Arc<ResponseRegistry>,
+    emote_only: Arc<EmoteOnlyChannels>,
+    pause: Arc<PauseHandle>,
     event_sender: impl Sink<Result<Event, Error>> + Send + Sync + 'static,
     handle_whispers: bool,
 ) -> Result<(MessageSender, Arc<ConnectionContext>), Error> {
+    let (message_sender, state, abort_handle) = start_connection(
+        cfg,
+        connection_id,
+        rate_limiter,
+        responses,
+        emote_only,
+        pause.clone(),
+        event_sender,
+        handle_whispers,
+    );
+
+    let mut connected_state = state.connected_state.clone();
+    let ready = async {
+        loop {
+            match connected_state.next().await {
+                Some(ConnectedState::Active) => return Ok(()),
+                // gave up reconnecting before the connection became ready
+                Some(ConnectedState::Closed) | None => return Err(Error::ConnectionClosed),
+                _ => {}
+            }
+        }
+    };
+    if let Some(ready_timeout) = cfg.ready_timeout {
+        match timeout(ready_timeout, ready).await {
+            Ok(result) => result?,
+            Err(_) => {
+                error!(
+                    "[{}] Connection did not become ready within {:?}, giving up.",
+                    state.label, ready_timeout
+                );
+                abort_handle.abort();
+                return Err(Error::ReadyTimeout(ready_timeout));
+            }
+        }
+    } else {
+        ready.await?;
+    }
+
+    Ok((
+        MessageSender::new(
+            message_sender,
+            state.responses.clone(),
+            state.emote_only.clone(),
+            pause,
+        ),
+        state,
+    ))
+}
+
+/// Spawn the task that maintains the connection, without waiting for it to connect
+#[allow(clippy::too_many_arguments)]
+fn start_connection(
+    cfg: &Arc<TwitchClientConfig>,
+    connection_id: usize,
+    rate_limiter: Arc<RateLimiter>,
+    responses: Arc<ResponseRegistry>,
+    emote_only: Arc<EmoteOnlyChannels>,
+    pause: Arc<PauseHandle>,
+    event_sender: impl Sink<Result<Event, Error>> + Send + Sync + 'static,
+    handle_whispers: bool,
+) -> (InnerMessageSender, Arc<ConnectionContext>, AbortHandle) {
     let (connected_setter, connected_state) = watch::channel(ConnectedState::Disconnected);
     let state = Arc::new(ConnectionContext {
+        id: connection_id,
+        label: cfg.connection_label(connection_id),
         connected_state,
         connected_setter,
         connecting_lock: RwLock::new(()),
-        joined_channels: RwLock::new(vec![]),
+        joined_channels: parking_lot::RwLock::new(vec![]),
         rate_limiter,
+        responses,
+        reconnect: Notify::new(),
+        capabilities: Default::default(),
+        own_badges: Default::default(),
+        emote_only,
+        active_since: Default::default(),
         whisper_enabled: handle_whispers,
     });
 
     let (message_sender, message_stream) = mpsc::channel::<SentClientMessage>(cfg.channel_buffer);
+    // internal messages like login, rejoins and pongs bypass the send middleware, so a middleware
+    // that filters or delays messages can't break the connection
+    let (internal_sender, internal_stream) = mpsc::channel::<SentClientMessage>(cfg.channel_buffer);
 
-    let mut message_stream =
-        message_stream.rate_limited(cfg.channel_buffer, state.rate_limiter.clone());
+    let message_stream: Box<dyn ClientMessageStream> = match &cfg.send_middleware {
+        Some(middleware) => middleware(Box::new(message_stream)),
+        None => Box::new(message_stream),
+    };
+    let mut message_stream = stream::select(message_stream, internal_stream)
+        .pausable(pause.clone())
+        .rate_limited(cfg.channel_buffer, state.rate_limiter.clone());
 
-    tokio::spawn({
+    let (connection_task, abort_handle) = abortable({
         let cfg = cfg.clone();
-        let mut message_sender = MessageSender::from(message_sender.clone());
+        let mut message_sender = MessageSender::new(
+            internal_sender,
+            state.responses.clone(),
+            state.emote_only.clone(),
+            pause.clone(),
+        );
         let context = state.clone();
 
         async move {
             pin!(event_sender);
-            let mut reconnect_counter = 0_u32;
+            let mut reconnects = ReconnectCounter::new(cfg.stability_threshold);
+            let mut first_attempt = true;
+            let mut result = Ok(());
             loop {
-                if reconnect_counter > 0 {
-                    if reconnect_counter < cfg.max_reconnects {
+                if reconnects.attempts > 0 {
+                    if reconnects.may_retry(cfg.max_reconnects) {
+                        let delay = cfg.reconnect_delay(reconnects.attempts - 1);
                         info!(
-                            "Reconnecting in {} seconds...",
-                            cfg.reconnect_delay.as_secs()
+                            label = context.label, url = cfg.url;
+                            "[{}] Reconnecting in {:.1} seconds...",
+                            context.label,
+                            delay.as_secs_f32()
                         );
-                        delay_for(cfg.reconnect_delay).await;
+                        delay_for(delay).await;
                     } else {
-                        error!("Maximum number of reconnect attempts reached, quitting.");
+                        error!(
+                            "[{}] Maximum number of reconnect attempts reached, quitting.",
+                            context.label
+                        );
                         break;
                     }
                 }
-                reconnect_counter += 1;
+                reconnects.attempts += 1;
+                if !first_attempt {
+                    record_counter!("tmi.reconnects", 1);
+                    let reconnecting = ReconnectingEvent {
+                        attempt: reconnects.attempts,
+                    };
+                    let sent = send_connection_event(
+                        Pin::new(&mut event_sender),
+                        reconnecting.into(),
+                        &context.label,
+                    );
+                    if !sent {
+                        break;
+                    }
+                }
+                first_attempt = false;
 
-                match inner_connect_task(
+                let disconnect_reason = inner_connect_task(
                     &context,
                     &cfg,
                     Pin::new(&mut event_sender),
@@ -92,28 +286,95 @@ pub(crate) async fn connect_internal(
                     &mut message_stream,
                     handle_whispers,
                 )
-                .await?
-                {
-                    DisconnectReason::Closed => break,
-                    DisconnectReason::Canceled
-                    | DisconnectReason::Timeout
-                    | DisconnectReason::IoError
-                    | DisconnectReason::ConnectFailed => {}
+                .await;
+                // only the time the connection was active counts towards stability, not the login
+                let active_for = context
+                    .active_since
+                    .lock()
+                    .take()
+                    .map_or(Duration::from_secs(0), |since| since.elapsed());
+                match disconnect_reason {
+                    Ok(DisconnectReason::Closed) => break,
+                    // requested reconnects happen immediately and don't count as failed attempts
+                    Ok(DisconnectReason::Reconnect) => reconnects.attempts = 0,
+                    Ok(DisconnectReason::Canceled)
+                    | Ok(DisconnectReason::Timeout)
+                    | Ok(DisconnectReason::IoError) => reconnects.connection_lost(active_for),
+                    Ok(DisconnectReason::ConnectFailed) => {}
+                    Err(e) => {
+                        result = Err(e);
+                        break;
+                    }
                 }
             }
 
-            Ok::<_, Error>(())
+            // close the channel first, so sending fails once the state is `Closed`
+            drop(message_stream);
+            context
+                .connected_setter
+                .broadcast(ConnectedState::Closed)
+                .ok();
+            result
         }
     });
+    runtime::spawn(connection_task);
 
-    let mut connected_state = state.connected_state.clone();
-    while connected_state.next().await != Some(ConnectedState::Active) {}
+    (message_sender, state, abort_handle)
+}
+
+/// Sends an event about the state of the connection without waiting for the consumer. The event
+/// is dropped if the event channel is full, so a consumer that doesn't read events can't keep the
+/// connection from reconnecting. Returns `false` if the channel was closed.
+fn send_connection_event(
+    mut event_sender: Pin<&mut impl Sink<Result<Event, Error>>>,
+    event: Event,
+    label: &str,
+) -> bool {
+    match event_sender.send(Ok(event)).now_or_never() {
+        Some(result) => result.is_ok(),
+        None => {
+            warn!("[{}] Event channel is full, dropping event", label);
+            true
+        }
+    }
+}
 
-    Ok((MessageSender::from(message_sender), state))
+/// Counts connection attempts since the connection was last stable. A lost connection only resets
+/// the count if it stayed connected for at least the stability threshold, so a connection that
+/// keeps dropping right after connecting still runs out of attempts and keeps backing off.
+struct ReconnectCounter {
+    attempts: u32,
+    stability_threshold: Duration,
 }
 
+impl ReconnectCounter {
+    fn new(stability_threshold: Duration) -> Self {
+        ReconnectCounter {
+            attempts: 0,
+            stability_threshold,
+        }
+    }
+
+    /// Whether another attempt is allowed with the given limit, `None` allows any number
+    fn may_retry(&self, max_reconnects: Option<u32>) -> bool {
+        match max_reconnects {
+            Some(max) => self.attempts < max,
+            None => true,
+        }
+    }
+
+    /// Record a lost connection that was connected for `connected_for`
+    fn connection_lost(&mut self, connected_for: Duration) {
+        if connected_for >= self.stability_threshold {
+            self.attempts = 0;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 enum DisconnectReason {
     Closed,
+    Reconnect,
     Canceled,
     Timeout,
     IoError,
@@ -122,20 +383,125 @@ enum DisconnectReason {
 
 /// Everything stateful relating to a chat connection, usually passed around in an `Arc`
 pub struct ConnectionContext {
+    /// Number of the connection, unique within a connection pool
+    pub id: usize,
+    /// Label identifying the connection in log output
+    pub label: String,
     /// whether the client is currently (re-)connecting, to avoid losing queued messages during reconnections.
     /// Locked as read access for sending messages, as write access while reconnecting
     pub connecting_lock: RwLock<()>,
-    /// Channels that this client is supposed to be joining on reconnect
-    pub joined_channels: RwLock<Vec<String>>,
+    /// Channels that this client is supposed to be joining on reconnect. Only updated
+    /// synchronously in the order messages are sent and events are received, so the lock is
+    /// never held across await points.
+    pub joined_channels: parking_lot::RwLock<Vec<String>>,
     /// Rate limiter used for the connection. Since this can be shared between multiple connections,
     /// it is wrapped in an Arc
     pub rate_limiter: Arc<RateLimiter>,
+    /// Messages waiting for a response, can be shared between multiple connections
+    pub(crate) responses: Arc<ResponseRegistry>,
     /// whether the connection is currently active
     pub connected_state: watch::Receiver<ConnectedState>,
     connected_setter: watch::Sender<ConnectedState>,
+    /// notified to close the connection and connect again
+    reconnect: Notify,
+    /// capabilities requested on the current connection and Twitch's responses to them
+    capabilities: parking_lot::Mutex<FnvHashMap<&'static str, CapStatus>>,
+    /// badges of the logged in user in each joined channel, from USERSTATE
+    own_badges: parking_lot::RwLock<FnvHashMap<String, Vec<Badge<String>>>>,
+    /// channels that ROOMSTATEs and NOTICEs reported to be in emote-only mode, shared between the
+    /// connections of a pool like the rate limiter
+    pub(crate) emote_only: Arc<EmoteOnlyChannels>,
+    /// when the current connection became active, `None` while it is not
+    active_since: parking_lot::Mutex<Option<Instant>>,
     pub(crate) whisper_enabled: bool,
 }
 
+impl ConnectionContext {
+    /// Close the connection and connect again. Joined channels are joined again after
+    /// reconnecting.
+    pub fn reconnect(&self) {
+        self.reconnect.notify();
+    }
+
+    /// Whether Twitch granted a capability on the current connection
+    pub fn cap_status(&self, capability: Capability) -> CapStatus {
+        let name: &'static str = (&capability).into();
+        self.capabilities
+            .lock()
+            .get(name)
+            .copied()
+            .unwrap_or(CapStatus::NotRequested)
+    }
+
+    /// Badges of the logged in user in a channel, as of the last USERSTATE Twitch sent for it.
+    /// `None` if no USERSTATE was received for the channel since it was joined.
+    pub fn own_badges(&self, channel: &str) -> Option<Vec<Badge<String>>> {
+        self.own_badges.read().get(channel).cloned()
+    }
+
+    fn update_own_badges(&self, channel: &str, badges: &[Badge<&str>]) {
+        let badges = badges
+            .iter()
+            .map(|badge| Badge {
+                badge: badge.badge.to_string(),
+                version: badge.version.to_string(),
+            })
+            .collect();
+        self.own_badges.write().insert(channel.to_string(), badges);
+    }
+
+    /// Remember channels entering or leaving emote-only mode
+    fn track_emote_only(&self, event: &Event<String>) {
+        let (channel, emote_only) = match event {
+            // partial ROOMSTATEs only contain the setting that changed
+            Event::RoomState(event) => match event.tag("emote-only") {
+                Some(_) => (event.channel(), event.emote_only()),
+                None => return,
+            },
+            Event::Notice(event) => match event.msg_id() {
+                Ok(msg_id) if EMOTE_ONLY_NOTICES.contains(&msg_id) => (event.channel(), true),
+                Ok("emote_only_off") => (event.channel(), false),
+                _ => return,
+            },
+            _ => return,
+        };
+        let mut channels = self.emote_only.write();
+        if emote_only {
+            channels.insert(channel.to_string());
+        } else {
+            channels.remove(channel);
+        }
+    }
+
+    /// Record the ACK or NAK response to capability requests
+    fn update_capabilities<T: StringRef>(&self, event: &EventData<T, CapabilityEvent<T>>) {
+        let status = match event.subcommand() {
+            Some("ACK") => CapStatus::Granted,
+            Some("NAK") => CapStatus::Denied,
+            _ => return,
+        };
+        let mut capabilities = self.capabilities.lock();
+        for name in event.capabilities() {
+            if let Some(requested) = capabilities.get_mut(name) {
+                *requested = status;
+            }
+        }
+    }
+}
+
+/// Response to a capability request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapStatus {
+    /// Requested, but Twitch didn't respond yet
+    Pending,
+    /// Twitch acknowledged the capability
+    Granted,
+    /// Twitch rejected the capability
+    Denied,
+    /// The capability wasn't requested on this connection
+    NotRequested,
+}
+
 async fn inner_connect_task(
     context: &Arc<ConnectionContext>,
     cfg: &TwitchClientConfig,
@@ -151,12 +517,19 @@ async fn inner_connect_task(
             .expect("set connecting state");
         let _connecting_guard = context.connecting_lock.write();
 
-        info!("Connecting to {}", cfg.url);
+        info!(
+            label = context.label, url = cfg.url;
+            "[{}] Connecting to {}", context.label, cfg.url
+        );
         // create the websocket connection
         let (ws, _) = match connect_async(cfg.url.clone()).await {
             Ok(conn) => conn,
             Err(e) => {
-                warn!("Connection could not be established. {}", e);
+                warn!(
+                    label = context.label, url = cfg.url;
+                    "[{}] Connection could not be established. {}",
+                    context.label, e
+                );
                 return Ok(DisconnectReason::ConnectFailed);
             }
         };
@@ -165,39 +538,51 @@ async fn inner_connect_task(
             .connected_setter
             .broadcast(ConnectedState::Established)
             .expect("set connecting state");
+        if !send_connection_event(event_sender.as_mut(), ConnectedEvent.into(), &context.label) {
+            return Ok(DisconnectReason::Canceled);
+        }
 
         // wrap with IRC/Twitch logic
-        let (mut chat_sink, incoming_stream) = TwitchChatStream::new(ws).split::<Message>();
+        let (mut chat_sink, incoming_stream) = TwitchChatStream::new(ws)
+            .keep_raw_lines(cfg.keep_raw_lines)
+            .lowercase_tag_keys(cfg.lowercase_tag_keys)
+            .strict(cfg.strict_parsing)
+            .split::<Message>();
 
+        let channel_limit = cfg.channel_limit;
+        let batch_window = cfg.batch_window;
+        let sent_observer = cfg.sent_observer.as_ref();
         let connection_future = async move {
-            while let Some(SentClientMessage { message, responder }) = message_stream.next().await {
-                match &message {
-                    ClientMessage::Join(channel) => {
-                        let conn_ctx = context.clone();
-                        let channel = channel.clone();
-                        tokio::spawn(async move {
-                            conn_ctx.joined_channels.write().await.push(channel);
-                        });
-                    }
-                    ClientMessage::Part(channel) => {
-                        let conn_ctx = context.clone();
-                        let channel = channel.clone();
-                        tokio::spawn(async move {
-                            conn_ctx
-                                .joined_channels
-                                .write()
-                                .await
-                                .retain(|ch| ch != &channel);
-                        });
+            let mut stream_ended = false;
+            while !stream_ended {
+                let mut batch = MessageBatch::default();
+                match message_stream.next().await {
+                    Some(sent_message) => batch.push(context, sent_message, channel_limit),
+                    None => break,
+                }
+                // collect everything else that is ready to be sent within the window
+                if let Some(batch_window) = batch_window {
+                    let deadline = Instant::now() + batch_window;
+                    loop {
+                        match timeout_at(deadline, message_stream.next()).await {
+                            Ok(Some(sent_message)) => {
+                                batch.push(context, sent_message, channel_limit)
+                            }
+                            Ok(None) => {
+                                stream_ended = true;
+                                break;
+                            }
+                            Err(_) => break,
+                        }
                     }
-                    _ => {}
                 }
 
                 let _connecting_guard = context.connecting_lock.read().await;
-                chat_sink.send(message.into()).await?;
-                responder.send(Ok(MessageResponse::Ok)).ok();
+                if batch.send(&mut chat_sink, sent_observer).await? {
+                    return Ok(true);
+                }
             }
-            Ok::<(), Error>(())
+            Ok::<_, Error>(false)
         }
         .fuse();
 
@@ -209,17 +594,23 @@ async fn inner_connect_task(
     let mut event_receiver = event_receiver.fuse();
 
     pin_mut!(connection_future);
+    let reconnect = context.reconnect.notified().fuse();
+    pin_mut!(reconnect);
 
     #[inline]
     async fn handle_event(
         item: Option<Result<Event, Error>>,
         event_sender: &mut (impl Sink<Result<Event, Error>> + Unpin),
         handle_whispers: bool,
+        label: &str,
     ) -> Option<Result<DisconnectReason, Error>> {
         if let Some(item) = item {
             if let Err(Error::WebsocketError(ws_err)) = &item {
                 if let tokio_tungstenite::tungstenite::Error::Io(io_err) = &**ws_err {
-                    warn!("IO error in websocket, reconnecting: {}", io_err);
+                    warn!(
+                        "[{}] IO error in websocket, reconnecting: {}",
+                        label, io_err
+                    );
                     return Some(Ok(DisconnectReason::IoError));
                 }
             }
@@ -231,37 +622,29 @@ async fn inner_connect_task(
             }
 
             if event_sender.send(item).await.is_err() {
-                info!("Chat consumer dropped receiver stream, ending connection");
+                info!(
+                    "[{}] Chat consumer dropped receiver stream, ending connection",
+                    label
+                );
                 return Some(Ok(DisconnectReason::Canceled));
             }
         } else {
-            debug!("Connection closed normally");
+            debug!("[{}] Connection closed normally", label);
             return Some(Ok(DisconnectReason::Closed));
         }
         None
     }
 
-    tokio::spawn({
+    runtime::spawn({
         let context = context.clone();
         let cfg = cfg.clone();
         let mut message_sender = message_sender.clone();
         async move {
-            // todo: get rid of unwraps
-            // send capability requests on connect
-            let capabilities = cfg.get_capabilities();
-            message_sender
-                .send(ClientMessage::CapRequest(capabilities))
-                .await
-                .unwrap();
-            for msg in ClientMessage::login(cfg.username.clone(), cfg.token.clone()).into_iter() {
-                message_sender.send(msg).await.unwrap();
-            }
-
-            for channel in context.joined_channels.read().await.clone() {
-                message_sender
-                    .send(ClientMessage::Join(channel))
-                    .await
-                    .unwrap();
+            if let Err(e) = login_and_rejoin(&context, &cfg, &mut message_sender).await {
+                warn!(
+                    "[{}] Failed to log in and rejoin channels: {}",
+                    context.label, e
+                );
             }
         }
     });
@@ -274,15 +657,23 @@ async fn inner_connect_task(
         loop {
             select! {
                 item = event_receiver.next() => {
-                    if let Some(handle_event_result) = handle_event(item, &mut event_sender, handle_whispers).await {
+                    if let Some(handle_event_result) = handle_event(item, &mut event_sender, handle_whispers, &context.label).await {
                         return handle_event_result;
                     }
                 },
                 timeout = timeout_receiver => {
-                    warn!("Twitch didn't respond to PING in time, closing connection.");
+                    warn!(label = context.label; "[{}] Twitch didn't respond to PING in time, closing connection.", context.label);
                     return Ok(DisconnectReason::Timeout);
                 },
-                msg_forward = connection_future => {}
+                closed = connection_future => {
+                    if let Some(reason) = connection_future_ended(closed, &context.label) {
+                        return Ok(reason);
+                    }
+                },
+                _ = reconnect => {
+                    info!(label = context.label; "[{}] Reconnecting on request.", context.label);
+                    return Ok(DisconnectReason::Reconnect);
+                }
             }
         }
     } else {
@@ -291,16 +682,191 @@ async fn inner_connect_task(
         loop {
             select! {
                 item = event_receiver.next() => {
-                    if let Some(handle_event_result) = handle_event(item, &mut event_sender, handle_whispers).await {
+                    if let Some(handle_event_result) = handle_event(item, &mut event_sender, handle_whispers, &context.label).await {
                         return handle_event_result;
                     }
                 },
-                msg_forward = connection_future => {}
+                closed = connection_future => {
+                    if let Some(reason) = connection_future_ended(closed, &context.label) {
+                        return Ok(reason);
+                    }
+                },
+                _ = reconnect => {
+                    info!(label = context.label; "[{}] Reconnecting on request.", context.label);
+                    return Ok(DisconnectReason::Reconnect);
+                }
             }
         }
     }
 }
 
+/// Requests capabilities, logs in and joins the channels that were joined on the previous
+/// connection. Failing to rejoin a channel is logged and doesn't stop the other channels from
+/// being joined.
+async fn login_and_rejoin(
+    context: &ConnectionContext,
+    cfg: &TwitchClientConfig,
+    message_sender: &mut MessageSender,
+) -> Result<(), MessageSendError> {
+    // send capability requests on connect
+    let capabilities = cfg.get_capabilities();
+    *context.capabilities.lock() = capabilities
+        .iter()
+        .map(|capability| (capability.into(), CapStatus::Pending))
+        .collect();
+    message_sender
+        .send(ClientMessage::CapRequest(capabilities))
+        .await?;
+    for msg in ClientMessage::login(cfg.username.clone(), cfg.token.clone()).into_iter() {
+        message_sender.send(msg).await?;
+    }
+
+    // the lock must not be held while sending, joins need write access to it
+    let joined_channels = context.joined_channels.read().clone();
+    for channel in joined_channels {
+        // limits learned on the previous connection may be stale, they are learned again
+        // from the USERSTATE and ROOMSTATE sent after joining
+        context.rate_limiter.reset_channel(&channel);
+        context.own_badges.write().remove(&channel);
+        context.emote_only.write().remove(&channel);
+        match message_sender.send(ClientMessage::Join(channel)).await {
+            Ok(_) => {}
+            // the connection is gone, the next one rejoins the remaining channels
+            Err(e @ MessageSendError::Closed(_)) => return Err(e),
+            Err(e) => warn!("[{}] Failed to rejoin channel: {}", context.label, e),
+        }
+    }
+    Ok(())
+}
+
+/// Decides how to continue once the future writing messages to the connection completed with
+/// `result`. Failing to write means the connection is broken, so it is treated like an IO error
+/// and reconnected instead of ending the connection task.
+fn connection_future_ended(result: Result<bool, Error>, label: &str) -> Option<DisconnectReason> {
+    match result {
+        Ok(true) => {
+            debug!("[{}] Connection closed on request", label);
+            Some(DisconnectReason::Closed)
+        }
+        Ok(false) => None,
+        Err(e) => {
+            warn!("[{}] Error while sending, reconnecting: {}", label, e);
+            Some(DisconnectReason::IoError)
+        }
+    }
+}
+
+/// Messages that are sent to the server together
+#[derive(Default)]
+struct MessageBatch {
+    messages: Vec<ClientMessage>,
+    responders: Vec<MessageResponder>,
+    /// whether the batch contains a close request
+    close: bool,
+}
+
+impl MessageBatch {
+    /// Add a message to the batch, after checking the channel limit and tracking joined channels
+    fn push(
+        &mut self,
+        context: &ConnectionContext,
+        SentClientMessage { message, responder }: SentClientMessage,
+        channel_limit: usize,
+    ) {
+        match &message {
+            ClientMessage::Join(channel) => {
+                let mut joined_channels = context.joined_channels.write();
+                if !joined_channels.contains(channel) {
+                    if joined_channels.len() >= channel_limit {
+                        responder
+                            .send(Err(MessageSendError::ChannelLimitReached(channel_limit)))
+                            .ok();
+                        return;
+                    }
+                    joined_channels.push(channel.clone());
+                }
+            }
+            ClientMessage::JoinAll(channels) => {
+                let mut joined_channels = context.joined_channels.write();
+                let mut new_channels: Vec<&String> = vec![];
+                for channel in channels {
+                    if !joined_channels.contains(channel) && !new_channels.contains(&channel) {
+                        new_channels.push(channel);
+                    }
+                }
+                if joined_channels.len() + new_channels.len() > channel_limit {
+                    responder
+                        .send(Err(MessageSendError::ChannelLimitReached(channel_limit)))
+                        .ok();
+                    return;
+                }
+                joined_channels.extend(new_channels.into_iter().cloned());
+            }
+            ClientMessage::Part(channel) => {
+                context.joined_channels.write().retain(|ch| ch != channel);
+            }
+            ClientMessage::PartAll(channels) => {
+                context
+                    .joined_channels
+                    .write()
+                    .retain(|ch| !channels.contains(ch));
+            }
+            ClientMessage::Close => self.close = true,
+            _ => {}
+        }
+        self.messages.push(message);
+        self.responders.push(responder);
+    }
+
+    /// Send the batch, joining consecutive text messages into a single websocket frame. Returns
+    /// whether the connection was closed on request. If writing fails, all messages of the batch
+    /// fail with `MessageSendError::Closed`.
+    async fn send(
+        self,
+        sink: &mut (impl Sink<Message, Error = WsError> + Unpin),
+        observer: Option<&SentObserver>,
+    ) -> Result<bool, Error> {
+        if let Err(e) = Self::write(&self.messages, sink, observer).await {
+            for (message, responder) in self.messages.into_iter().zip(self.responders) {
+                responder.send(Err(MessageSendError::Closed(message))).ok();
+            }
+            return Err(e.into());
+        }
+        record_counter!("tmi.messages_sent", self.responders.len() as u64);
+        for responder in self.responders {
+            responder.send(Ok(MessageResponse::Ok)).ok();
+        }
+        Ok(self.close)
+    }
+
+    async fn write(
+        messages: &[ClientMessage],
+        sink: &mut (impl Sink<Message, Error = WsError> + Unpin),
+        observer: Option<&SentObserver>,
+    ) -> Result<(), WsError> {
+        let mut lines: Vec<String> = vec![];
+        for message in messages {
+            if let Some(observer) = observer {
+                observer(message);
+            }
+            match message.into() {
+                Message::Text(line) => lines.push(line),
+                other => {
+                    if !lines.is_empty() {
+                        sink.feed(Message::Text(lines.join("\r\n"))).await?;
+                        lines.clear();
+                    }
+                    sink.feed(other).await?;
+                }
+            }
+        }
+        if !lines.is_empty() {
+            sink.feed(Message::Text(lines.join("\r\n"))).await?;
+        }
+        sink.flush().await
+    }
+}
+
 /// Wraps the chat receiver with additional commonly needed logic. Currently includes these features:
 ///
 /// * Configure a provided rate limiter for each joined channel (e.g. set VIP, Mod, Broadcaster status)
@@ -316,72 +882,137 @@ fn decorate_receiver_with_internals(
 ) -> (impl EventStream + 'static, Option<TimeoutReceiver>) {
     let (heartbeat_tx, timeout_rx) = if cfg.heartbeat {
         let (heartbeat_tx, heartbeat_rx) = watch::channel(Instant::now());
-        let timeout_rx = spawn_heartbeat(sender, heartbeat_rx);
+        let timeout_rx = spawn_heartbeat(sender, heartbeat_rx, conn_ctx.label.clone());
         (Some(heartbeat_tx), Some(timeout_rx))
     } else {
         (None, None)
     };
 
     let conn_ctx = conn_ctx.clone();
+    let ready_predicate = cfg.ready_predicate.clone();
+    let username = cfg.username.clone();
 
     let with_internals = chat_receiver.inspect_ok({
         let sender = sender.clone();
-        move |event| match event {
-            Event::Ping(_) => {
-                let mut sender = sender.clone();
-                tokio::spawn(async move {
-                    if sender.send(ClientMessage::Pong).await.is_err() {
-                        error!("Tried to respond to ping but the send channel was closed");
-                    }
-                });
-            }
-            Event::UserState(ref event) => {
-                let is_mod = event
-                    .badges()
-                    .unwrap()
-                    .into_iter()
-                    .any(|badge| ["moderator", "broadcaster", "vip"].contains(&badge.badge));
-                conn_ctx
-                    .rate_limiter
-                    .update_mod_status(event.channel(), is_mod);
-            }
-            Event::Pong(_) => {
-                if let Some(ref heartbeat_tx) = heartbeat_tx {
-                    if heartbeat_tx.broadcast(Instant::now()).is_err() {
-                        error!("heartbeat channel closed!");
+        move |event| {
+            record_counter!("tmi.events_received", 1);
+            conn_ctx.responses.handle_event(event);
+            conn_ctx.track_emote_only(event);
+            match event {
+                Event::Ping(ref ping) => {
+                    let mut sender = sender.clone();
+                    let label = conn_ctx.label.clone();
+                    let pong = ClientMessage::Pong(ping.params().to_vec());
+                    runtime::spawn(async move {
+                        if sender.send(pong).await.is_err() {
+                            error!(
+                                "[{}] Tried to respond to ping but the send channel was closed",
+                                label
+                            );
+                        }
+                    });
+                }
+                Event::UserState(ref event) => {
+                    let badges = event.badges().unwrap();
+                    let privilege =
+                        ChannelPrivilege::from_badges(badges.iter().map(|badge| badge.badge));
+                    conn_ctx
+                        .rate_limiter
+                        .update_privilege(event.channel(), privilege);
+                    conn_ctx.update_own_badges(event.channel(), &badges);
+                }
+                // partial ROOMSTATE updates only contain the settings that changed
+                Event::RoomState(ref event) if event.tag("slow").is_some() => {
+                    let slow = match event.slow() {
+                        Some(secs) => SlowModeLimit::Channel(secs),
+                        None => conn_ctx.rate_limiter.default_slow(),
+                    };
+                    conn_ctx.rate_limiter.update_slow_mode(event.channel(), slow);
+                }
+                Event::Capability(ref event) => conn_ctx.update_capabilities(event),
+                Event::GlobalUserState(ref event) => match event.user_id() {
+                    Ok(_) => info!("[{}] Logged in as {}.", conn_ctx.label, username),
+                    Err(e) => warn!("[{}] Invalid GLOBALUSERSTATE: {}", conn_ctx.label, e),
+                },
+                // the server confirmed that the user left the channel
+                Event::Part(ref event)
+                    if matches!(event.sender(), Some(sender) if sender.eq_ignore_ascii_case(&username)) =>
+                {
+                    let channel = event.channel();
+                    conn_ctx.rate_limiter.forget_channel(channel);
+                    conn_ctx.own_badges.write().remove(channel);
+                    conn_ctx.emote_only.write().remove(channel);
+                    conn_ctx.joined_channels.write().retain(|ch| ch != channel);
+                }
+                Event::Pong(_) => {
+                    if let Some(ref heartbeat_tx) = heartbeat_tx {
+                        if heartbeat_tx.broadcast(Instant::now()).is_err() {
+                            error!("[{}] heartbeat channel closed!", conn_ctx.label);
+                        }
                     }
                 }
+                _ => {}
             }
-            Event::ConnectMessage(msg) if msg.command() == RPL_ENDOFMOTD => {
-                info!("Connection established.");
+            // checked separately so that events handled above can signal readiness as well.
+            // Twitch sometimes repeats the welcome sequence, only announce the first one
+            if *conn_ctx.connected_state.borrow() != ConnectedState::Active
+                && ready_predicate(event)
+            {
+                info!("[{}] Connection established.", conn_ctx.label);
+                *conn_ctx.active_since.lock() = Some(Instant::now());
                 conn_ctx
                     .connected_setter
                     .broadcast(ConnectedState::Active)
                     .ok();
             }
-            _ => {}
         }
     });
 
+    // rejected messages and logins are reported right after the NOTICE or GLOBALUSERSTATE that
+    // says so
+    let login = cfg.username.clone();
+    let with_internals = with_internals.flat_map(move |item| {
+        let derived = match &item {
+            Ok(Event::Notice(notice)) => match notice.notice_id() {
+                Some(reason) if reason.is_message_rejection() => {
+                    let rejected = MessageRejectedEvent::new(notice.channel().clone(), reason);
+                    Some(Ok(rejected.into()))
+                }
+                _ => None,
+            },
+            Ok(Event::GlobalUserState(event)) => event.user_id().ok().map(|user_id| {
+                Ok(AuthenticatedEvent {
+                    user_id,
+                    login: login.clone(),
+                }
+                .into())
+            }),
+            _ => None,
+        };
+        stream::iter(iter::once(item).chain(derived))
+    });
+
     (with_internals, timeout_rx)
 }
 
 fn spawn_heartbeat(
     sender: &MessageSender,
     heartbeat_rx: watch::Receiver<Instant>,
+    label: String,
 ) -> TimeoutReceiver {
     const HEARTBEAT_DURATION: Duration = Duration::from_secs(20);
     let (timeout_tx, timeout_rx) = oneshot::channel();
     let mut sender = sender.clone();
 
-    tokio::spawn(async move {
+    runtime::spawn(async move {
         loop {
             sender.send(ClientMessage::<String>::Ping).await?;
             let sent_at = Instant::now();
             delay_until(sent_at + HEARTBEAT_DURATION).await;
             if *heartbeat_rx.borrow() < sent_at {
                 error!(
-                    "Connection timed out, waited {} seconds for PONG",
+                    "[{}] Connection timed out, waited {} seconds for PONG",
+                    label,
                     HEARTBEAT_DURATION.as_secs()
                 );
                 timeout_tx.send(()).ok(); // if this fails it just means a reconnect happened in the meantime
@@ -402,4 +1033,1581 @@ pub enum ConnectedState {
     Established,
     /// Fully active connection
     Active,
+    /// Closed on request or after giving up on reconnecting, the connection won't be used again
+    Closed,
+}
+
+#[cfg(test)]
+mod test {
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+
+    use futures_sink::Sink;
+    use futures_util::{future, FutureExt, StreamExt};
+    use tokio::sync::{mpsc, oneshot};
+    use tokio::time::{delay_for, pause, timeout, Duration};
+    use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+
+    use crate::client::mock_server::MockServer;
+    use crate::client::single::{
+        connect, connect_internal, connect_nowait, connection_future_ended, CapStatus,
+        ConnectedState, DisconnectReason, MessageBatch, ReconnectCounter,
+    };
+    use crate::event::tags::{Badge, NoticeId};
+    use crate::event::{
+        AuthenticatedEvent, ConnectMessageEventData, ConnectedEvent, Event, MessageRejectedEvent,
+        ReconnectingEvent,
+    };
+    use crate::stream::rate_limits::{
+        RateLimitBucketConfig, RateLimiter, RateLimiterConfig, SlowModeLimit,
+    };
+    use crate::stream::{message_responder_channel, SendStreamExt, SentClientMessage};
+    #[cfg(feature = "metrics")]
+    use crate::util::test_metrics;
+    use crate::util::{test_logger, InternalSender};
+    use crate::{
+        Capability, ClientMessage, Error, MessageResponse, MessageSendError, ReadyPredicate,
+    };
+
+    #[tokio::test]
+    async fn test_connection_label_in_logs() {
+        test_logger::init();
+        let server = MockServer::bind().await;
+        let url = server.url.clone();
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .connection_label("single-label".to_string())
+                .build()
+                .unwrap(),
+        );
+        server.accept_all();
+
+        let _client = connect(&cfg).await.unwrap();
+
+        let lines = test_logger::lines();
+        // with the tracing feature, structured fields are appended to the log record
+        let connecting = format!("[single-label-0] Connecting to {}", url);
+        assert!(lines.iter().any(|line| line.starts_with(&connecting)));
+        assert!(lines.contains(&"[single-label-0] Connection established.".to_string()));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics() {
+        test_metrics::init();
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (received_tx, received_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            connection
+                .send(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #metrics :hi")
+                .await;
+            let mut received = vec![];
+            while received.len() < 2 {
+                received.push(connection.recv_line().await.unwrap());
+            }
+            received_tx.send(received).unwrap();
+            // keep the connection open until the client reconnects on its own, closing it would
+            // end the connection for good
+            tokio::spawn(async move { while connection.recv_line().await.is_some() {} });
+            server.accept_all();
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        // the second message is delayed by the global slow mode
+        sender.send(("#metrics", "one")).await.unwrap();
+        sender.send(("#metrics", "two")).await.unwrap();
+        timeout(Duration::from_secs(5), received_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        client.reconnect();
+        timeout(Duration::from_secs(5), async {
+            while test_metrics::values("tmi.reconnects").is_empty() {
+                delay_for(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("reconnect is recorded");
+
+        assert!(
+            test_metrics::values("tmi.messages_sent")
+                .iter()
+                .sum::<u64>()
+                >= 2
+        );
+        assert!(!test_metrics::values("tmi.events_received").is_empty());
+        assert!(
+            !test_metrics::labeled_values("tmi.rate_limit.delayed", ("reason", "slow_mode"))
+                .is_empty()
+        );
+        assert!(test_metrics::values("tmi.rate_limit.delay")
+            .iter()
+            .any(|&nanos| nanos > 0));
+    }
+
+    #[tokio::test]
+    async fn test_custom_capability() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .custom_capabilities(vec!["example.com/custom"])
+                .build()
+                .unwrap(),
+        );
+        let (cap_tx, cap_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            let cap_request = connection.recv_line().await.unwrap();
+            cap_tx.send(cap_request).unwrap();
+            connection.accept_login().await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let _client = connect(&cfg).await.unwrap();
+        assert_eq!(
+            cap_rx.await.unwrap(),
+            "CAP REQ :twitch.tv/commands twitch.tv/tags example.com/custom"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_joined_channels_during_reconnect() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        server.accept_all();
+
+        let client = connect(&cfg).await.unwrap();
+        let sends = (0..20).map(|i| {
+            let mut sender = client.sender_cloned();
+            let channel = format!("#channel{}", i);
+            async move {
+                sender.send(ClientMessage::join(&*channel)).await.unwrap();
+                if i % 2 == 0 {
+                    sender.send(ClientMessage::Part(channel)).await.unwrap();
+                }
+            }
+        });
+        let sends = futures::future::join_all(sends);
+        client.reconnect();
+        timeout(Duration::from_secs(5), sends).await.unwrap();
+
+        let mut joined_channels = client.context.joined_channels.read().clone();
+        joined_channels.sort();
+        let mut expected = (0..20)
+            .filter(|i| i % 2 == 1)
+            .map(|i| format!("#channel{}", i))
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(joined_channels, expected);
+    }
+
+    #[tokio::test]
+    async fn test_join_all_channel_limit() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().channel_limit(2).build().unwrap());
+        server.accept_all();
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        sender
+            .send(ClientMessage::join_all(vec!["#a", "#b", "#a"]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            sender.send(ClientMessage::join_all(vec!["#b", "#c"])).await,
+            Err(MessageSendError::ChannelLimitReached(2))
+        ));
+        assert_eq!(*client.context.joined_channels.read(), vec!["#a", "#b"]);
+    }
+
+    #[tokio::test]
+    async fn test_cap_status() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .custom_capabilities(vec!["example.com/custom"])
+                .build()
+                .unwrap(),
+        );
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            while let Some(line) = connection.recv_line().await {
+                if line.lines().any(|line| line.starts_with("NICK ")) {
+                    break;
+                }
+            }
+            connection
+                .send(
+                    ":tmi.twitch.tv CAP * ACK :twitch.tv/commands twitch.tv/tags\r\n\
+                     :tmi.twitch.tv CAP * NAK :example.com/custom",
+                )
+                .await;
+            connection.send_welcome().await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        assert_eq!(client.cap_status(Capability::Commands), CapStatus::Granted);
+        assert_eq!(client.cap_status(Capability::Tags), CapStatus::Granted);
+        assert_eq!(
+            client.cap_status(Capability::Custom("example.com/custom")),
+            CapStatus::Denied
+        );
+        assert_eq!(
+            client.cap_status(Capability::Membership),
+            CapStatus::NotRequested
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reconnect() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (rejoin_tx, rejoin_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            tokio::spawn(async move { while connection.recv_line().await.is_some() {} });
+
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                if line.starts_with("JOIN ") {
+                    rejoin_tx.send(line).unwrap();
+                    break;
+                }
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        client
+            .sender_mut()
+            .send(ClientMessage::join("#channel"))
+            .await
+            .unwrap();
+        client.reconnect();
+        assert_eq!(
+            timeout(Duration::from_secs(5), rejoin_rx)
+                .await
+                .unwrap()
+                .unwrap(),
+            "JOIN #channel"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejoin_over_join_bucket() {
+        let mut server = MockServer::bind().await;
+        let mut rate_limiter = RateLimiterConfig::default();
+        rate_limiter.buckets.insert(
+            "join",
+            RateLimitBucketConfig::new(2, Duration::from_millis(200)),
+        );
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .rate_limiter(rate_limiter)
+                .build()
+                .unwrap(),
+        );
+        let (rejoin_tx, rejoin_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            tokio::spawn(async move { while connection.recv_line().await.is_some() {} });
+
+            // nothing is received after logging in, only the bucket refills can let the
+            // held back rejoins through
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            let mut joins = vec![];
+            while let Some(line) = connection.recv_line().await {
+                if line.starts_with("JOIN ") {
+                    joins.push(line);
+                    if joins.len() == 3 {
+                        break;
+                    }
+                }
+            }
+            rejoin_tx.send(joins).unwrap();
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        client
+            .sender_mut()
+            .send(ClientMessage::join_all(vec!["#a", "#b", "#c"]))
+            .await
+            .unwrap();
+        client.reconnect();
+        assert_eq!(
+            timeout(Duration::from_secs(5), rejoin_rx)
+                .await
+                .expect("rejoins all channels")
+                .unwrap(),
+            vec!["JOIN #a", "JOIN #b", "JOIN #c"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_close() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        server.accept_all();
+
+        let mut client = connect(&cfg).await.unwrap();
+        timeout(Duration::from_secs(5), client.close())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            *client.context.connected_state.borrow(),
+            ConnectedState::Closed
+        );
+        assert!(matches!(
+            client.sender_mut().send(ClientMessage::Ping).await,
+            Err(MessageSendError::Closed(ClientMessage::Ping))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_connection_events() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        server.accept_all();
+
+        let client = connect(&cfg).await.unwrap();
+        client.reconnect();
+        let events: Vec<_> = client
+            .stream
+            .filter_map(|event| async move {
+                match event {
+                    Ok(event @ Event::Reconnecting(_)) | Ok(event @ Event::Connected(_)) => {
+                        Some(event)
+                    }
+                    _ => None,
+                }
+            })
+            .take(3)
+            .collect()
+            .await;
+        assert_eq!(
+            events,
+            vec![
+                Event::Connected(ConnectedEvent),
+                Event::Reconnecting(ReconnectingEvent { attempt: 1 }),
+                Event::Connected(ConnectedEvent),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconnect_stability() {
+        let mut reconnects = ReconnectCounter::new(Duration::from_secs(60));
+        // connections that drop quickly keep counting
+        for expected in 1..=3 {
+            reconnects.attempts += 1;
+            reconnects.connection_lost(Duration::from_secs(5));
+            assert_eq!(reconnects.attempts, expected);
+        }
+        // a connection that stayed up long enough resets the count
+        reconnects.attempts += 1;
+        reconnects.connection_lost(Duration::from_secs(60));
+        assert_eq!(reconnects.attempts, 0);
+    }
+
+    #[test]
+    fn test_reconnect_limit() {
+        let mut reconnects = ReconnectCounter::new(Duration::from_secs(60));
+        reconnects.attempts = 20;
+        assert!(!reconnects.may_retry(Some(20)));
+        assert!(reconnects.may_retry(Some(21)));
+        assert!(reconnects.may_retry(None));
+    }
+
+    #[tokio::test]
+    async fn test_infinite_reconnects() {
+        test_logger::init();
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .connection_label("infinite".to_string())
+                .max_reconnects(None)
+                .reconnect_base_delay(Duration::from_millis(0))
+                .build()
+                .unwrap(),
+        );
+        let accept = tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            // the listener is dropped here, so every further attempt fails to connect
+            connection
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let _connection = accept.await.unwrap();
+        client.reconnect();
+
+        let count_lines = |needle: &str| {
+            test_logger::lines()
+                .iter()
+                .filter(|line| line.starts_with("[infinite-0]") && line.contains(needle))
+                .count()
+        };
+        timeout(Duration::from_secs(5), async {
+            while count_lines("Reconnecting in") <= 20 {
+                delay_for(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("keeps reconnecting past the default limit");
+        assert_eq!(count_lines("Maximum number of reconnect attempts"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_with_dropping_connection() {
+        test_logger::init();
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .connection_label("dropping".to_string())
+                .max_reconnects(Some(4))
+                .reconnect_base_delay(Duration::from_millis(100))
+                .reconnect_jitter(false)
+                .build()
+                .unwrap(),
+        );
+        let (connected_tx, connected_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            connected_rx.await.unwrap();
+            connection.reset();
+            // every further connection drops right after logging in
+            loop {
+                let mut connection = server.next_connection().await;
+                connection.accept_login().await;
+                connection.reset();
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let mut state = client.context.connected_state.clone();
+        tokio::spawn(client.stream.for_each(|_| async {}));
+        connected_tx.send(()).unwrap();
+        timeout(Duration::from_secs(5), async {
+            while state.recv().await != Some(ConnectedState::Closed) {}
+        })
+        .await
+        .expect("gives up after the maximum number of attempts");
+
+        // with the tracing feature, structured fields are appended to the log record
+        let delays = test_logger::lines()
+            .into_iter()
+            .filter(|line| line.starts_with("[dropping-0] Reconnecting in"))
+            .map(|line| line.split(" label=").next().unwrap().to_string())
+            .collect::<Vec<_>>();
+        // becoming active again doesn't reset the backoff, only a stable connection does
+        assert_eq!(
+            delays,
+            vec![
+                "[dropping-0] Reconnecting in 0.1 seconds...",
+                "[dropping-0] Reconnecting in 0.2 seconds...",
+                "[dropping-0] Reconnecting in 0.4 seconds...",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_timeout() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .ready_timeout(Duration::from_millis(200))
+                .build()
+                .unwrap(),
+        );
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            // welcome without the end of the MOTD
+            connection
+                .send(":tmi.twitch.tv 001 tester :Welcome, GLHF!")
+                .await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        match timeout(Duration::from_secs(5), connect(&cfg))
+            .await
+            .unwrap()
+        {
+            Err(Error::ReadyTimeout(duration)) => {
+                assert_eq!(duration, Duration::from_millis(200))
+            }
+            _ => panic!("expected the ready timeout to fire"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closed_before_ready() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .max_reconnects(Some(2))
+                .reconnect_base_delay(Duration::from_millis(0))
+                .build()
+                .unwrap(),
+        );
+        // every connection drops before the welcome message
+        tokio::spawn(async move {
+            loop {
+                let mut connection = server.next_connection().await;
+                connection.recv_line().await;
+                connection.reset();
+            }
+        });
+
+        assert!(matches!(
+            timeout(Duration::from_secs(5), connect(&cfg))
+                .await
+                .expect("connect returns once reconnecting is given up"),
+            Err(Error::ConnectionClosed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_ready_predicate() {
+        let predicates: Vec<(ReadyPredicate, &'static str)> = vec![
+            (
+                Arc::new(|event: &Event| match event {
+                    Event::ConnectMessage(msg) => msg.command() == "001",
+                    _ => false,
+                }),
+                ":tmi.twitch.tv 001 tester :Welcome, GLHF!",
+            ),
+            // events with their own internal handling can signal readiness as well
+            (
+                Arc::new(|event: &Event| matches!(event, Event::GlobalUserState(_))),
+                "@badge-info=;badges=;color=;display-name=tester;emote-sets=0;user-id=1;\
+                 user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+            ),
+        ];
+        for (ready_predicate, line) in predicates {
+            let mut server = MockServer::bind().await;
+            let cfg = Arc::new(
+                server
+                    .config_builder()
+                    .ready_predicate(ready_predicate)
+                    .ready_timeout(Duration::from_secs(5))
+                    .build()
+                    .unwrap(),
+            );
+            // the server never sends RPL_ENDOFMOTD
+            tokio::spawn(async move {
+                let mut connection = server.next_connection().await;
+                connection.send(line).await;
+                while connection.recv_line().await.is_some() {}
+            });
+
+            let client = connect(&cfg).await.unwrap();
+            assert_eq!(
+                *client.context.connected_state.borrow(),
+                ConnectedState::Active
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_nowait() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (line_tx, line_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            line_tx.send(connection.recv_line().await.unwrap()).unwrap();
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let mut client = connect_nowait(&cfg);
+        assert_ne!(
+            *client.context.connected_state.borrow(),
+            ConnectedState::Active
+        );
+        client
+            .sender_mut()
+            .send(ClientMessage::join("#channel"))
+            .await
+            .unwrap();
+        // the join is only sent after logging in
+        assert_eq!(line_rx.await.unwrap(), "JOIN #channel");
+    }
+
+    #[tokio::test]
+    async fn test_connect_nowait_closed_before_active() {
+        pause();
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .max_reconnects(Some(2))
+                .reconnect_base_delay(Duration::from_secs(1))
+                .build()
+                .unwrap(),
+        );
+        // every connection drops before the welcome message
+        tokio::spawn(async move {
+            loop {
+                let mut connection = server.next_connection().await;
+                connection.recv_line().await;
+                connection.reset();
+            }
+        });
+
+        let mut client = connect_nowait(&cfg);
+        assert!(matches!(
+            client.sender_mut().send(ClientMessage::join("#channel")).await,
+            Err(MessageSendError::Closed(ClientMessage::Join(channel))) if channel == "#channel"
+        ));
+        assert_eq!(
+            *client.context.connected_state.borrow(),
+            ConnectedState::Closed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_join_and_wait() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                match line.as_str() {
+                    "JOIN #joinable" => {
+                        connection
+                            .send(
+                                ":tester!tester@tester.tmi.twitch.tv JOIN #joinable\r\n\
+                                 :tester.tmi.twitch.tv 353 tester = #joinable :tester\r\n\
+                                 :tester.tmi.twitch.tv 366 tester #joinable :End of /NAMES list",
+                            )
+                            .await
+                    }
+                    "JOIN #suspended" => {
+                        connection
+                            .send(
+                                "@msg-id=msg_channel_suspended :tmi.twitch.tv NOTICE #suspended \
+                                 :This channel has been suspended.",
+                            )
+                            .await
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        assert_eq!(
+            sender
+                .join_and_wait("#joinable", Duration::from_secs(5))
+                .await
+                .unwrap(),
+            MessageResponse::Ok
+        );
+        match sender
+            .join_and_wait("#suspended", Duration::from_secs(5))
+            .await
+        {
+            Err(MessageSendError::JoinFailed { channel, msg_id }) => {
+                assert_eq!(channel, "#suspended");
+                assert_eq!(msg_id, "msg_channel_suspended");
+            }
+            other => panic!("expected join failure, got {:?}", other),
+        }
+        assert!(matches!(
+            sender
+                .join_and_wait("#unanswered", Duration::from_millis(100))
+                .await,
+            Err(MessageSendError::ResponseTimeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slow_and_wait() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                if line == "PRIVMSG #channel :/slow 30" {
+                    connection
+                        .send(
+                            "@room-id=12345;slow=10 :tmi.twitch.tv ROOMSTATE #other\r\n\
+                             @room-id=12345;slow=30 :tmi.twitch.tv ROOMSTATE #channel",
+                        )
+                        .await
+                }
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        assert_eq!(
+            sender
+                .slow_and_wait("#channel", 30, Duration::from_secs(5))
+                .await
+                .unwrap(),
+            MessageResponse::Ok
+        );
+        assert!(matches!(
+            sender
+                .slow_off_and_wait("#channel", Duration::from_millis(100))
+                .await,
+            Err(MessageSendError::ResponseTimeout)
+        ));
+    }
+
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
+    #[tokio::test]
+    async fn test_slow_and_wait_timeout() {
+        use crate::MessageSender;
+        use tokio::time::Instant;
+
+        pause();
+        let (tx, mut rx) = mpsc::channel::<SentClientMessage>(1);
+        tokio::spawn(async move {
+            while let Some(sent) = rx.recv().await {
+                assert_eq!(sent.message, ClientMessage::slow_off("#channel"));
+                sent.responder.send(Ok(MessageResponse::Ok)).ok();
+            }
+        });
+
+        // nothing confirms the change, so it fails after exactly the given duration
+        let mut sender = MessageSender::from(tx);
+        let start = Instant::now();
+        assert!(matches!(
+            sender
+                .slow_off_and_wait("#channel", Duration::from_secs(5))
+                .await,
+            Err(MessageSendError::ResponseTimeout)
+        ));
+        // the timer rounds the deadline up to the next millisecond
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(5));
+        assert!(elapsed < Duration::from_millis(5001));
+    }
+
+    #[tokio::test]
+    async fn test_clear_and_wait() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                if line == "PRIVMSG #channel :/clear" {
+                    // a timeout of a single user doesn't confirm the clear
+                    connection
+                        .send(
+                            "@room-id=12345 :tmi.twitch.tv CLEARCHAT #channel :ronni\r\n\
+                             @room-id=54321 :tmi.twitch.tv CLEARCHAT #other\r\n\
+                             @room-id=12345 :tmi.twitch.tv CLEARCHAT #channel",
+                        )
+                        .await
+                }
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        assert_eq!(
+            sender
+                .clear_and_wait("#channel", Duration::from_secs(5))
+                .await
+                .unwrap(),
+            MessageResponse::Ok
+        );
+        assert!(matches!(
+            sender
+                .clear_and_wait("#unanswered", Duration::from_millis(100))
+                .await,
+            Err(MessageSendError::ResponseTimeout)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_emote_only_guard() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (sent_tx, mut sent_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                match line.as_str() {
+                    "JOIN #channel" => {
+                        connection
+                            .send("@emote-only=1;room-id=12345 :tmi.twitch.tv ROOMSTATE #channel")
+                            .await
+                    }
+                    "PRIVMSG #channel :/emoteonlyoff" => {
+                        connection
+                            .send("@emote-only=0;room-id=12345 :tmi.twitch.tv ROOMSTATE #channel")
+                            .await
+                    }
+                    "JOIN #other" => {
+                        connection
+                            .send(
+                                ":tester!tester@tester.tmi.twitch.tv JOIN #other\r\n\
+                                 @emote-only=1;room-id=23456 :tmi.twitch.tv ROOMSTATE #other",
+                            )
+                            .await
+                    }
+                    "PART #other" => {
+                        connection
+                            .send(":tester!tester@tester.tmi.twitch.tv PART #other")
+                            .await
+                    }
+                    _ => {}
+                }
+                sent_tx.send(line).unwrap();
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, mut stream) = (client.sender, client.stream);
+        sender.guard_emote_only(true);
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::RoomState(_)))) {}
+
+        assert!(matches!(
+            sender.send(("#channel", "plain text")).await,
+            Err(MessageSendError::EmoteOnly(channel)) if channel == "#channel"
+        ));
+        // commands are not affected
+        sender.send(("#channel", "/emoteonlyoff")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::RoomState(_)))) {}
+        sender.send(("#channel", "plain text")).await.unwrap();
+
+        // leaving a channel forgets its mode
+        sender.send(ClientMessage::join("#other")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::RoomState(_)))) {}
+        assert!(sender.send(("#other", "plain text")).await.is_err());
+        sender
+            .send(ClientMessage::Part("#other".to_string()))
+            .await
+            .unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::Part(_)))) {}
+        assert!(client.context.emote_only.read().is_empty());
+
+        let mut sent = vec![];
+        while sent.len() < 3 {
+            sent.push(sent_rx.recv().await.unwrap());
+        }
+        assert_eq!(
+            sent,
+            vec![
+                "JOIN #channel",
+                "PRIVMSG #channel :/emoteonlyoff",
+                "PRIVMSG #channel :plain text"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_roomstate_slow_mode() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .rate_limiter(RateLimiterConfig {
+                    default_slow: SlowModeLimit::Channel(2),
+                    ..RateLimiterConfig::default()
+                })
+                .build()
+                .unwrap(),
+        );
+        let (mut next_tx, mut next_rx) = mpsc::channel::<&'static str>(1);
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while connection.recv_line().await.as_deref() != Some("JOIN #channel") {}
+            connection
+                .send(
+                    ":tester!tester@tester.tmi.twitch.tv JOIN #channel\r\n\
+                     @badges=;mod=0 :tmi.twitch.tv USERSTATE #channel",
+                )
+                .await;
+            while let Some(line) = next_rx.recv().await {
+                connection.send(line).await;
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, mut stream) = (client.sender, client.stream);
+        let rate_limiter = client.context.rate_limiter.clone();
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::UserState(_)))) {}
+
+        for (line, slow_mode) in &[
+            (
+                "@emote-only=0;followers-only=-1;r9k=0;room-id=1;slow=30;subs-only=0 \
+                 :tmi.twitch.tv ROOMSTATE #channel",
+                SlowModeLimit::Channel(30),
+            ),
+            // partial update without the slow tag
+            (
+                "@room-id=1;subs-only=1 :tmi.twitch.tv ROOMSTATE #channel",
+                SlowModeLimit::Channel(30),
+            ),
+            (
+                "@room-id=1;slow=0 :tmi.twitch.tv ROOMSTATE #channel",
+                SlowModeLimit::Channel(2),
+            ),
+        ] {
+            next_tx.send(line).await.unwrap();
+            while !matches!(stream.next().await, Some(Ok(Event::RoomState(_)))) {}
+            assert_eq!(rate_limiter.slow_mode("#channel"), Some(*slow_mode));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_rejected() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while connection.recv_line().await.as_deref() != Some("PRIVMSG #channel :hi") {}
+            connection
+                .send(
+                    "@msg-id=msg_ratelimit :tmi.twitch.tv NOTICE #channel \
+                     :Your message was not sent because you are sending messages too quickly.\r\n\
+                     @msg-id=slow_on :tmi.twitch.tv NOTICE #channel \
+                     :This room is now in slow mode. You may send messages every 30 seconds.",
+                )
+                .await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, stream) = (client.sender, client.stream);
+        sender.send(("#channel", "hi")).await.unwrap();
+
+        let events = stream
+            .filter_map(|event| async move {
+                match event {
+                    Ok(event @ Event::Notice(_)) | Ok(event @ Event::MessageRejected(_)) => {
+                        Some(event)
+                    }
+                    _ => None,
+                }
+            })
+            .take(3)
+            .collect::<Vec<_>>()
+            .await;
+        assert!(matches!(&events[0], Event::Notice(_)));
+        assert_eq!(
+            events[1],
+            Event::MessageRejected(MessageRejectedEvent::new(
+                "#channel".to_string(),
+                NoticeId::MsgRatelimit
+            ))
+        );
+        assert!(matches!(&events[2], Event::Notice(_)));
+    }
+
+    #[tokio::test]
+    async fn test_own_badges() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                match line.as_str() {
+                    "JOIN #channel" => {
+                        connection
+                            .send(
+                                ":tester!tester@tester.tmi.twitch.tv JOIN #channel\r\n\
+                                 @badges=moderator/1,vip/1;mod=1 :tmi.twitch.tv USERSTATE #channel",
+                            )
+                            .await
+                    }
+                    "PART #channel" => {
+                        connection
+                            .send(":tester!tester@tester.tmi.twitch.tv PART #channel")
+                            .await
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, mut stream) = (client.sender, client.stream);
+        let context = client.context;
+        assert_eq!(context.own_badges("#channel"), None);
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::UserState(_)))) {}
+        let badge = |badge: &str| Badge {
+            badge: badge.to_string(),
+            version: "1".to_string(),
+        };
+        assert_eq!(
+            context.own_badges("#channel"),
+            Some(vec![badge("moderator"), badge("vip")])
+        );
+
+        sender
+            .send(ClientMessage::Part("#channel".to_string()))
+            .await
+            .unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::Part(_)))) {}
+        assert_eq!(context.own_badges("#channel"), None);
+    }
+
+    #[tokio::test]
+    async fn test_part_forgets_channel() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                match line.as_str() {
+                    "JOIN #channel" => {
+                        connection
+                            .send(
+                                ":tester!tester@tester.tmi.twitch.tv JOIN #channel\r\n\
+                                 @badges=;mod=0 :tmi.twitch.tv USERSTATE #channel",
+                            )
+                            .await
+                    }
+                    "PART #channel" => {
+                        connection
+                            .send(":tester!tester@tester.tmi.twitch.tv PART #channel")
+                            .await
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, mut stream) = (client.sender, client.stream);
+        let context = client.context;
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::UserState(_)))) {}
+        assert!(context.rate_limiter.tracks_channel("#channel"));
+
+        sender
+            .send(ClientMessage::Part("#channel".to_string()))
+            .await
+            .unwrap();
+        while !matches!(stream.next().await, Some(Ok(Event::Part(_)))) {}
+        assert!(!context.rate_limiter.tracks_channel("#channel"));
+    }
+
+    #[tokio::test]
+    async fn test_sent_observer() {
+        let mut server = MockServer::bind().await;
+        let observed = Arc::new(parking_lot::Mutex::new(vec![]));
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .sent_observer({
+                    let observed = observed.clone();
+                    Arc::new(move |message: &ClientMessage| observed.lock().push(message.clone()))
+                })
+                .build()
+                .unwrap(),
+        );
+        let (lines_tx, mut lines_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                lines_tx.send(line).unwrap();
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        observed.lock().clear();
+        let sender = client.sender_mut();
+        sender.send(ClientMessage::join("#channel")).await.unwrap();
+        for text in &["first", "second", "third"] {
+            sender.send(("#channel", *text)).await.unwrap();
+        }
+
+        let mut lines = vec![];
+        while lines.len() < 4 {
+            lines.push(lines_rx.recv().await.unwrap());
+        }
+        assert_eq!(
+            *observed.lock(),
+            vec![
+                ClientMessage::join("#channel"),
+                ClientMessage::message("#channel", "first"),
+                ClientMessage::message("#channel", "second"),
+                ClientMessage::message("#channel", "third"),
+            ]
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "JOIN #channel",
+                "PRIVMSG #channel :first",
+                "PRIVMSG #channel :second",
+                "PRIVMSG #channel :third"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sent_observer_split() {
+        let mut server = MockServer::bind().await;
+        let observed = Arc::new(parking_lot::Mutex::new(vec![]));
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .send_middleware(Arc::new(|stream| Box::new(stream.split_oversize(5))))
+                .sent_observer({
+                    let observed = observed.clone();
+                    Arc::new(move |message: &ClientMessage| observed.lock().push(message.clone()))
+                })
+                .build()
+                .unwrap(),
+        );
+        let (lines_tx, mut lines_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                lines_tx.send(line).unwrap();
+            }
+        });
+
+        let mut client = connect(&cfg).await.unwrap();
+        observed.lock().clear();
+        let sender = client.sender_mut();
+        sender.send(("#channel", "abcdefgh")).await.unwrap();
+        sender.send(("#channel", "ij")).await.unwrap();
+
+        let mut lines = vec![];
+        while lines.len() < 3 {
+            lines.push(lines_rx.recv().await.unwrap());
+        }
+        // the chunks are rate limited one by one and observed as they are written
+        assert_eq!(
+            *observed.lock(),
+            vec![
+                ClientMessage::message("#channel", "abcde"),
+                ClientMessage::message("#channel", "fgh"),
+                ClientMessage::message("#channel", "ij"),
+            ]
+        );
+        assert_eq!(
+            lines,
+            vec![
+                "PRIVMSG #channel :abcde",
+                "PRIVMSG #channel :fgh",
+                "PRIVMSG #channel :ij"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_send_middleware_skips_internal_messages() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .send_middleware(Arc::new(|stream| {
+                    // drop every message, answering it as if it was sent
+                    Box::new(stream.filter_map(|message: SentClientMessage| {
+                        message.responder.send(Ok(MessageResponse::Ok)).ok();
+                        future::ready(None)
+                    }))
+                }))
+                .build()
+                .unwrap(),
+        );
+        let (lines_tx, mut lines_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            connection.send("PING :tmi.twitch.tv").await;
+            while let Some(line) = connection.recv_line().await {
+                lines_tx.send(line).unwrap();
+            }
+        });
+
+        // the login isn't filtered, so the client still connects and answers pings
+        let mut client = connect(&cfg).await.unwrap();
+        assert_eq!(lines_rx.recv().await.unwrap(), "PONG :tmi.twitch.tv");
+        // user messages are
+        client
+            .sender_mut()
+            .send(("#channel", "filtered"))
+            .await
+            .unwrap();
+        assert!(timeout(Duration::from_millis(100), lines_rx.recv())
+            .await
+            .is_err());
+    }
+
+    /// Sink that fails every write like a broken connection
+    struct FailingSink;
+
+    impl Sink<Message> for FailingSink {
+        type Error = WsError;
+
+        fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn start_send(self: Pin<&mut Self>, _item: Message) -> Result<(), WsError> {
+            Err(WsError::AlreadyClosed)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_send_error_reconnects() {
+        let (responder, response) = message_responder_channel();
+        let batch = MessageBatch {
+            messages: vec![ClientMessage::message("#channel", "hi")],
+            responders: vec![responder],
+            close: false,
+        };
+        let result = batch.send(&mut FailingSink, None).await;
+        assert!(matches!(result, Err(Error::WebsocketError(_))));
+        assert!(matches!(
+            response.await.unwrap(),
+            Err(MessageSendError::Closed(ClientMessage::PrivMsg { .. }))
+        ));
+
+        // a failed write reconnects, only a requested close ends the connection
+        assert_eq!(
+            connection_future_ended(result, "test"),
+            Some(DisconnectReason::IoError)
+        );
+        assert_eq!(
+            connection_future_ended(Ok(true), "test"),
+            Some(DisconnectReason::Closed)
+        );
+        assert_eq!(connection_future_ended(Ok(false), "test"), None);
+    }
+
+    #[tokio::test]
+    async fn test_write_error_reconnects() {
+        test_logger::init();
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .connection_label("write-error".to_string())
+                .reconnect_base_delay(Duration::from_millis(0))
+                .build()
+                .unwrap(),
+        );
+        let (second_tx, second_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut first = server.next_connection().await;
+            first.accept_login().await;
+            first.send_close().await;
+            let mut second = server.next_connection().await;
+            second.accept_login().await;
+            second_tx.send(()).unwrap();
+            // keep the first connection open, the client only notices the failed write
+            while second.recv_line().await.is_some() {}
+            drop(first);
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let (mut sender, mut stream) = (client.sender, client.stream);
+        while !matches!(stream.next().await, Some(Ok(Event::Close(_)))) {}
+        assert!(matches!(
+            sender.send(("#channel", "hi")).await,
+            Err(MessageSendError::Closed(_))
+        ));
+        while !matches!(stream.next().await, Some(Ok(Event::Reconnecting(_)))) {}
+        timeout(Duration::from_secs(5), second_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(test_logger::lines()
+            .iter()
+            .any(|line| line.starts_with("[write-error-0] Error while sending, reconnecting")));
+    }
+
+    #[tokio::test]
+    async fn test_batch_window() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(
+            server
+                .config_builder()
+                .batch_window(Duration::from_millis(50))
+                .build()
+                .unwrap(),
+        );
+        let (frame_tx, frame_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            frame_tx
+                .send(connection.recv_line().await.unwrap())
+                .unwrap();
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let sender = client.sender;
+        let send_join = |channel: &str| {
+            let mut sender = sender.clone();
+            let message = ClientMessage::join(channel);
+            async move { sender.send(message).await }
+        };
+        let (first, second, third) = futures_util::future::join3(
+            send_join("#first"),
+            send_join("#second"),
+            send_join("#third"),
+        )
+        .await;
+        assert!(first.is_ok() && second.is_ok() && third.is_ok());
+
+        let frame = timeout(Duration::from_secs(5), frame_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        let mut lines = frame.split("\r\n").collect::<Vec<_>>();
+        lines.sort_unstable();
+        assert_eq!(lines, vec!["JOIN #first", "JOIN #second", "JOIN #third"]);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            connection
+                .send(
+                    "@badge-info=;badges=;color=#0D4200;display-name=Tester;emote-sets=0;\
+                     user-id=1337;user-type= :tmi.twitch.tv GLOBALUSERSTATE",
+                )
+                .await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let events = timeout(
+            Duration::from_secs(5),
+            client
+                .stream
+                .filter_map(|event| async move {
+                    match event {
+                        Ok(event @ Event::GlobalUserState(_))
+                        | Ok(event @ Event::Authenticated(_)) => Some(event),
+                        _ => None,
+                    }
+                })
+                .take(2)
+                .collect::<Vec<_>>(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(&events[0], Event::GlobalUserState(_)));
+        assert_eq!(
+            events[1],
+            Event::Authenticated(AuthenticatedEvent {
+                user_id: 1337,
+                login: "tester".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_channel_limit() {
+        let server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().channel_limit(2).build().unwrap());
+        server.accept_all();
+
+        let mut client = connect(&cfg).await.unwrap();
+        let sender = client.sender_mut();
+        sender.send(ClientMessage::join("#first")).await.unwrap();
+        sender.send(ClientMessage::join("#second")).await.unwrap();
+        assert!(matches!(
+            sender.send(ClientMessage::join("#third")).await,
+            Err(MessageSendError::ChannelLimitReached(2))
+        ));
+        // joining a channel again doesn't count towards the limit
+        sender.send(ClientMessage::join("#first")).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pause_sending() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (line_tx, mut lines) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            while let Some(line) = connection.recv_line().await {
+                line_tx.send(line).ok();
+            }
+        });
+
+        let client = connect(&cfg).await.unwrap();
+        let sender = client.sender_cloned();
+        sender.pause();
+        let sending = tokio::spawn({
+            let mut sender = sender.clone();
+            async move {
+                for message in &["first", "second", "third"] {
+                    sender.send(("#channel", *message)).await.unwrap();
+                }
+            }
+        });
+        delay_for(Duration::from_millis(100)).await;
+        assert!(lines.try_recv().is_err());
+        // other messages are still sent while paused
+        sender
+            .clone()
+            .send(ClientMessage::join("#other"))
+            .await
+            .unwrap();
+        assert_eq!(lines.recv().await.unwrap(), "JOIN #other");
+
+        sender.resume();
+        sending.await.unwrap();
+        for message in &["first", "second", "third"] {
+            assert_eq!(
+                lines.recv().await.unwrap(),
+                format!("PRIVMSG #channel :{}", message)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repeated_welcome() {
+        let mut server = MockServer::bind().await;
+        let cfg = Arc::new(server.config_builder().build().unwrap());
+        let (repeat_tx, repeat_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let mut connection = server.next_connection().await;
+            connection.accept_login().await;
+            repeat_rx.await.unwrap();
+            connection.send_welcome().await;
+            connection
+                .send(":tester!tester@tester.tmi.twitch.tv PRIVMSG #channel :after welcome")
+                .await;
+            while connection.recv_line().await.is_some() {}
+        });
+
+        let (event_sender, mut events) = mpsc::channel(20);
+        let (_sender, context) = connect_internal(
+            &cfg,
+            0,
+            Arc::new(RateLimiter::from(&cfg.rate_limiter)),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            InternalSender(event_sender),
+            true,
+        )
+        .await
+        .unwrap();
+
+        let mut state = context.connected_state.clone();
+        assert_eq!(
+            state.recv().now_or_never(),
+            Some(Some(ConnectedState::Active))
+        );
+        repeat_tx.send(()).unwrap();
+        while let Some(event) = events.recv().await {
+            if let Event::PrivMsg(_) = event.unwrap() {
+                break;
+            }
+        }
+        assert_eq!(state.recv().now_or_never(), None);
+    }
+
+    // runs on async-std's executor, the timeouts and delays are async-std timers as well
+    #[cfg(feature = "async-std")]
+    #[test]
+    fn test_async_std_runtime() {
+        use crate::event::MessageEventData;
+        use crate::runtime;
+
+        async_std::task::block_on(async {
+            let mut server = MockServer::bind().await;
+            let cfg = Arc::new(server.config_builder().build().unwrap());
+            let received = async_std::task::spawn(async move {
+                let mut connection = server.next_connection().await;
+                connection.accept_login().await;
+                while connection.recv_line().await.as_deref() != Some("JOIN #channel") {}
+                connection
+                    .send(
+                        ":tester!tester@tester.tmi.twitch.tv JOIN #channel\r\n\
+                         :someone!someone@someone.tmi.twitch.tv PRIVMSG #channel :hello",
+                    )
+                    .await;
+                connection.recv_line().await
+            });
+
+            let mut client = connect(&cfg).await.unwrap();
+            let mut sender = client.sender_cloned();
+            sender.send(ClientMessage::join("#channel")).await.unwrap();
+            let message = runtime::timeout(Duration::from_secs(5), async {
+                loop {
+                    if let Some(Ok(Event::PrivMsg(event))) = client.stream_mut().next().await {
+                        break event;
+                    }
+                }
+            })
+            .await
+            .unwrap();
+            assert_eq!(message.message(), "hello");
+
+            sender
+                .send(ClientMessage::message("#channel", "hi"))
+                .await
+                .unwrap();
+            assert_eq!(
+                runtime::timeout(Duration::from_secs(5), received)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some("PRIVMSG #channel :hi")
+            );
+
+            let start = runtime::Instant::now();
+            runtime::delay_for(Duration::from_millis(50)).await;
+            assert!(start.elapsed() >= Duration::from_millis(50));
+        });
+    }
 }
@@ -1,27 +1,54 @@
 //! Types to represent messages sent by the client
 
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
 use std::fmt;
+use std::str::FromStr;
 
 use smallvec::SmallVec;
 use tokio_tungstenite::tungstenite::Message;
 
-use crate::stream::rate_limits::RateLimitable;
-use crate::StringRef;
+use crate::irc::escape_tag_value;
+use crate::stream::rate_limits::{RateLimitable, JOIN_BUCKETS, WHISPER_BUCKETS};
+use crate::{InvalidChannelName, InvalidCommand, StringRef};
+
+/// Channel that commands which don't apply to a specific channel (whispers, color changes) are
+/// sent to
+pub const JTV_CHANNEL: &str = "#jtv";
 
 /// Messages to be sent from the client to twitch servers
 #[allow(missing_docs)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ClientMessage<T: StringRef = String> {
-    PrivMsg { channel: T, message: T },
-    Whisper { recipient: T, message: T },
+    PrivMsg {
+        channel: T,
+        message: T,
+    },
+    Reply {
+        channel: T,
+        parent_msg_id: T,
+        message: T,
+    },
+    Whisper {
+        recipient: T,
+        message: T,
+    },
+    /// A channel message sent with a command other than `PRIVMSG`. It is routed and rate limited
+    /// like a message to `channel`.
+    CustomPrivMsg {
+        command: T,
+        channel: T,
+        message: T,
+    },
     Join(T),
+    JoinAll(Vec<T>),
     Part(T),
+    PartAll(Vec<T>),
     Nick(T),
     Pass(T),
     CapRequest(SmallVec<[Capability; 3]>),
     Ping,
-    Pong,
+    /// Response to a PING, echoing its parameters
+    Pong(Vec<T>),
     Close,
 }
 
@@ -33,8 +60,48 @@ impl ClientMessage<String> {
     ) -> Self {
         ClientMessage::PrivMsg {
             channel: channel.into(),
-            message: message.into(),
+            message: sanitize(message.borrow()),
+        }
+    }
+
+    /// Send a channel message as a reply to the message with the ID `parent_msg_id`, which is
+    /// the `id` tag of the message
+    pub fn reply<
+        S1: Into<String> + Borrow<str>,
+        S2: Into<String> + Borrow<str>,
+        S3: Into<String> + Borrow<str>,
+    >(
+        channel: S1,
+        parent_msg_id: S2,
+        message: S3,
+    ) -> Self {
+        ClientMessage::Reply {
+            channel: channel.into(),
+            parent_msg_id: parent_msg_id.into(),
+            message: sanitize(message.borrow()),
+        }
+    }
+
+    /// Send a channel message with the given command in place of `PRIVMSG`, which is rendered
+    /// as `<command> <channel> :<message>`. Fails if the command isn't a single word of ASCII
+    /// letters, which would either be rejected by the server or change the meaning of the line.
+    pub fn with_command<
+        S1: Into<String> + Borrow<str>,
+        S2: Into<String> + Borrow<str>,
+        S3: Into<String> + Borrow<str>,
+    >(
+        command: S1,
+        channel: S2,
+        message: S3,
+    ) -> Result<Self, InvalidCommand> {
+        if !is_valid_command(command.borrow()) {
+            return Err(InvalidCommand(command.into()));
         }
+        Ok(ClientMessage::CustomPrivMsg {
+            command: command.into(),
+            channel: channel.into(),
+            message: sanitize(message.borrow()),
+        })
     }
 
     /// Joins a twitch channel
@@ -42,6 +109,23 @@ impl ClientMessage<String> {
         ClientMessage::Join(channel.into())
     }
 
+    /// Joins several channels with a single `JOIN #a,#b,#c` line. Keep IRC's line length limit
+    /// of 512 bytes in mind when joining a lot of channels at once. The rate limiter counts every
+    /// channel of the line as a join. Connection pools send one line to each connection the
+    /// channels are joined on. If one of the lines fails, pools leave the channels that were
+    /// joined by the other lines again, so either all of the channels end up joined or only the
+    /// ones that were joined before.
+    pub fn join_all<S: Into<String>>(channels: impl IntoIterator<Item = S>) -> Self {
+        ClientMessage::JoinAll(channels.into_iter().map(Into::into).collect())
+    }
+
+    /// Leaves several channels with a single `PART #a,#b,#c` line. Connection pools send one line
+    /// to each connection the channels are joined on. Channels whose line failed to send stay
+    /// joined.
+    pub fn part_all<S: Into<String>>(channels: impl IntoIterator<Item = S>) -> Self {
+        ClientMessage::PartAll(channels.into_iter().map(Into::into).collect())
+    }
+
     /// Authenticates the user. Caution: this is normally called automatically when calling
     /// [`TwitchClient::connect`](tmi_rs::TwitchClient::connect), only use it if this stream was created in some other
     /// way.
@@ -52,6 +136,22 @@ impl ClientMessage<String> {
         )
     }
 
+    /// Send an announcement, highlighted in the given color or the channel's accent color
+    pub fn announce<S1: Into<String> + Borrow<str>, S2: Into<String> + Borrow<str>>(
+        channel: S1,
+        color: Option<AnnounceColor>,
+        message: S2,
+    ) -> Self {
+        ClientMessage::PrivMsg {
+            channel: channel.into(),
+            message: Command::Announce {
+                color,
+                message: sanitize(message.borrow()),
+            }
+            .to_string(),
+        }
+    }
+
     /// Permanently ban a user
     pub fn ban<S: Into<String> + Borrow<str>>(channel: S, username: S) -> Self {
         ClientMessage::PrivMsg {
@@ -79,7 +179,7 @@ impl ClientMessage<String> {
     /// Set the user name color
     pub fn color<S: Into<String> + Borrow<str>>(color: S) -> Self {
         ClientMessage::PrivMsg {
-            channel: String::from("jtv"),
+            channel: String::from(JTV_CHANNEL),
             message: Command::Color(color.borrow()).to_string(),
         }
     }
@@ -106,7 +206,7 @@ impl ClientMessage<String> {
     /// Disconnect from chat
     pub fn disconnect<S: Into<String> + Borrow<str>>(&mut self) -> Self {
         ClientMessage::PrivMsg {
-            channel: String::from("jtv"),
+            channel: String::from(JTV_CHANNEL),
             message: Command::<&str>::Disconnect.to_string(),
         }
     }
@@ -119,11 +219,22 @@ impl ClientMessage<String> {
         }
     }
 
-    /// Set followers only mode on or off
-    pub fn followers_only<S: Into<String> + Borrow<str>>(channel: S, status: bool) -> Self {
+    /// Enable followers only mode, optionally with a minimum follow duration like `10m` or `1w`
+    pub fn followers_only<S: Into<String> + Borrow<str>>(
+        channel: S,
+        duration: Option<&str>,
+    ) -> Self {
+        ClientMessage::PrivMsg {
+            channel: channel.into(),
+            message: Command::FollowersOnly(duration).to_string(),
+        }
+    }
+
+    /// Disable followers only mode
+    pub fn followers_off<S: Into<String> + Borrow<str>>(channel: S) -> Self {
         ClientMessage::PrivMsg {
             channel: channel.into(),
-            message: Command::<&str>::FollowersOnly(status).to_string(),
+            message: Command::<&str>::FollowersOff.to_string(),
         }
     }
 
@@ -151,11 +262,19 @@ impl ClientMessage<String> {
         }
     }
 
-    /// Send a /me message
-    pub fn me<S: Into<String> + Borrow<str>>(message: S) -> Self {
+    /// Send an action (`/me`) message to a channel
+    pub fn me<S1: Into<String> + Borrow<str>, S2: Into<String> + Borrow<str>>(
+        channel: S1,
+        message: S2,
+    ) -> Self {
         ClientMessage::PrivMsg {
-            channel: String::from("jtv"),
-            message: Command::Me(message.borrow()).to_string(),
+            channel: channel.into(),
+            message: format!(
+                "{}{}{}",
+                ACTION_PREFIX,
+                sanitize(message.borrow()),
+                ACTION_SUFFIX
+            ),
         }
     }
 
@@ -175,6 +294,14 @@ impl ClientMessage<String> {
         }
     }
 
+    /// List the moderators in a channel
+    pub fn mods<S: Into<String> + Borrow<str>>(channel: S) -> Self {
+        ClientMessage::PrivMsg {
+            channel: channel.into(),
+            message: Command::<&str>::Mods.to_string(),
+        }
+    }
+
     /// Enable or disable r9k mode
     pub fn r9k<S: Into<String> + Borrow<str>>(channel: S, on: bool) -> Self {
         ClientMessage::PrivMsg {
@@ -199,6 +326,14 @@ impl ClientMessage<String> {
         }
     }
 
+    /// Give a shoutout to another streamer
+    pub fn shoutout<S: Into<String> + Borrow<str>>(channel: S, user: S) -> Self {
+        ClientMessage::PrivMsg {
+            channel: channel.into(),
+            message: Command::Shoutout(user.borrow()).to_string(),
+        }
+    }
+
     /// Enable slow mode with the given amount of seconds
     pub fn slow<S: Into<String> + Borrow<str>>(channel: S, seconds: usize) -> Self {
         ClientMessage::PrivMsg {
@@ -270,22 +405,251 @@ impl ClientMessage<String> {
     ) -> Self {
         ClientMessage::Whisper {
             recipient: user.into(),
-            message: message.into(),
+            message: sanitize(message.borrow()),
+        }
+    }
+}
+
+impl<T: StringRef> ClientMessage<T> {
+    /// The channel of channel messages, replies, joins and parts, if it is malformed. Valid
+    /// channel names start with `#` and contain no whitespace or commas. Joining an empty list of
+    /// channels reports an empty channel name.
+    pub fn invalid_channel(&self) -> Option<&str> {
+        match self {
+            ClientMessage::PrivMsg { channel, .. }
+            | ClientMessage::Reply { channel, .. }
+            | ClientMessage::CustomPrivMsg { channel, .. }
+            | ClientMessage::Join(channel)
+            | ClientMessage::Part(channel) => {
+                Some(channel.borrow()).filter(|channel| !is_valid_channel(channel))
+            }
+            ClientMessage::JoinAll(channels) | ClientMessage::PartAll(channels)
+                if channels.is_empty() =>
+            {
+                Some("")
+            }
+            ClientMessage::JoinAll(channels) | ClientMessage::PartAll(channels) => channels
+                .iter()
+                .map(|channel| channel.borrow())
+                .find(|channel| !is_valid_channel(channel)),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a channel message, action or whisper without any visible text. Twitch
+    /// rejects these.
+    pub fn has_empty_text(&self) -> bool {
+        match self {
+            ClientMessage::PrivMsg { message, .. }
+            | ClientMessage::Reply { message, .. }
+            | ClientMessage::CustomPrivMsg { message, .. }
+            | ClientMessage::Whisper { message, .. } => {
+                let message = message.borrow();
+                action_text(message).unwrap_or(message).trim().is_empty()
+            }
+            _ => false,
         }
     }
+
+    /// The channel of a channel message or reply that contains chat text rather than a command
+    /// like `/timeout`. Actions count as chat text, including ones written as `/me text`.
+    pub(crate) fn chat_text_channel(&self) -> Option<&str> {
+        match self {
+            ClientMessage::PrivMsg { channel, message }
+            | ClientMessage::Reply {
+                channel, message, ..
+            }
+            | ClientMessage::CustomPrivMsg {
+                channel, message, ..
+            } => {
+                let message = message.borrow();
+                let is_command = message.starts_with('/') || message.starts_with('.');
+                if is_command && !message[1..].starts_with("me ") {
+                    None
+                } else {
+                    Some(channel.borrow())
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<S1, S2> From<(S1, S2)> for ClientMessage<String>
+where
+    S1: Into<String> + Borrow<str>,
+    S2: Into<String> + Borrow<str>,
+{
+    /// Creates a channel message from a `(channel, message)` tuple
+    fn from((channel, message): (S1, S2)) -> Self {
+        ClientMessage::message(channel, message)
+    }
+}
+
+fn is_valid_command(command: &str) -> bool {
+    !command.is_empty() && command.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+fn is_valid_channel(channel: &str) -> bool {
+    channel.len() > 1
+        && channel.starts_with('#')
+        && !channel.contains(|c: char| c.is_whitespace() || c == ',')
+}
+
+/// A channel name in the form Twitch expects it: lowercase and starting with `#`. Parsing accepts
+/// names with or without the `#` in any case, so `"Dallas"` and `"#dallas"` result in the same
+/// channel. Can be passed to all `ClientMessage` constructors that take a channel.
+///
+/// ```
+/// use tmi_rs::Channel;
+///
+/// let channel: Channel = "Dallas".parse().unwrap();
+/// assert_eq!(channel.as_str(), "#dallas");
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Channel(String);
+
+impl Channel {
+    /// The channel name including the leading `#`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The channel name without the leading `#`, which is the login name of the broadcaster
+    pub fn login(&self) -> &str {
+        &self.0[1..]
+    }
+}
+
+impl FromStr for Channel {
+    type Err = InvalidChannelName;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let channel = format!("#{}", name.strip_prefix('#').unwrap_or(name).to_lowercase());
+        if is_valid_channel(&channel) && !channel[1..].contains('#') {
+            Ok(Channel(channel))
+        } else {
+            Err(InvalidChannelName(name.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Borrow<str> for Channel {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Channel {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Channel> for String {
+    fn from(channel: Channel) -> Self {
+        channel.0
+    }
+}
+
+const LINE_BREAKS: [char; 2] = ['\r', '\n'];
+
+/// Action messages are wrapped as CTCP ACTION: `\u{1}ACTION message\u{1}`
+pub(crate) const ACTION_PREFIX: &str = "\u{1}ACTION ";
+pub(crate) const ACTION_SUFFIX: &str = "\u{1}";
+const ANNOUNCE_COMMAND: &str = "/announce";
+
+/// Text of an action message without the CTCP ACTION wrapper, `None` if `message` isn't an action
+pub(crate) fn action_text(message: &str) -> Option<&str> {
+    message
+        .strip_prefix(ACTION_PREFIX)
+        .and_then(|text| text.strip_suffix(ACTION_SUFFIX))
+}
+
+/// The `/announce` command an announcement starts with, including its color suffix and the space
+/// before the text. `None` if `message` isn't an announcement.
+pub(crate) fn announce_prefix(message: &str) -> Option<&str> {
+    let color_len = message.strip_prefix(ANNOUNCE_COMMAND)?.find(' ')?;
+    let prefix = &message[..ANNOUNCE_COMMAND.len() + color_len + 1];
+    match prefix[ANNOUNCE_COMMAND.len()..].trim_end() {
+        "" | "blue" | "green" | "orange" | "purple" => Some(prefix),
+        _ => None,
+    }
+}
+
+/// Removes characters from user provided text that would break the IRC line it is sent in:
+/// line breaks are replaced by spaces, NUL characters are dropped.
+pub fn sanitize(message: &str) -> String {
+    message
+        .chars()
+        .filter(|c| *c != '\0')
+        .map(|c| if LINE_BREAKS.contains(&c) { ' ' } else { c })
+        .collect()
+}
+
+/// Replaces any line breaks left in a message parameter when it is written to the connection, so
+/// that a message can never be interpreted as more than one IRC command. NUL characters are kept
+/// because they are used to mark deduplicated messages.
+fn single_line(param: &str) -> Cow<'_, str> {
+    if param.contains(&LINE_BREAKS[..]) {
+        Cow::Owned(param.replace(&LINE_BREAKS[..], " "))
+    } else {
+        Cow::Borrowed(param)
+    }
 }
 
 impl<T: StringRef> Into<Message> for &ClientMessage<T> {
     fn into(self) -> Message {
         match self {
-            ClientMessage::PrivMsg { channel, message } => {
-                Message::Text(format!("PRIVMSG {} :{}", channel, message))
+            ClientMessage::PrivMsg { channel, message } => Message::Text(format!(
+                "PRIVMSG {} :{}",
+                single_line(channel.borrow()),
+                single_line(message.borrow())
+            )),
+            ClientMessage::Reply {
+                channel,
+                parent_msg_id,
+                message,
+            } => Message::Text(format!(
+                "@reply-parent-msg-id={} PRIVMSG {} :{}",
+                escape_tag_value(parent_msg_id.borrow()),
+                single_line(channel.borrow()),
+                single_line(message.borrow())
+            )),
+            ClientMessage::Whisper { recipient, message } => Message::Text(format!(
+                "PRIVMSG {} :/w {} {}",
+                JTV_CHANNEL,
+                single_line(recipient.borrow()),
+                single_line(message.borrow())
+            )),
+            ClientMessage::CustomPrivMsg {
+                command,
+                channel,
+                message,
+            } => Message::Text(format!(
+                "{} {} :{}",
+                single_line(command.borrow()),
+                single_line(channel.borrow()),
+                single_line(message.borrow())
+            )),
+            ClientMessage::Join(channel) => {
+                Message::Text(format!("JOIN {}", single_line(channel.borrow())))
+            }
+            ClientMessage::JoinAll(channels) => {
+                Message::Text(format!("JOIN {}", channel_list(channels)))
             }
-            ClientMessage::Whisper { recipient, message } => {
-                Message::Text(format!("PRIVMSG jtv :/w {} {}", recipient, message))
+            ClientMessage::Part(channel) => {
+                Message::Text(format!("PART {}", single_line(channel.borrow())))
+            }
+            ClientMessage::PartAll(channels) => {
+                Message::Text(format!("PART {}", channel_list(channels)))
             }
-            ClientMessage::Join(channel) => Message::Text(format!("JOIN {}", channel)),
-            ClientMessage::Part(channel) => Message::Text(format!("PART {}", channel)),
             ClientMessage::CapRequest(caps) => Message::Text(format!(
                 "CAP REQ :{}",
                 caps.iter()
@@ -296,12 +660,31 @@ impl<T: StringRef> Into<Message> for &ClientMessage<T> {
             ClientMessage::Nick(nick) => Message::Text(format!("NICK {}", nick)),
             ClientMessage::Pass(pass) => Message::Text(format!("PASS {}", pass)),
             ClientMessage::Ping => Message::Text("PING".to_string()),
-            ClientMessage::Pong => Message::Text("PONG".to_string()),
+            ClientMessage::Pong(params) => Message::Text(match params.split_last() {
+                Some((last, middle)) => format!(
+                    "PONG {}:{}",
+                    middle
+                        .iter()
+                        .map(|param| format!("{} ", single_line(param.borrow())))
+                        .collect::<String>(),
+                    single_line(last.borrow())
+                ),
+                None => "PONG".to_string(),
+            }),
             ClientMessage::Close => Message::Close(None),
         }
     }
 }
 
+/// Comma separated list of channels for JOIN and PART
+fn channel_list<T: StringRef>(channels: &[T]) -> String {
+    channels
+        .iter()
+        .map(|channel| single_line(channel.borrow()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
 impl<T: StringRef> Into<Message> for ClientMessage<T> {
     fn into(self) -> Message {
         (&self).into()
@@ -311,33 +694,76 @@ impl<T: StringRef> Into<Message> for ClientMessage<T> {
 impl<T: StringRef> RateLimitable for &ClientMessage<T> {
     fn channel_limits(&self) -> Option<&str> {
         match self {
-            ClientMessage::PrivMsg { channel, .. } => Some(channel.borrow()),
+            ClientMessage::PrivMsg { channel, .. }
+            | ClientMessage::Reply { channel, .. }
+            | ClientMessage::CustomPrivMsg { channel, .. } => Some(channel.borrow()),
             _ => None,
         }
     }
+
+    fn global_limits(&self) -> &'static [&'static str] {
+        match self {
+            ClientMessage::Whisper { .. } => WHISPER_BUCKETS,
+            ClientMessage::Join(_) | ClientMessage::JoinAll(_) => JOIN_BUCKETS,
+            _ => &[],
+        }
+    }
+
+    fn global_tokens(&self) -> usize {
+        match self {
+            ClientMessage::JoinAll(channels) => channels.len(),
+            _ => 1,
+        }
+    }
 }
 
 impl<T: StringRef> RateLimitable for ClientMessage<T> {
     fn channel_limits(&self) -> Option<&str> {
         match self {
-            ClientMessage::PrivMsg { channel, .. } => Some(channel.borrow()),
+            ClientMessage::PrivMsg { channel, .. }
+            | ClientMessage::Reply { channel, .. }
+            | ClientMessage::CustomPrivMsg { channel, .. } => Some(channel.borrow()),
             _ => None,
         }
     }
+
+    fn global_limits(&self) -> &'static [&'static str] {
+        match self {
+            ClientMessage::Whisper { .. } => WHISPER_BUCKETS,
+            ClientMessage::Join(_) | ClientMessage::JoinAll(_) => JOIN_BUCKETS,
+            _ => &[],
+        }
+    }
+
+    fn global_tokens(&self) -> usize {
+        match self {
+            ClientMessage::JoinAll(channels) => channels.len(),
+            _ => 1,
+        }
+    }
 }
 
 /// Available twitch chat commands (/timeout etc)
 #[allow(missing_docs)]
 pub enum Command<T: Borrow<str>> {
+    Announce {
+        color: Option<AnnounceColor>,
+        message: T,
+    },
     Ban(T),
     Unban(T),
     Clear,
     Color(T),
-    Commercial { time: Option<usize> },
-    Delete { msg_id: T },
+    Commercial {
+        time: Option<usize>,
+    },
+    Delete {
+        msg_id: T,
+    },
     Disconnect,
     EmoteOnly(bool),
-    FollowersOnly(bool),
+    FollowersOnly(Option<T>),
+    FollowersOff,
     Host(T),
     Unhost,
     Marker(Option<T>),
@@ -348,10 +774,14 @@ pub enum Command<T: Borrow<str>> {
     R9k(bool),
     Raid(T),
     Unraid,
+    Shoutout(T),
     Slow(usize),
     SlowOff,
     SubscribersOnly(bool),
-    Timeout { user: T, time: Option<usize> },
+    Timeout {
+        user: T,
+        time: Option<usize>,
+    },
     Vip(T),
     Vips,
 }
@@ -359,6 +789,16 @@ pub enum Command<T: Borrow<str>> {
 impl<T: StringRef> fmt::Display for Command<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self {
+            Command::Announce { color, message } => {
+                let suffix = match color {
+                    None => "",
+                    Some(AnnounceColor::Blue) => "blue",
+                    Some(AnnounceColor::Green) => "green",
+                    Some(AnnounceColor::Orange) => "orange",
+                    Some(AnnounceColor::Purple) => "purple",
+                };
+                write!(f, "/announce{} {}", suffix, message)
+            }
             Command::Ban(user) => write!(f, "/ban {}", user),
             Command::Unban(user) => write!(f, "/unban {}", user),
             Command::Clear => write!(f, "/clear"),
@@ -379,13 +819,14 @@ impl<T: StringRef> fmt::Display for Command<T> {
                     write!(f, "/emoteonlyoff")
                 }
             }
-            Command::FollowersOnly(on) => {
-                if *on {
-                    write!(f, "/followers")
+            Command::FollowersOnly(opt_duration) => {
+                if let Some(duration) = opt_duration {
+                    write!(f, "/followers {}", duration)
                 } else {
-                    write!(f, "/followersoff")
+                    write!(f, "/followers")
                 }
             }
+            Command::FollowersOff => write!(f, "/followersoff"),
             Command::Host(host_channel) => write!(f, "/host {}", host_channel),
             Command::Unhost => write!(f, "/unhost"),
             Command::Marker(opt_description) => {
@@ -408,6 +849,7 @@ impl<T: StringRef> fmt::Display for Command<T> {
             }
             Command::Raid(target) => write!(f, "/raid {}", target),
             Command::Unraid => write!(f, "/unraid"),
+            Command::Shoutout(user) => write!(f, "/shoutout {}", user),
             Command::Slow(seconds) => write!(f, "/slow {}", seconds),
             Command::SlowOff => write!(f, "/slowoff"),
             Command::SubscribersOnly(on) => {
@@ -430,6 +872,19 @@ impl<T: StringRef> fmt::Display for Command<T> {
     }
 }
 
+/// Highlight color of an announcement, the channel's accent color is used if none is given
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnnounceColor {
+    /// `/announceblue`
+    Blue,
+    /// `/announcegreen`
+    Green,
+    /// `/announceorange`
+    Orange,
+    /// `/announcepurple`
+    Purple,
+}
+
 /// Twitch client capabilities
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Capability {
@@ -439,6 +894,8 @@ pub enum Capability {
     Tags,
     /// twitch.tv/commands capability
     Commands,
+    /// Any other capability, requested verbatim
+    Custom(&'static str),
 }
 
 impl Into<&'static str> for &Capability {
@@ -447,6 +904,7 @@ impl Into<&'static str> for &Capability {
             Capability::Membership => "twitch.tv/membership",
             Capability::Tags => "twitch.tv/tags",
             Capability::Commands => "twitch.tv/commands",
+            Capability::Custom(capability) => capability,
         }
     }
 }
@@ -457,3 +915,218 @@ impl fmt::Display for Capability {
         write!(f, "{}", cap_as_str)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::event::*;
+    use crate::irc::IrcMessage;
+    use crate::stream::rate_limits::RateLimitable;
+    use crate::{AnnounceColor, Channel, ClientMessage, InvalidCommand, MessageSender};
+
+    fn render(message: &ClientMessage) -> String {
+        match message.into() {
+            Message::Text(text) => text,
+            other => panic!("expected text message, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_message_injection() {
+        let message = ClientMessage::message("#channel", "hi\r\nJOIN #evil");
+        assert_eq!(
+            message,
+            ClientMessage::message("#channel", "hi  JOIN #evil".to_string())
+        );
+        assert_eq!(render(&message), "PRIVMSG #channel :hi  JOIN #evil");
+
+        let whisper = ClientMessage::whisper("user", "hi\nJOIN #evil\0");
+        assert_eq!(render(&whisper), "PRIVMSG #jtv :/w user hi JOIN #evil");
+    }
+
+    #[test]
+    fn test_from_tuple() {
+        assert_eq!(
+            ClientMessage::from(("#channel", "hi")),
+            ClientMessage::message("#channel", "hi")
+        );
+        let message: ClientMessage = ("#channel".to_string(), "hi\r\n").into();
+        assert_eq!(message, ClientMessage::message("#channel", "hi  "));
+    }
+
+    #[test]
+    fn test_followers_only() {
+        assert_eq!(
+            ClientMessage::followers_only("#channel", Some("10m")),
+            ClientMessage::message("#channel", "/followers 10m")
+        );
+        assert_eq!(
+            ClientMessage::followers_only("#channel", None),
+            ClientMessage::message("#channel", "/followers")
+        );
+        assert_eq!(
+            ClientMessage::followers_off("#channel"),
+            ClientMessage::message("#channel", "/followersoff")
+        );
+    }
+
+    #[test]
+    fn test_announce() {
+        assert_eq!(
+            render(&ClientMessage::announce("#channel", None, "hello")),
+            "PRIVMSG #channel :/announce hello"
+        );
+        let colors = [
+            (AnnounceColor::Blue, "/announceblue"),
+            (AnnounceColor::Green, "/announcegreen"),
+            (AnnounceColor::Orange, "/announceorange"),
+            (AnnounceColor::Purple, "/announcepurple"),
+        ];
+        for (color, command) in &colors {
+            assert_eq!(
+                ClientMessage::announce("#channel", Some(*color), "hello\r\n"),
+                ClientMessage::message("#channel", format!("{} hello  ", command))
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_command() {
+        let message =
+            ClientMessage::with_command("NOTICE", "#channel", "hi\r\nJOIN #evil").unwrap();
+        assert_eq!(render(&message), "NOTICE #channel :hi  JOIN #evil");
+        assert_eq!(message.channel_limits(), Some("#channel"));
+        assert_eq!(message.invalid_channel(), None);
+        assert_eq!(message.chat_text_channel(), Some("#channel"));
+    }
+
+    #[test]
+    fn test_chat_text_channel() {
+        for message in &["hello", "/me waves", ".me waves", "\u{1}ACTION waves\u{1}"] {
+            assert_eq!(
+                ClientMessage::message("#channel", *message).chat_text_channel(),
+                Some("#channel")
+            );
+        }
+        for message in &["/timeout someone", ".emoteonly", "/mods", "/meh"] {
+            assert_eq!(
+                ClientMessage::message("#channel", *message).chat_text_channel(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_custom_command() {
+        for command in &["", "PRIVMSG #other", "NOTICE\r\nJOIN", "/w", "PRIVMSG\n"] {
+            assert_eq!(
+                ClientMessage::with_command(*command, "#channel", "hi"),
+                Err(InvalidCommand(command.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_shoutout() {
+        assert_eq!(
+            render(&ClientMessage::shoutout("#channel", "someone")),
+            "PRIVMSG #channel :/shoutout someone"
+        );
+    }
+
+    #[test]
+    fn test_whisper() {
+        assert_eq!(
+            render(&ClientMessage::whisper("someone", "hello there")),
+            "PRIVMSG #jtv :/w someone hello there"
+        );
+    }
+
+    #[test]
+    fn test_reply() {
+        let message =
+            ClientMessage::reply("#channel", "b34ccfc7-4977-403a-8a94-33c6bac34fb8", "hi");
+        assert_eq!(
+            render(&message),
+            "@reply-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8 PRIVMSG #channel :hi"
+        );
+        assert_eq!(message.channel_limits(), Some("#channel"));
+    }
+
+    #[test]
+    fn test_join_all() {
+        let message = ClientMessage::join_all(vec!["#a", "#b", "#c"]);
+        assert_eq!(render(&message), "JOIN #a,#b,#c");
+        assert_eq!(message.invalid_channel(), None);
+        assert_eq!(
+            ClientMessage::join_all(vec!["#a", "#b,#c"]).invalid_channel(),
+            Some("#b,#c")
+        );
+        assert_eq!(
+            ClientMessage::join_all(Vec::<String>::new()).invalid_channel(),
+            Some("")
+        );
+
+        let message = ClientMessage::part_all(vec!["#a", "#b"]);
+        assert_eq!(render(&message), "PART #a,#b");
+        assert_eq!(message.invalid_channel(), None);
+        assert_eq!(
+            ClientMessage::part_all(vec!["a"]).invalid_channel(),
+            Some("a")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_action() {
+        let (mut sender, recorded) = MessageSender::dry_run();
+        sender.action("#chan", "waves").await.unwrap();
+        let sent = recorded.take();
+        assert_eq!(render(&sent[0]), "PRIVMSG #chan :\u{1}ACTION waves\u{1}");
+
+        // received actions can be sent again as actions
+        let line = format!(
+            ":someone!someone@someone.tmi.twitch.tv {}",
+            render(&sent[0])
+        );
+        match Event::try_from(IrcMessage::parse(&line).unwrap().1).unwrap() {
+            Event::PrivMsg(event) => {
+                assert_eq!(event.action(), Some("waves"));
+                assert_eq!(
+                    ClientMessage::me(*event.channel(), event.action().unwrap()),
+                    sent[0]
+                );
+            }
+            other => panic!("expected privmsg, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_render_single_line() {
+        // bypasses the sanitizing constructor
+        let message = ClientMessage::PrivMsg {
+            channel: "#channel".to_string(),
+            message: "hi\r\nJOIN #evil\u{0}".to_string(),
+        };
+        let rendered = render(&message);
+        assert!(!rendered.contains(&['\r', '\n'][..]));
+        assert_eq!(rendered, "PRIVMSG #channel :hi  JOIN #evil\u{0}");
+    }
+
+    #[test]
+    fn test_channel_from_str() {
+        let channel: Channel = "dallas".parse().unwrap();
+        assert_eq!(channel.as_str(), "#dallas");
+        assert_eq!(channel.login(), "dallas");
+        assert_eq!(channel, "#Dallas".parse().unwrap());
+        assert_eq!(channel.to_string(), "#dallas");
+        assert_eq!(render(&ClientMessage::join(channel)), "JOIN #dallas");
+
+        for invalid in &["dal las", "", "#", "##dallas", "a,b"] {
+            let err = invalid.parse::<Channel>().unwrap_err();
+            assert_eq!(err.0, *invalid);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Parsing of chat commands like `!ping` and routing them to handlers
+
+use std::future::Future;
+
+use fnv::FnvHashMap;
+use futures_core::future::BoxFuture;
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::event::*;
+use crate::runtime;
+use crate::{ClientMessage, Error, MessageSender};
+
+/// A chat command contained in a message, e.g. `!ping some args`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedCommand<'a> {
+    /// Name of the command, without the prefix
+    pub name: &'a str,
+    /// Whitespace separated arguments following the command name
+    pub args: Vec<&'a str>,
+}
+
+/// Parses a command from a chat message. Returns `None` if the message doesn't start with
+/// `prefix` directly followed by a command name.
+///
+/// ```
+/// use tmi_rs::commands::parse_command;
+///
+/// let command = parse_command("!", "!so  somebody now").unwrap();
+/// assert_eq!(command.name, "so");
+/// assert_eq!(command.args, vec!["somebody", "now"]);
+/// assert!(parse_command("!", "hello !so").is_none());
+/// ```
+pub fn parse_command<'a>(prefix: &str, message: &'a str) -> Option<ParsedCommand<'a>> {
+    if prefix.is_empty() {
+        return None;
+    }
+    let command = message.strip_prefix(prefix)?;
+    if command.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let mut parts = command.split_whitespace();
+    let name = parts.next()?;
+    Some(ParsedCommand {
+        name,
+        args: parts.collect(),
+    })
+}
+
+/// Everything passed to a command handler when its command is used
+#[derive(Debug)]
+pub struct CommandContext {
+    /// The message containing the command
+    pub event: EventData<String, PrivMsgEvent<String>>,
+    /// Arguments following the command name
+    pub args: Vec<String>,
+    /// Sender to respond with
+    pub sender: MessageSender,
+}
+
+impl CommandContext {
+    /// Send a message to the channel the command was used in
+    pub async fn reply(&mut self, message: impl Into<String>) -> Result<(), Error> {
+        let channel = self.event.channel().clone();
+        self.sender
+            .send(ClientMessage::message(channel, message.into()))
+            .await?;
+        Ok(())
+    }
+}
+
+type CommandHandler =
+    Box<dyn Fn(CommandContext) -> BoxFuture<'static, Result<(), Error>> + Send + Sync>;
+
+/// Dispatches commands in channel messages to registered handlers.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use tmi_rs::{single::connect, TwitchClientConfig};
+/// use tmi_rs::commands::CommandRouter;
+///
+/// # async fn run(config: Arc<TwitchClientConfig>) -> Result<(), tmi_rs::Error> {
+/// let mut client = connect(&config).await?;
+/// let sender = client.sender_cloned();
+/// CommandRouter::new("!")
+///     .command("ping", |mut ctx| async move { ctx.reply("pong").await })
+///     .run(client.stream_mut(), sender)
+///     .await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct CommandRouter {
+    prefix: String,
+    handlers: FnvHashMap<String, CommandHandler>,
+}
+
+impl CommandRouter {
+    /// Create a router for commands starting with `prefix`
+    pub fn new(prefix: impl Into<String>) -> Self {
+        CommandRouter {
+            prefix: prefix.into(),
+            handlers: Default::default(),
+        }
+    }
+
+    /// Register a handler for a command name. A handler registered for a name that is already in
+    /// use replaces the previous one.
+    pub fn command<F, Fut>(mut self, name: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(CommandContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), Error>> + Send + 'static,
+    {
+        self.handlers
+            .insert(name.into(), Box::new(move |ctx| Box::pin(handler(ctx))));
+        self
+    }
+
+    /// Consume the event stream and dispatch all commands in it until the stream ends. Each
+    /// handler invocation is spawned as its own task, errors returned by handlers are logged.
+    pub async fn run<St>(&self, events: St, sender: MessageSender)
+    where
+        St: Stream<Item = Result<Event, Error>> + Unpin,
+    {
+        let mut events = events;
+        while let Some(event) = events.next().await {
+            if let Ok(Event::PrivMsg(event)) = event {
+                self.dispatch(event, &sender);
+            }
+        }
+    }
+
+    fn dispatch(&self, event: EventData<String, PrivMsgEvent<String>>, sender: &MessageSender) {
+        let (handler, args) = match parse_command(&self.prefix, event.message()) {
+            Some(ParsedCommand { name, args }) => match self.handlers.get(name) {
+                Some(handler) => (handler, args.into_iter().map(String::from).collect()),
+                None => return,
+            },
+            None => return,
+        };
+        let handler_future = handler(CommandContext {
+            event,
+            args,
+            sender: sender.clone(),
+        });
+        runtime::spawn(async move {
+            if let Err(e) = handler_future.await {
+                warn!("Command handler failed: {}", e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use tokio::sync::mpsc;
+
+    use crate::commands::CommandRouter;
+    use crate::event::Event;
+    use crate::util::test_events::parse;
+    use crate::{ClientMessage, MessageResponse, MessageSender};
+
+    #[tokio::test]
+    async fn test_ping_command() {
+        let (message_tx, mut message_rx) = mpsc::channel(10);
+        let line = ":somebody!somebody@somebody.tmi.twitch.tv PRIVMSG #channel :!ping";
+        let events = stream::iter(vec![Ok(Event::from(&parse(line)))]);
+
+        CommandRouter::new("!")
+            .command("ping", |mut ctx| async move { ctx.reply("pong").await })
+            .run(events, MessageSender::from(message_tx))
+            .await;
+
+        let sent = message_rx.recv().await.unwrap();
+        sent.responder.send(Ok(MessageResponse::Ok)).ok();
+        assert_eq!(sent.message, ClientMessage::message("#channel", "pong"));
+    }
+}
@@ -9,6 +9,7 @@ use crate::irc::IrcMessage;
 use crate::stream::SentClientMessage;
 use crate::ClientMessage;
 use std::sync::Arc;
+use tokio::time::Duration;
 
 /// Error type for tmi-rs methods
 #[derive(Debug, Error, Clone)]
@@ -22,7 +23,8 @@ pub enum Error {
     /// Wrong number of IRC parameters in one of the received messages
     #[error("Received unknown IRC command in message {0:?}")]
     WrongIrcParameterCount(usize, IrcMessage<String>),
-    /// Unrecognized IRC command was received
+    /// Unrecognized IRC command was received while strict parsing is enabled, otherwise these are
+    /// returned as `Event::Unknown`. Contains the complete message, so it can still be inspected.
     #[error("Received unknown IRC command in message {0:?}")]
     UnknownIrcCommand(IrcMessage<String>),
     /// An IRCv3 tag that is normally expected to be set on a message was missing
@@ -45,8 +47,40 @@ pub enum Error {
     /// Tag parsing error
     #[error("Tag content parsing error in tag {0}={1}")]
     TagParseError(String, String),
+    /// The connection did not become active within the configured `ready_timeout`
+    #[error("Connection did not become ready within {0:?}")]
+    ReadyTimeout(Duration),
+    /// The connection was closed for good before it became active, for example because the
+    /// maximum number of reconnect attempts was reached
+    #[error("Connection was closed before it became ready")]
+    ConnectionClosed,
 }
 
+impl Error {
+    /// The received IRC message that could not be converted to an event, for errors caused by
+    /// unknown commands or unexpected parameters
+    pub fn irc_message(&self) -> Option<&IrcMessage<String>> {
+        match self {
+            Error::MissingIrcCommandParameter(_, msg)
+            | Error::WrongIrcParameterCount(_, msg)
+            | Error::UnknownIrcCommand(msg) => Some(msg),
+            _ => None,
+        }
+    }
+}
+
+/// A channel name could not be parsed into a [`Channel`](struct.Channel.html) because it is empty
+/// or contains spaces, commas or more than one `#`
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Invalid channel name {0:?}")]
+pub struct InvalidChannelName(pub String);
+
+/// A command passed to [`ClientMessage::with_command`](enum.ClientMessage.html#method.with_command)
+/// is not a single IRC command word, like when it is empty or contains spaces or line breaks
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+#[error("Invalid IRC command {0:?}")]
+pub struct InvalidCommand(pub String);
+
 /// Errors from the internal event channels sharing events between tasks
 #[derive(Debug, Error, Clone)]
 pub enum EventChannelError {
@@ -62,10 +96,16 @@ pub enum EventChannelError {
 #[derive(Debug, Error, Clone)]
 pub enum MessageSendError {
     /// Sending the message failed because the connection has already been closed
-    #[error("Sending the message failed because the connection has already been closed")]
+    #[error(
+        "Sending {} failed because the connection has already been closed",
+        describe_message(.0)
+    )]
     Closed(ClientMessage),
     /// No connection in the pool has the twitch channel specified in the message
-    #[error("No connection in the pool has the twitch channel specified in the message")]
+    #[error(
+        "Can't send {}, no connection in the pool has joined the channel",
+        describe_message(.0)
+    )]
     ChannelNotJoined(ClientMessage),
     /// Unsupported message type
     #[error("Unsupported message type: {0}")]
@@ -74,6 +114,57 @@ pub enum MessageSendError {
     /// happened while connecting
     #[error("Error while trying to create a new connection: {0}")]
     NewConnectionFailed(String),
+    /// Joining another channel would exceed the configured channel limit of the connection
+    #[error("Joining the channel would exceed the limit of {0} channels for this connection")]
+    ChannelLimitReached(usize),
+    /// Joining another channel would need a new connection, but the connection pool already has
+    /// the configured maximum number of connections
+    #[error("Joining the channel would exceed the limit of {0} connections for this pool")]
+    ConnectionLimitReached(usize),
+    /// Twitch refused to join a channel
+    #[error("Joining {channel} failed: {msg_id}")]
+    JoinFailed {
+        /// The channel that was supposed to be joined
+        channel: String,
+        /// `msg-id` of the NOTICE Twitch responded with
+        msg_id: String,
+    },
+    /// The channel name of the message is malformed, Twitch would ignore the message
+    #[error("Invalid channel name {0:?}, channel names start with # and contain no spaces")]
+    InvalidChannel(String),
+    /// The message has no visible text and would be rejected by Twitch
+    #[error("Empty messages can not be sent")]
+    EmptyMessage,
+    /// The channel is in emote-only mode and the message contains chat text, Twitch would reject
+    /// it. Only reported when the emote-only guard of the sender is enabled.
+    #[error("{0} is in emote-only mode")]
+    EmoteOnly(String),
+    /// Twitch did not respond to a message in time
+    #[error("Timed out while waiting for a response")]
+    ResponseTimeout,
+}
+
+/// Short description of a message for error messages, including its channel or recipient. Doesn't
+/// include the message text, or the token in case of `PASS`.
+fn describe_message(message: &ClientMessage) -> String {
+    match message {
+        ClientMessage::PrivMsg { channel, .. } => format!("message to {}", channel),
+        ClientMessage::Reply { channel, .. } => format!("reply in {}", channel),
+        ClientMessage::CustomPrivMsg {
+            command, channel, ..
+        } => format!("{} to {}", command, channel),
+        ClientMessage::Whisper { recipient, .. } => format!("whisper to {}", recipient),
+        ClientMessage::Join(channel) => format!("JOIN {}", channel),
+        ClientMessage::JoinAll(channels) => format!("JOIN {}", channels.join(",")),
+        ClientMessage::Part(channel) => format!("PART {}", channel),
+        ClientMessage::PartAll(channels) => format!("PART {}", channels.join(",")),
+        ClientMessage::Nick(nick) => format!("NICK {}", nick),
+        ClientMessage::Pass(_) => "PASS".to_string(),
+        ClientMessage::CapRequest(_) => "CAP REQ".to_string(),
+        ClientMessage::Ping => "PING".to_string(),
+        ClientMessage::Pong(_) => "PONG".to_string(),
+        ClientMessage::Close => "close request".to_string(),
+    }
 }
 
 impl From<mpsc::error::SendError<ClientMessage>> for MessageSendError {
@@ -99,3 +190,35 @@ impl From<tokio_tungstenite::tungstenite::Error> for Error {
         Error::WebsocketError(Arc::new(err))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{ClientMessage, MessageSendError};
+
+    #[test]
+    fn test_message_send_error_display() {
+        let error = MessageSendError::ChannelNotJoined(ClientMessage::message("#channel", "hi"));
+        assert_eq!(
+            error.to_string(),
+            "Can't send message to #channel, no connection in the pool has joined the channel"
+        );
+
+        let error = MessageSendError::Closed(ClientMessage::whisper("someone", "hi"));
+        assert!(error.to_string().contains("whisper to someone"));
+
+        let error = MessageSendError::Closed(ClientMessage::Pass("oauth:secret".to_string()));
+        assert!(!error.to_string().contains("secret"));
+
+        let error = MessageSendError::UnsupportedMessage("NICK is sent automatically.");
+        assert!(error.to_string().contains("NICK is sent automatically."));
+
+        let error = MessageSendError::JoinFailed {
+            channel: "#channel".to_string(),
+            msg_id: "msg_channel_suspended".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Joining #channel failed: msg_channel_suspended"
+        );
+    }
+}
@@ -1,13 +1,24 @@
 //! Contains the structs that hold event specific data, and traits to access their contents in
 //! EventData<_, _> structs.
 
+use std::borrow::Borrow;
 use std::fmt::Debug;
 
 use derive_more::{From, Into};
 
+use crate::client_messages::action_text;
+use crate::event::tags::NoticeId;
 use crate::event::{EventData, ToOwnedEvent};
 use crate::util::RefToString;
-use crate::StringRef;
+use crate::{ClientMessage, StringRef};
+
+/// Access to the channel of any event that belongs to a channel. It is implemented for
+/// [`Event`](crate::event::Event), so generic code can get the channel without knowing the event
+/// type. The event data of a specific event type has its own `channel()` accessor.
+pub trait HasChannel<T> {
+    /// Channel the event belongs to, `None` for events that aren't related to a channel
+    fn channel(&self) -> Option<&T>;
+}
 
 /// Welcome messages that Twitch sends after connection and logging
 /// in successfully.
@@ -19,6 +30,7 @@ use crate::StringRef;
 /// [RPL_MOTDSTART](crate::irc_constants::RPL_MOTDSTART), [RPL_MOTD](crate::irc_constants::RPL_MOTD),
 /// [RPL_ENDOFMOTD](crate::irc_constants::RPL_ENDOFMOTD)
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ConnectMessageEvent<T: Debug + Clone + Eq> {
     /// IRC command name, typically 3 digit numeric code
     command: T,
@@ -41,13 +53,23 @@ pub trait ConnectMessageEventData<T> {
     fn params(&self) -> &[T];
 }
 
-impl<T: StringRef> ConnectMessageEventData<T> for EventData<T, ConnectMessageEvent<T>> {
+impl<T, Inner> ConnectMessageEventData<T> for EventData<T, Inner>
+where
+    T: StringRef,
+    Inner: AsRef<ConnectMessageEvent<T>> + Debug + Clone + Eq,
+{
     fn command(&self) -> &T {
-        &self.event.command
+        &self.event.as_ref().command
     }
 
     fn params(&self) -> &[T] {
-        self.event.params.as_slice()
+        self.event.as_ref().params.as_slice()
+    }
+}
+
+impl<T: StringRef> AsRef<ConnectMessageEvent<T>> for ConnectMessageEvent<T> {
+    fn as_ref(&self) -> &ConnectMessageEvent<T> {
+        self
     }
 }
 
@@ -64,6 +86,7 @@ impl<T: StringRef> ToOwnedEvent for ConnectMessageEvent<T> {
 
 /// Event containing just a username
 #[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserEvent<T: StringRef> {
     user: T,
 }
@@ -97,6 +120,7 @@ impl<T: StringRef> ToOwnedEvent for UserEvent<T> {
 
 /// Events containing a channel and a message
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelMessageEvent<T: StringRef> {
     channel: T,
     message: T,
@@ -157,6 +181,7 @@ impl<T: StringRef> ToOwnedEvent for ChannelMessageEvent<T> {
 
 /// Event containing only a channel
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelEvent<T: StringRef> {
     channel: T,
 }
@@ -197,6 +222,7 @@ impl<T: StringRef> ToOwnedEvent for ChannelEvent<T> {
 
 /// Event containing a channel and a username
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ChannelUserEvent<T: StringRef> {
     channel: T,
     user: Option<T>,
@@ -262,80 +288,259 @@ macro_rules! impl_inner_to_owned {
 
 /// PRIVMSG event contents
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PrivMsgEvent<T: StringRef>(ChannelMessageEvent<T>);
 impl_inner_to_owned!(PrivMsgEvent, ChannelMessageEvent);
 
+impl<T: StringRef> EventData<T, PrivMsgEvent<T>> {
+    /// Create a message that responds to this one in the same channel
+    pub fn reply<S: Into<String> + Borrow<str>>(&self, message: S) -> ClientMessage {
+        ClientMessage::message(self.channel().borrow(), message)
+    }
+
+    /// Text of an action (`/me`) message, `None` if this is a regular message. Send it as an
+    /// action again using [`ClientMessage::me`](crate::ClientMessage::me).
+    pub fn action(&self) -> Option<&str> {
+        action_text(self.message().borrow())
+    }
+
+    /// Users mentioned with `@username` in the message, lowercased and without surrounding
+    /// punctuation, in the order they appear
+    pub fn mentions(&self) -> Vec<String> {
+        self.message()
+            .borrow()
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix('@'))
+            .map(|name| name.trim_matches(|c: char| !c.is_alphanumeric() && c != '_'))
+            .filter(|name| !name.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    }
+}
+
 /// JOIN event contents
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct JoinEvent<T: StringRef>(ChannelEvent<T>);
 impl_inner_to_owned!(JoinEvent, ChannelEvent);
 
 /// End of NAMES list event content
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EndOfNamesEvent<T: StringRef>(ChannelEvent<T>);
 impl_inner_to_owned!(EndOfNamesEvent, ChannelEvent);
 
 /// PART event content
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PartEvent<T: StringRef>(ChannelEvent<T>);
 impl_inner_to_owned!(PartEvent, ChannelEvent);
 
 /// CLEARCHAT event content
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClearChatEvent<T: StringRef>(ChannelUserEvent<T>);
 impl_inner_to_owned!(ClearChatEvent, ChannelUserEvent);
 
 /// CLEARMSG event content
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ClearMsgEvent<T: StringRef>(ChannelMessageEvent<T>);
 impl_inner_to_owned!(ClearMsgEvent, ChannelMessageEvent);
 
 /// NOTICE event content
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NoticeEvent<T: StringRef>(ChannelMessageEvent<T>);
 impl_inner_to_owned!(NoticeEvent, ChannelMessageEvent);
 
+/// Access to the user lists contained in the NOTICE responses to `/mods` and `/vips`.
+///
+/// The lists are parsed from the notice text only, so they are available without the `msg-id`
+/// tag or any other capability-dependent data.
+pub trait UserListNoticeData<T> {
+    /// List of moderators if the notice is a response to `/mods`, `None` for other notices
+    fn mods(&self) -> Option<Vec<&str>>;
+    /// List of VIPs if the notice is a response to `/vips`, `None` for other notices
+    fn vips(&self) -> Option<Vec<&str>>;
+}
+
+impl<T: StringRef> UserListNoticeData<T> for EventData<T, NoticeEvent<T>> {
+    fn mods(&self) -> Option<Vec<&str>> {
+        parse_user_list(
+            self.message().borrow(),
+            "The moderators of this channel are:",
+            "There are no moderators of this channel.",
+        )
+    }
+
+    fn vips(&self) -> Option<Vec<&str>> {
+        parse_user_list(
+            self.message().borrow(),
+            "The VIPs of this channel are:",
+            "This channel does not have any VIPs.",
+        )
+    }
+}
+
+/// Parses a comma separated user list following `prefix`, or an empty list if the message
+/// is the equivalent "no users" notice
+fn parse_user_list<'a>(
+    message: &'a str,
+    prefix: &str,
+    empty_message: &str,
+) -> Option<Vec<&'a str>> {
+    if message == empty_message {
+        return Some(vec![]);
+    }
+    message.strip_prefix(prefix).map(|list| {
+        list.trim_end_matches('.')
+            .split(',')
+            .map(str::trim)
+            .filter(|user| !user.is_empty())
+            .collect()
+    })
+}
+
 /// RECONNECT event
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ReconnectEvent;
 
 /// ROOMSTATE event
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RoomStateEvent<T: StringRef>(ChannelEvent<T>);
 impl_inner_to_owned!(RoomStateEvent, ChannelEvent);
 
 /// USERNOTICE event
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserNoticeEvent<T: StringRef>(ChannelMessageEvent<T>);
 impl_inner_to_owned!(UserNoticeEvent, ChannelMessageEvent);
 
 /// USERSTATE event
 #[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UserStateEvent<T: StringRef>(ChannelEvent<T>);
 impl_inner_to_owned!(UserStateEvent, ChannelEvent);
 
 /// GLOBALUSERSTATE event
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GlobalUserStateEvent;
 
 /// Connection close event
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CloseEvent;
 
-/// IRC PING event
+/// Emitted by the client before it tries to reconnect after the connection was lost
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
-pub struct PingEvent;
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReconnectingEvent {
+    /// Number of connection attempts since the connection was last stable, including this one
+    pub attempt: u32,
+}
+
+/// Emitted by the client whenever the websocket connection to Twitch was (re-)established
+#[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ConnectedEvent;
+
+/// Emitted by the client after the GLOBALUSERSTATE that Twitch sends once the login succeeded.
+/// The GLOBALUSERSTATE itself is passed on as well.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AuthenticatedEvent {
+    /// ID of the logged in user
+    pub user_id: usize,
+    /// Login name of the user, as configured in `TwitchClientConfig::username`
+    pub login: String,
+}
+
+/// Emitted by the client after a NOTICE that says Twitch rejected a message sent to a channel,
+/// for example because it was sent too quickly. The NOTICE itself is passed on as well.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MessageRejectedEvent<T: StringRef> {
+    channel: T,
+    reason: NoticeId,
+}
+
+impl<T: StringRef> MessageRejectedEvent<T> {
+    /// New event for a message that was rejected for the given reason
+    pub fn new(channel: T, reason: NoticeId) -> Self {
+        MessageRejectedEvent { channel, reason }
+    }
+
+    /// The channel the message was sent to
+    pub fn channel(&self) -> &T {
+        &self.channel
+    }
+
+    /// Why the message was rejected, from the `msg-id` tag of the NOTICE
+    pub fn reason(&self) -> &NoticeId {
+        &self.reason
+    }
+}
+
+impl<T: StringRef> ToOwnedEvent for MessageRejectedEvent<T> {
+    type Owned = MessageRejectedEvent<String>;
+
+    fn to_owned_event(&self) -> Self::Owned {
+        MessageRejectedEvent {
+            channel: self.channel.ref_to_string(),
+            reason: self.reason.clone(),
+        }
+    }
+}
+
+/// IRC PING event
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PingEvent<T: StringRef> {
+    params: Vec<T>,
+}
+
+impl<T: StringRef> PingEvent<T> {
+    /// New PING event with the given parameters
+    pub fn new(params: Vec<T>) -> Self {
+        PingEvent { params }
+    }
+
+    /// Parameters of the PING, which are echoed in the PONG response. Usually just the server
+    /// name, `tmi.twitch.tv`.
+    pub fn params(&self) -> &[T] {
+        &self.params
+    }
+}
+
+impl<T: StringRef> ToOwnedEvent for PingEvent<T> {
+    type Owned = PingEvent<String>;
+
+    fn to_owned_event(&self) -> Self::Owned {
+        PingEvent {
+            params: self.params.iter().map(RefToString::ref_to_string).collect(),
+        }
+    }
+}
 
 /// IRC PONG event
 #[derive(Debug, Clone, Eq, PartialEq, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct PongEvent;
 
-/// Unknown event that could not be parsed.
-#[derive(Debug, Clone, Eq, PartialEq, Copy)]
-pub struct UnknownEvent;
+/// Event with an IRC command that is not known to this library. The command and its parameters
+/// are available through [`ConnectMessageEventData`](trait.ConnectMessageEventData.html).
+#[derive(Debug, Clone, Eq, PartialEq, From, Into)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UnknownEvent<T: StringRef>(ConnectMessageEvent<T>);
+impl_inner_to_owned!(UnknownEvent, ConnectMessageEvent);
 
 /// NAMES list response data
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NamesListEvent<T: StringRef> {
     user: T,
     channel: T,
@@ -353,6 +558,12 @@ impl<T: StringRef> NamesListEvent<T> {
     }
 }
 
+impl<T: StringRef> AsRef<NamesListEvent<T>> for NamesListEvent<T> {
+    fn as_ref(&self) -> &NamesListEvent<T> {
+        self
+    }
+}
+
 /// Data accessors for NAMES events
 pub trait NamesEventData<T: StringRef> {
     /// Current user
@@ -395,6 +606,7 @@ impl<T: StringRef> ToOwnedEvent for NamesListEvent<T> {
 
 /// User mode change event
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ModeChangeEvent<T: StringRef> {
     channel: T,
     mode_change: T,
@@ -456,6 +668,7 @@ impl<T: StringRef> ToOwnedEvent for ModeChangeEvent<T> {
 
 /// Whisper message event data
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WhisperEvent<T: StringRef> {
     recipient: T,
     message: T,
@@ -511,6 +724,7 @@ pub trait HostEventData<T> {
 
 /// Host event data (hosting channel, target channel, viewers)
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HostEvent<T: StringRef> {
     hosting_channel: T,
     target_channel: Option<T>,
@@ -559,11 +773,34 @@ impl<T: StringRef> ToOwnedEvent for HostEvent<T> {
 
 /// IRCv3 CAP response data, sent in response to CAP requests
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CapabilityEvent<T: Debug + Clone + Eq> {
     /// Parameters of the CAP response
     pub params: Vec<T>,
 }
 
+/// Accessors for CAP responses
+pub trait CapabilityEventData<T> {
+    /// CAP subcommand, `ACK` when the capabilities were granted or `NAK` when they were denied
+    fn subcommand(&self) -> Option<&str>;
+    /// Capabilities the response applies to
+    fn capabilities(&self) -> Vec<&str>;
+}
+
+impl<T: StringRef> CapabilityEventData<T> for EventData<T, CapabilityEvent<T>> {
+    fn subcommand(&self) -> Option<&str> {
+        self.event.params.get(1).map(|param| param.borrow())
+    }
+
+    fn capabilities(&self) -> Vec<&str> {
+        self.event
+            .params
+            .get(2)
+            .map(|param| param.borrow().split_whitespace().collect())
+            .unwrap_or_default()
+    }
+}
+
 impl<T: StringRef> ToOwnedEvent for CapabilityEvent<T> {
     type Owned = CapabilityEvent<String>;
 
@@ -578,8 +815,11 @@ impl<T: StringRef> ToOwnedEvent for CapabilityEvent<T> {
 mod test {
     use std::convert::TryFrom;
 
+    use crate::event::tags::*;
     use crate::event::Event;
     use crate::irc::*;
+    use crate::util::test_events;
+    use tokio_tungstenite::tungstenite::Message;
 
     use super::*;
 
@@ -594,7 +834,8 @@ mod test {
             Event::Join(EventData {
                 sender: Some("ronni"),
                 event: ChannelEvent::new("#dallas").into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
@@ -613,7 +854,8 @@ mod test {
                     mode_change: "+o",
                     user: "ronni"
                 },
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
@@ -649,7 +891,8 @@ mod test {
                         channel: "#dallas",
                         names: vec!["ronni", "fred", "wilma"]
                     },
-                    tags: None
+                    tags: None,
+                    raw: None
                 }),
                 Event::Names(EventData {
                     sender: None,
@@ -658,17 +901,30 @@ mod test {
                         channel: "#dallas",
                         names: vec!["barney", "betty"]
                     },
-                    tags: None
+                    tags: None,
+                    raw: None
                 }),
                 Event::EndOfNames(EventData {
                     sender: None,
                     event: ChannelEvent::new("#dallas").into(),
-                    tags: None
+                    tags: None,
+                    raw: None
                 })
             ]
         )
     }
 
+    #[test]
+    fn test_mentions() {
+        let (_, msg) =
+            IrcMessage::parse(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :@Alice hi @bob!")
+                .unwrap();
+        match Event::try_from(msg).unwrap() {
+            Event::PrivMsg(event) => assert_eq!(event.mentions(), vec!["alice", "bob"]),
+            event => panic!("unexpected event: {:?}", event),
+        }
+    }
+
     #[test]
     fn test_part() {
         let (remaining, msg) =
@@ -680,7 +936,8 @@ mod test {
             Event::Part(EventData {
                 sender: Some("ronni"),
                 event: ChannelEvent::new("#dallas").into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
@@ -695,7 +952,8 @@ mod test {
             Event::ClearChat(EventData {
                 sender: None,
                 event: ChannelUserEvent::new("#dallas", Some("ronni")).into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         );
 
@@ -706,7 +964,8 @@ mod test {
             Event::ClearChat(EventData {
                 sender: None,
                 event: ChannelUserEvent::new("#dallas", None).into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         );
     }
@@ -733,7 +992,8 @@ mod test {
                         ("target-msg-id", "abc-123-def".to_string())
                     ]
                     .into_iter()
-                ))
+                )),
+                raw: None
             })
         )
     }
@@ -752,7 +1012,8 @@ mod test {
                     target_channel: Some("<channel>"),
                     viewer_count: Some(999)
                 },
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
@@ -771,11 +1032,43 @@ mod test {
                     target_channel: None,
                     viewer_count: None
                 },
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
 
+    #[test]
+    fn test_host_unknown_viewers() {
+        let (_, msg) =
+            IrcMessage::parse(":tmi.twitch.tv HOSTTARGET #hosting_channel :<channel> -").unwrap();
+        assert_eq!(
+            Event::try_from(msg).unwrap(),
+            Event::Host(EventData {
+                sender: None,
+                event: HostEvent {
+                    hosting_channel: "#hosting_channel",
+                    target_channel: Some("<channel>"),
+                    viewer_count: None
+                },
+                tags: None,
+                raw: None
+            })
+        );
+
+        // missing payload doesn't panic
+        let (_, msg) = IrcMessage::parse(":tmi.twitch.tv HOSTTARGET #hosting_channel").unwrap();
+        match Event::try_from(msg).unwrap() {
+            Event::Host(event) => {
+                assert_eq!(event.target_channel(), None);
+                assert_eq!(event.viewer_count(), None);
+            }
+            other => panic!("expected host event, got {:?}", other),
+        }
+        let (_, msg) = IrcMessage::parse(":tmi.twitch.tv HOSTTARGET").unwrap();
+        assert!(Event::try_from(msg).is_err());
+    }
+
     #[test]
     fn test_notice() {
         use fnv::FnvHashMap;
@@ -793,11 +1086,113 @@ mod test {
                     .into(),
                 tags: Some(FnvHashMap::from_iter(
                     vec![("msg-id", "slow_off".to_string())].into_iter()
-                ))
+                )),
+                raw: None
             })
         )
     }
 
+    #[test]
+    fn test_privmsg_reply() {
+        let event = test_events::privmsg(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello");
+        assert_eq!(
+            event.reply("hi there"),
+            ClientMessage::message("#dallas", "hi there")
+        );
+    }
+
+    #[test]
+    fn test_ping_pong_params() {
+        for (ping, pong) in &[
+            ("PING", "PONG"),
+            ("PING :tmi.twitch.tv", "PONG :tmi.twitch.tv"),
+            ("PING server1 server2", "PONG server1 :server2"),
+        ] {
+            let params = match test_events::parse(ping) {
+                Event::Ping(ping) => ping.params().to_vec(),
+                other => panic!("expected ping, got {:?}", other),
+            };
+            let message: Message = (&ClientMessage::Pong(params)).into();
+            assert_eq!(message, Message::Text(pong.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_has_channel() {
+        let events: Vec<Event<String>> = vec![
+            ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello",
+            ":ronni!ronni@ronni.tmi.twitch.tv JOIN #ronni",
+            "@msg-id=slow_off :tmi.twitch.tv NOTICE #dallas :This room is no longer in slow mode.",
+            ":tmi.twitch.tv HOSTTARGET #hosting_channel :target_channel 9",
+            ":tmi.twitch.tv CLEARCHAT #dallas :ronni",
+            "PING :tmi.twitch.tv",
+        ]
+        .into_iter()
+        .map(|line| Event::from(&test_events::parse(line)))
+        .collect();
+
+        let channels: Vec<Option<&str>> = events
+            .iter()
+            .map(|event| event.channel().map(String::as_str))
+            .collect();
+        assert_eq!(
+            channels,
+            vec![
+                Some("#dallas"),
+                Some("#ronni"),
+                Some("#dallas"),
+                Some("#hosting_channel"),
+                Some("#dallas"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_whisper() {
+        let (remaining, msg) = IrcMessage::parse(
+            "@badges=;color=#8A2BE2;display-name=SomeOne;emotes=;message-id=306;\
+             thread-id=12345_67890;turbo=0;user-id=67890;user-type= \
+             :someone!someone@someone.tmi.twitch.tv WHISPER recipient :hello there",
+        )
+        .unwrap();
+        assert_eq!(remaining, "");
+        let event = match Event::try_from(msg).unwrap() {
+            Event::Whisper(data) => data,
+            other => panic!("expected whisper, got {:?}", other),
+        };
+        assert_eq!(event.sender(), &Some("someone"));
+        assert_eq!(event.recipient(), &"recipient");
+        assert_eq!(event.message(), &"hello there");
+        assert_eq!(event.color(), Some("#8A2BE2"));
+        assert_eq!(event.display_name(), Some("SomeOne"));
+        assert_eq!(event.thread_id().unwrap(), "12345_67890");
+        assert_eq!(event.message_id().unwrap(), 306);
+    }
+
+    #[test]
+    fn test_notice_user_lists() {
+        use crate::util::test_events::notice;
+
+        let mods = notice(
+            ":tmi.twitch.tv NOTICE #dallas :The moderators of this channel are: ronni, fred, bob",
+        );
+        assert_eq!(mods.mods(), Some(vec!["ronni", "fred", "bob"]));
+        assert_eq!(mods.vips(), None);
+
+        let no_mods =
+            notice(":tmi.twitch.tv NOTICE #dallas :There are no moderators of this channel.");
+        assert_eq!(no_mods.mods(), Some(vec![]));
+
+        let vips =
+            notice(":tmi.twitch.tv NOTICE #dallas :The VIPs of this channel are: ronni, fred.");
+        assert_eq!(vips.vips(), Some(vec!["ronni", "fred"]));
+        assert_eq!(vips.mods(), None);
+
+        let no_vips = notice(":tmi.twitch.tv NOTICE #dallas :This channel does not have any VIPs.");
+        assert_eq!(no_vips.vips(), Some(vec![]));
+    }
+
     #[test]
     fn test_roomstate() {
         use fnv::FnvHashMap;
@@ -821,7 +1216,8 @@ mod test {
                         ("r9k", "0".to_string())
                     ]
                     .into_iter()
-                ))
+                )),
+                raw: None
             })
         )
     }
@@ -837,7 +1233,8 @@ mod test {
             Event::UserNotice(EventData {
                 sender: None,
                 event: ChannelMessageEvent::new("#<channel>", "<message>").into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
@@ -852,8 +1249,24 @@ mod test {
             Event::UserState(EventData {
                 sender: None,
                 event: ChannelEvent::new("#dallas").into(),
-                tags: None
+                tags: None,
+                raw: None
             })
         )
     }
+
+    #[test]
+    fn test_unknown_command() {
+        let (remaining, msg) =
+            IrcMessage::parse("@some-tag=1 :tmi.twitch.tv NEWCOMMAND #dallas :some content")
+                .unwrap();
+        assert_eq!(remaining, "");
+        let event = match Event::try_from(msg).unwrap() {
+            Event::Unknown(data) => data,
+            other => panic!("expected unknown event, got {:?}", other),
+        };
+        assert_eq!(*event.command(), "NEWCOMMAND");
+        assert_eq!(event.params(), &["#dallas", "some content"]);
+        assert_eq!(event.tag("some-tag"), Some("1"));
+    }
 }
@@ -29,6 +29,7 @@ pub type SharedEvent = Arc<Event<String>>;
 /// Enum containing all event types that can be received from Twitch
 #[allow(missing_docs)]
 #[derive(Debug, Clone, PartialEq, Eq, From)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event<T = String>
 where
     T: StringRef,
@@ -52,9 +53,13 @@ where
     ConnectMessage(EventData<T, ConnectMessageEvent<T>>),
     GlobalUserState(EventData<T, GlobalUserStateEvent>),
     Close(CloseEvent),
-    Ping(PingEvent),
+    Reconnecting(ReconnectingEvent),
+    Connected(ConnectedEvent),
+    Authenticated(AuthenticatedEvent),
+    MessageRejected(MessageRejectedEvent<T>),
+    Ping(PingEvent<T>),
     Pong(PongEvent),
-    Unknown(UnknownEvent),
+    Unknown(EventData<T, UnknownEvent<T>>),
 }
 
 impl<T> From<&Event<T>> for Event<String>
@@ -82,9 +87,13 @@ where
             Event::GlobalUserState(inner) => Event::GlobalUserState(inner.to_owned_event()),
             Event::Reconnect(inner) => Event::Reconnect(inner.to_owned_event()),
             Event::Close(e) => Event::Close(*e),
-            Event::Ping(e) => Event::Ping(*e),
+            Event::Reconnecting(e) => Event::Reconnecting(*e),
+            Event::Connected(e) => Event::Connected(*e),
+            Event::Authenticated(e) => Event::Authenticated(e.clone()),
+            Event::MessageRejected(e) => Event::MessageRejected(e.to_owned_event()),
+            Event::Ping(e) => Event::Ping(e.to_owned_event()),
             Event::Pong(e) => Event::Pong(*e),
-            Event::Unknown(e) => Event::Unknown(*e),
+            Event::Unknown(inner) => Event::Unknown(inner.to_owned_event()),
         }
     }
 }
@@ -101,6 +110,104 @@ impl<T: StringRef> Event<T> {
             _ => None,
         }
     }
+
+    /// Get the IRC line the event was parsed from, if raw lines are kept. Events that are not
+    /// received from Twitch, like `Close` or `Connected`, never have one.
+    pub fn raw(&self) -> Option<&str> {
+        match self {
+            Event::PrivMsg(data) => data.raw(),
+            Event::Whisper(data) => data.raw(),
+            Event::Join(data) => data.raw(),
+            Event::Mode(data) => data.raw(),
+            Event::Names(data) => data.raw(),
+            Event::EndOfNames(data) => data.raw(),
+            Event::Part(data) => data.raw(),
+            Event::ClearChat(data) => data.raw(),
+            Event::ClearMsg(data) => data.raw(),
+            Event::Host(data) => data.raw(),
+            Event::Notice(data) => data.raw(),
+            Event::Reconnect(data) => data.raw(),
+            Event::RoomState(data) => data.raw(),
+            Event::UserNotice(data) => data.raw(),
+            Event::UserState(data) => data.raw(),
+            Event::Capability(data) => data.raw(),
+            Event::ConnectMessage(data) => data.raw(),
+            Event::GlobalUserState(data) => data.raw(),
+            Event::Unknown(data) => data.raw(),
+            Event::Close(_)
+            | Event::Reconnecting(_)
+            | Event::Connected(_)
+            | Event::Authenticated(_)
+            | Event::MessageRejected(_)
+            | Event::Ping(_)
+            | Event::Pong(_) => None,
+        }
+    }
+
+    /// Attach the IRC line the event was parsed from
+    pub(crate) fn set_raw(&mut self, line: Arc<str>) {
+        match self {
+            Event::PrivMsg(data) => data.raw = Some(line),
+            Event::Whisper(data) => data.raw = Some(line),
+            Event::Join(data) => data.raw = Some(line),
+            Event::Mode(data) => data.raw = Some(line),
+            Event::Names(data) => data.raw = Some(line),
+            Event::EndOfNames(data) => data.raw = Some(line),
+            Event::Part(data) => data.raw = Some(line),
+            Event::ClearChat(data) => data.raw = Some(line),
+            Event::ClearMsg(data) => data.raw = Some(line),
+            Event::Host(data) => data.raw = Some(line),
+            Event::Notice(data) => data.raw = Some(line),
+            Event::Reconnect(data) => data.raw = Some(line),
+            Event::RoomState(data) => data.raw = Some(line),
+            Event::UserNotice(data) => data.raw = Some(line),
+            Event::UserState(data) => data.raw = Some(line),
+            Event::Capability(data) => data.raw = Some(line),
+            Event::ConnectMessage(data) => data.raw = Some(line),
+            Event::GlobalUserState(data) => data.raw = Some(line),
+            Event::Unknown(data) => data.raw = Some(line),
+            Event::Close(_)
+            | Event::Reconnecting(_)
+            | Event::Connected(_)
+            | Event::Authenticated(_)
+            | Event::MessageRejected(_)
+            | Event::Ping(_)
+            | Event::Pong(_) => {}
+        }
+    }
+}
+
+impl<T: StringRef> HasChannel<T> for Event<T> {
+    fn channel(&self) -> Option<&T> {
+        match self {
+            Event::PrivMsg(data) => Some(data.channel()),
+            Event::Join(data) => Some(data.channel()),
+            Event::Mode(data) => Some(data.channel()),
+            Event::Names(data) => Some(data.channel()),
+            Event::EndOfNames(data) => Some(data.channel()),
+            Event::Part(data) => Some(data.channel()),
+            Event::ClearChat(data) => Some(data.channel()),
+            Event::ClearMsg(data) => Some(data.channel()),
+            Event::Host(data) => Some(data.hosting_channel()),
+            Event::Notice(data) => Some(data.channel()),
+            Event::RoomState(data) => Some(data.channel()),
+            Event::UserNotice(data) => Some(data.channel()),
+            Event::UserState(data) => Some(data.channel()),
+            Event::MessageRejected(event) => Some(event.channel()),
+            Event::Whisper(_)
+            | Event::Reconnect(_)
+            | Event::Capability(_)
+            | Event::ConnectMessage(_)
+            | Event::GlobalUserState(_)
+            | Event::Close(_)
+            | Event::Reconnecting(_)
+            | Event::Connected(_)
+            | Event::Authenticated(_)
+            | Event::Ping(_)
+            | Event::Pong(_)
+            | Event::Unknown(_) => None,
+        }
+    }
 }
 
 #[inline]
@@ -129,6 +236,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                         *msg.param(1),
                     )),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
@@ -136,12 +244,14 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                 sender,
                 event: WhisperEvent::<&str>::new(msg.try_param(0)?, msg.try_param(1)?),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "JOIN" => EventData {
                 sender,
                 event: JoinEvent::from(ChannelEvent::<&str>::new(msg.try_param(0)?)),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "MODE" => {
@@ -150,6 +260,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                     sender,
                     event: ModeChangeEvent::<&str>::new(msg.param(0), msg.param(1), msg.param(2)),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
@@ -163,6 +274,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                         msg.param(3).split(' ').collect(),
                     ),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
@@ -170,12 +282,14 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                 sender,
                 event: EndOfNamesEvent::from(ChannelEvent::<&str>::new(msg.try_param(1)?)),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "PART" => EventData {
                 sender,
                 event: PartEvent::from(ChannelEvent::<&str>::new(msg.try_param(0)?)),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "CLEARCHAT" => EventData {
@@ -185,6 +299,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                     msg.params().get(1).copied(),
                 )),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "CLEARMSG" => {
@@ -196,25 +311,24 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                         *msg.try_param(1)?,
                     )),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
             "HOSTTARGET" => {
-                let host_parts: Vec<_> = msg.param(1).split(' ').collect();
-                let hosting_channel = msg.param(0);
-                let target_channel = if host_parts[0] == "-" {
-                    None
-                } else {
-                    host_parts.get(0).copied()
-                };
-                let viewer_count = host_parts
-                    .get(1)
-                    .copied()
-                    .and_then(|num| num.parse::<usize>().ok());
+                // `<target> <viewers>` when hosting starts, `-` when it ends. Either part can be
+                // `-` when it's unknown.
+                let hosting_channel = msg.try_param(0)?;
+                let mut host_parts = msg.params().get(1).copied().unwrap_or("").split(' ');
+                let target_channel = host_parts
+                    .next()
+                    .filter(|target| !target.is_empty() && *target != "-");
+                let viewer_count = host_parts.next().and_then(|num| num.parse::<usize>().ok());
                 EventData {
                     sender,
                     event: HostEvent::<&str>::new(hosting_channel, target_channel, viewer_count),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
@@ -227,6 +341,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                         msg.param(1),
                     )),
                     tags: msg.tags,
+                    raw: None,
                 }
                 .into()
             }
@@ -234,30 +349,32 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                 sender,
                 event: ReconnectEvent,
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "ROOMSTATE" => EventData {
                 sender,
                 event: RoomStateEvent::from(ChannelEvent::<&str>::new(msg.try_param(0)?)),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
-            "USERNOTICE" => {
-                check_parameter_count(2, &msg)?;
-                EventData {
-                    sender,
-                    event: UserNoticeEvent::from(ChannelMessageEvent::<&str>::new(
-                        msg.param(0),
-                        msg.param(1),
-                    )),
-                    tags: msg.tags,
-                }
-                .into()
+            // notices like raids don't include a user message, their message is empty then
+            "USERNOTICE" => EventData {
+                sender,
+                event: UserNoticeEvent::from(ChannelMessageEvent::<&str>::new(
+                    *msg.try_param(0)?,
+                    msg.params().get(1).copied().unwrap_or(""),
+                )),
+                tags: msg.tags,
+                raw: None,
             }
+            .into(),
             "USERSTATE" => EventData {
                 sender,
                 event: UserStateEvent::from(ChannelEvent::<&str>::new(msg.try_param(0)?)),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "CAP" => EventData {
@@ -266,6 +383,7 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                     params: msg.params().to_vec(),
                 },
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             RPL_WELCOME | RPL_YOURHOST | RPL_CREATED | RPL_MYINFO | RPL_MOTDSTART | RPL_MOTD
@@ -273,17 +391,28 @@ impl<'a> TryFrom<IrcMessage<&'a str>> for Event<&'a str> {
                 sender,
                 event: ConnectMessageEvent::new(msg.command, msg.params().to_vec()),
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
             "GLOBALUSERSTATE" => EventData {
                 sender,
                 event: GlobalUserStateEvent,
                 tags: msg.tags,
+                raw: None,
             }
             .into(),
-            "PING" => PingEvent.into(),
+            "PING" => PingEvent::new(msg.command_params).into(),
             "PONG" => PongEvent.into(),
-            _ => return Err(Error::UnknownIrcCommand((&msg).into())),
+            _ => EventData {
+                sender,
+                event: UnknownEvent::from(ConnectMessageEvent::new(
+                    msg.command,
+                    msg.params().to_vec(),
+                )),
+                tags: msg.tags,
+                raw: None,
+            }
+            .into(),
         })
     }
 }
@@ -309,6 +438,7 @@ impl<T: Copy> ToOwnedEvent for T {
 /// Helper functions to access the contained data (message, target, specific tags) are implemented
 /// for for the EventContent<T, Inner> types they apply to.  
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct EventData<T, Inner>
 where
     T: StringRef,
@@ -320,6 +450,9 @@ where
     pub(crate) event: Inner,
     /// Map of IRCv3 tags
     pub(crate) tags: Option<FnvHashMap<T, String>>,
+    /// The IRC line the event was parsed from, only kept when enabled on the stream
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) raw: Option<Arc<str>>,
 }
 
 /// Methods common to all EventContent variants
@@ -338,6 +471,13 @@ where
     pub fn event(&self) -> &Inner {
         &self.event
     }
+
+    /// Get the IRC line this event was parsed from, without the trailing line break. Only
+    /// available when keeping raw lines was enabled, see
+    /// [`TwitchChatStream::keep_raw_lines`](struct.TwitchChatStream.html#method.keep_raw_lines).
+    pub fn raw(&self) -> Option<&str> {
+        self.raw.as_deref()
+    }
 }
 
 impl<T, Inner> MessageTags<T> for EventData<T, Inner>
@@ -355,7 +495,8 @@ where
     fn tag<Q: Borrow<str>>(&self, key: Q) -> Option<&str> {
         self.tags
             .as_ref()
-            .and_then(|tags| tags.get(key.borrow()).map(|s| s.as_str()))
+            .and_then(|tags| tags.get(key.borrow()))
+            .map(|s| s.as_str())
     }
 
     /// Gets a tag value, returns an Error if the value is not set. Intended for use in
@@ -387,6 +528,34 @@ where
                     .map(|(key, val)| (key.ref_to_string(), val.ref_to_string()))
                     .collect::<FnvHashMap<String, String>>()
             }),
+            raw: self.raw.clone(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use crate::event::{Event, MessageTags};
+    use crate::util::test_events::parse;
+
+    #[test]
+    fn test_serde_round_trip() {
+        let event = Event::<String>::from(&parse(
+            "@badges=;color=#0000FF;display-name=Ronni;id=b34ccfc7-4977-403a-8a94-33c6bac34fb8 \
+             :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :Kappa Keepo Kappa",
+        ));
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            json["PrivMsg"]["tags"]["display-name"],
+            serde_json::json!("Ronni")
+        );
+
+        let deserialized: Event<String> = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized, event);
+        match deserialized {
+            Event::PrivMsg(event) => assert_eq!(event.tag("color"), Some("#0000FF")),
+            other => panic!("expected privmsg, got {:?}", other),
         }
     }
 }
@@ -1,7 +1,8 @@
 use core::fmt;
 use core::pin::Pin;
 use std::convert::TryFrom;
-use std::iter::FromIterator;
+use std::ops::Range;
+use std::sync::Arc;
 
 use futures_core::stream::FusedStream;
 use futures_core::task::{Context, Poll};
@@ -13,10 +14,11 @@ use tokio_tungstenite::tungstenite::error::Error as WsError;
 use tokio_tungstenite::tungstenite::Message;
 
 use crate::event::{CloseEvent, Event};
-use crate::irc::IrcMessage;
+use crate::irc::{lowercase_tag_keys, IrcMessage};
 use crate::Error;
 
-type EventBuffer = SmallVec<[Result<Event<String>, Error>; 10]>;
+type ParseBuffer<T> = SmallVec<[Result<T, Error>; 10]>;
+type EventBuffer = ParseBuffer<Event<String>>;
 
 /// A wrapper around the websocket stream that parses incoming IRC messages into event structs
 /// and formats Message or Command structs as IRC messages.
@@ -24,6 +26,9 @@ type EventBuffer = SmallVec<[Result<Event<String>, Error>; 10]>;
 pub struct TwitchChatStream<St> {
     stream: St,
     buffer: Option<EventBuffer>,
+    keep_raw_lines: bool,
+    strict: bool,
+    lowercase_tag_keys: bool,
 }
 
 impl<St: Unpin> Unpin for TwitchChatStream<St> {}
@@ -35,6 +40,9 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("TwitchChatStream")
             .field("stream", &self.stream)
+            .field("keep_raw_lines", &self.keep_raw_lines)
+            .field("strict", &self.strict)
+            .field("lowercase_tag_keys", &self.lowercase_tag_keys)
             .finish()
     }
 }
@@ -50,9 +58,33 @@ where
         TwitchChatStream {
             stream,
             buffer: None,
+            keep_raw_lines: false,
+            strict: false,
+            lowercase_tag_keys: false,
         }
     }
 
+    /// Set whether IRC commands that are not known to this library are returned as
+    /// `Error::UnknownIrcCommand` instead of `Event::Unknown`. Disabled by default.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Set whether events keep a copy of the IRC line they were parsed from, available through
+    /// `Event::raw`. Disabled by default because it allocates for every event.
+    pub fn keep_raw_lines(mut self, keep: bool) -> Self {
+        self.keep_raw_lines = keep;
+        self
+    }
+
+    /// Set whether tag keys are lowercased before parsing, so tags sent with unusual case are
+    /// found by their lowercase names. Disabled by default, keys are kept as they were sent.
+    pub fn lowercase_tag_keys(mut self, lowercase: bool) -> Self {
+        self.lowercase_tag_keys = lowercase;
+        self
+    }
+
     /// Acquires a reference to the underlying stream that this combinator is
     /// pulling from.
     pub fn get_ref(&self) -> &St {
@@ -111,9 +143,16 @@ where
         }
 
         // otherwise, poll underlying stream
-        let parse_result = Pin::new(&mut unpin_self.stream)
-            .poll_next(cx)
-            .map(|opt| opt.map(parse));
+        let (keep_raw_lines, strict, lowercase_tag_keys) = (
+            unpin_self.keep_raw_lines,
+            unpin_self.strict,
+            unpin_self.lowercase_tag_keys,
+        );
+        let parse_result = Pin::new(&mut unpin_self.stream).poll_next(cx).map(|opt| {
+            opt.map(|msg_result| {
+                parse_events(msg_result, keep_raw_lines, strict, lowercase_tag_keys)
+            })
+        });
 
         match parse_result {
             Poll::Ready(result) => {
@@ -164,17 +203,212 @@ where
     }
 }
 
-fn parse(msg_result: Result<Message, WsError>) -> EventBuffer {
+/// Like [`TwitchChatStream`](struct.TwitchChatStream.html), but yields the parsed IRC messages
+/// without converting them to events. Useful to handle commands that are not modeled as events.
+#[must_use = "streams do nothing unless polled"]
+pub struct IrcMessageStream<St> {
+    stream: St,
+    buffer: ParseBuffer<IrcMessage<String>>,
+    lowercase_tag_keys: bool,
+}
+
+impl<St: Unpin> Unpin for IrcMessageStream<St> {}
+
+impl<St> fmt::Debug for IrcMessageStream<St>
+where
+    St: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IrcMessageStream")
+            .field("stream", &self.stream)
+            .field("lowercase_tag_keys", &self.lowercase_tag_keys)
+            .finish()
+    }
+}
+
+impl<St> IrcMessageStream<St>
+where
+    St: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    /// Create new `IrcMessageStream` from a stream of websocket messages
+    pub fn new(stream: St) -> IrcMessageStream<St> {
+        IrcMessageStream {
+            stream,
+            buffer: SmallVec::new(),
+            lowercase_tag_keys: false,
+        }
+    }
+
+    /// Set whether tag keys are lowercased before parsing. Disabled by default, see
+    /// [`TwitchChatStream::lowercase_tag_keys`](struct.TwitchChatStream.html#method.lowercase_tag_keys).
+    pub fn lowercase_tag_keys(mut self, lowercase: bool) -> Self {
+        self.lowercase_tag_keys = lowercase;
+        self
+    }
+
+    /// Acquires a reference to the underlying stream that this combinator is
+    /// pulling from.
+    pub fn get_ref(&self) -> &St {
+        &self.stream
+    }
+
+    /// Acquires a mutable reference to the underlying stream that this
+    /// combinator is pulling from.
+    ///
+    /// Note that care must be taken to avoid tampering with the state of the
+    /// stream which may otherwise confuse this combinator.
+    pub fn get_mut(&mut self) -> &mut St {
+        &mut self.stream
+    }
+
+    /// Consumes this combinator, returning the underlying stream.
+    ///
+    /// Note that this may discard intermediate state of this combinator, so
+    /// care should be taken to avoid losing resources when this is called.
+    pub fn into_inner(self) -> St {
+        self.stream
+    }
+}
+
+impl<St> FusedStream for IrcMessageStream<St>
+where
+    St: FusedStream + Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    fn is_terminated(&self) -> bool {
+        self.buffer.is_empty() && self.stream.is_terminated()
+    }
+}
+
+impl<St> Stream for IrcMessageStream<St>
+where
+    St: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    type Item = Result<IrcMessage<String>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let unpin_self = Pin::into_inner(self);
+        loop {
+            if let Some(item) = unpin_self.buffer.pop() {
+                return Poll::Ready(Some(item));
+            }
+            match Pin::new(&mut unpin_self.stream).poll_next(cx) {
+                Poll::Ready(Some(msg_result)) => {
+                    unpin_self.buffer =
+                        parse_irc_messages(msg_result, unpin_self.lowercase_tag_keys);
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+// Forwarding impl of Sink from the underlying stream
+impl<St, IntoMessage> Sink<IntoMessage> for IrcMessageStream<St>
+where
+    St: Stream + Sink<Message> + Unpin,
+    Message: From<IntoMessage>,
+{
+    type Error = St::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut Pin::into_inner(self).stream).poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: IntoMessage) -> Result<(), Self::Error> {
+        let msg = Message::from(item);
+        debug!("> {}", msg);
+        Pin::new(&mut Pin::into_inner(self).stream).start_send(msg)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut Pin::into_inner(self).stream).poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut Pin::into_inner(self).stream).poll_close(cx)
+    }
+}
+
+fn parse_events(
+    msg_result: Result<Message, WsError>,
+    keep_raw_lines: bool,
+    strict: bool,
+    lowercase_tag_keys: bool,
+) -> EventBuffer {
+    parse(
+        msg_result,
+        |irc_msg, line| {
+            let event = Event::try_from(irc_msg)?;
+            if strict && matches!(event, Event::Unknown(_)) {
+                // the message was consumed by the conversion, unknown commands are rare enough
+                // to parse their line again instead of keeping a copy of every message
+                if let Ok((_, original)) = IrcMessage::parse(line) {
+                    return Err(Error::UnknownIrcCommand((&original).into()));
+                }
+            }
+            let mut event = Event::<String>::from(&event);
+            if keep_raw_lines {
+                event.set_raw(Arc::from(line));
+            }
+            Ok(event)
+        },
+        Some(CloseEvent.into()),
+        lowercase_tag_keys,
+        keep_raw_lines,
+    )
+}
+
+fn parse_irc_messages(
+    msg_result: Result<Message, WsError>,
+    lowercase_tag_keys: bool,
+) -> ParseBuffer<IrcMessage<String>> {
+    parse(
+        msg_result,
+        |irc_msg, _line| Ok(IrcMessage::<String>::from(&irc_msg)),
+        None,
+        lowercase_tag_keys,
+        false,
+    )
+}
+
+/// Parses a websocket message into a buffer of items in reverse order, converting each contained
+/// IRC message and the line it was parsed from using `convert`. `close` is returned when a close
+/// frame is received. Tag keys are lowercased first if `lowercase_keys` is set. With
+/// `keep_original`, `convert` gets the line as it was received instead of the lowercased one.
+fn parse<T>(
+    msg_result: Result<Message, WsError>,
+    convert: impl Fn(IrcMessage<&str>, &str) -> Result<T, Error>,
+    close: Option<T>,
+    lowercase_keys: bool,
+    keep_original: bool,
+) -> ParseBuffer<T> {
     match msg_result {
         Ok(msg) => match msg {
-            Message::Text(msg) => {
+            Message::Text(mut msg) => {
                 debug!("< {}", msg.trim());
+                // lowercasing keeps the length, so lines are found at the same offsets
+                let original = if lowercase_keys && keep_original {
+                    Some(msg.clone())
+                } else {
+                    None
+                };
+                if lowercase_keys {
+                    lowercase_tag_keys(&mut msg);
+                }
                 match IrcMessage::<&str>::parse_many(&msg) {
                     Ok((_remaining, messages)) => {
-                        let event_results = messages.into_iter().map(|irc_msg| {
-                            Event::try_from(irc_msg).map(|event| Event::<String>::from(&event))
-                        });
-                        SmallVec::from_iter(event_results.rev())
+                        let mut items = SmallVec::with_capacity(messages.len());
+                        for irc_msg in messages.into_iter().rev() {
+                            let line = line_range(&msg, irc_msg.command);
+                            let line = &original.as_deref().unwrap_or(&msg)[line];
+                            items.push(convert(irc_msg, line));
+                        }
+                        items
                     }
                     Err(err) => {
                         error!("IRC parse error: {:?}", err);
@@ -191,7 +425,7 @@ fn parse(msg_result: Result<Message, WsError>) -> EventBuffer {
                     info!("Received close frame: {}", close_frame)
                 }
                 info!("Connection closed by the server.");
-                smallvec![Ok(CloseEvent.into())]
+                close.into_iter().map(Ok).collect()
             }
             Message::Ping(_payload) => {
                 debug!("< WS PING");
@@ -205,3 +439,151 @@ fn parse(msg_result: Result<Message, WsError>) -> EventBuffer {
         Err(e) => smallvec::smallvec![Err(e.into())],
     }
 }
+
+/// Finds the byte range of the line of `text` that contains `part`, which has to be a slice of
+/// `text`
+fn line_range(text: &str, part: &str) -> Range<usize> {
+    let offset = part.as_ptr() as usize - text.as_ptr() as usize;
+    let start = text[..offset].rfind("\r\n").map_or(0, |i| i + 2);
+    let end = text[offset..]
+        .find("\r\n")
+        .map_or(text.len(), |i| offset + i);
+    start..end
+}
+
+#[cfg(test)]
+mod test {
+    use futures_util::{stream, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    use crate::event::tags::{MessageTags, UserNoticeKind};
+    use crate::event::{ConnectMessageEventData, Event, IrcMessageStream, TwitchChatStream};
+    use crate::Error;
+
+    #[tokio::test]
+    async fn test_irc_message_stream() {
+        let ws_messages = vec![Ok(Message::Text(
+            "@badges=;color=#0000FF :tester!tester@tester.tmi.twitch.tv PRIVMSG #channel :hello there\r\n\
+             PING :tmi.twitch.tv"
+                .to_string(),
+        ))];
+        let mut stream = IrcMessageStream::new(stream::iter(ws_messages));
+
+        let privmsg = stream.next().await.unwrap().unwrap();
+        assert_eq!(privmsg.command, "PRIVMSG");
+        assert_eq!(privmsg.params(), &["#channel", "hello there"]);
+        assert_eq!(
+            privmsg.tags.unwrap().get("color").map(String::as_str),
+            Some("#0000FF")
+        );
+        let ping = stream.next().await.unwrap().unwrap();
+        assert_eq!(ping.command, "PING");
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_raw_lines() {
+        let frame = "@badges=;color=#0000FF :tester!tester@tester.tmi.twitch.tv PRIVMSG #channel :hello there\r\n\
+                     :tester!tester@tester.tmi.twitch.tv JOIN #channel\r\n";
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages)).keep_raw_lines(true);
+
+        let privmsg = stream.next().await.unwrap().unwrap();
+        assert!(matches!(privmsg, Event::PrivMsg(_)));
+        assert_eq!(
+            privmsg.raw(),
+            Some(
+                "@badges=;color=#0000FF :tester!tester@tester.tmi.twitch.tv PRIVMSG #channel :hello there"
+            )
+        );
+        let join = stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            join.raw(),
+            Some(":tester!tester@tester.tmi.twitch.tv JOIN #channel")
+        );
+        assert!(stream.next().await.is_none());
+
+        // the raw line keeps the tag keys as they were sent
+        let frame = "@Display-Name=Ronni :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi";
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages))
+            .keep_raw_lines(true)
+            .lowercase_tag_keys(true);
+        assert_eq!(stream.next().await.unwrap().unwrap().raw(), Some(frame));
+
+        // not kept by default
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages));
+        assert_eq!(stream.next().await.unwrap().unwrap().raw(), None);
+    }
+
+    #[tokio::test]
+    async fn test_lowercase_tag_keys() {
+        let frame = "@Display-Name=Ronni :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi";
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages));
+        match stream.next().await.unwrap().unwrap() {
+            Event::PrivMsg(event) => {
+                assert_eq!(event.tag("Display-Name"), Some("Ronni"));
+                assert_eq!(event.tag("display-name"), None);
+            }
+            _ => panic!("expected a PRIVMSG"),
+        }
+
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages)).lowercase_tag_keys(true);
+        match stream.next().await.unwrap().unwrap() {
+            Event::PrivMsg(event) => assert_eq!(event.tag("display-name"), Some("Ronni")),
+            _ => panic!("expected a PRIVMSG"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_raid() {
+        let frame = "@badge-info=;badges=turbo/1;color=#9ACD32;display-name=TestChannel;emotes=;\
+                     id=3d830f12-795c-447d-af3c-ea05e40fbddb;login=testchannel;mod=0;msg-id=raid;\
+                     msg-param-displayName=TestChannel;msg-param-login=testchannel;\
+                     msg-param-viewerCount=15;room-id=56789;subscriber=0;\
+                     system-msg=15\\sraiders\\sfrom\\sTestChannel\\shave\\sjoined\\n!;\
+                     tmi-sent-ts=1507246572675;turbo=1;user-id=123456;user-type= \
+                     :tmi.twitch.tv USERNOTICE #othertestchannel";
+        for lowercase in &[false, true] {
+            let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+            let mut stream =
+                TwitchChatStream::new(stream::iter(ws_messages)).lowercase_tag_keys(*lowercase);
+            match stream.next().await.unwrap().unwrap() {
+                Event::UserNotice(event) => assert_eq!(
+                    event.sub_event().unwrap(),
+                    UserNoticeKind::Raid {
+                        from: "testchannel",
+                        display_name: Some("TestChannel"),
+                        viewer_count: 15,
+                    }
+                ),
+                _ => panic!("expected a USERNOTICE"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_commands() {
+        let frame = "@some-tag=1 :tmi.twitch.tv NEWCOMMAND #dallas :some content";
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages));
+        let event = stream.next().await.unwrap().unwrap();
+        assert!(matches!(&event, Event::Unknown(data) if data.command() == "NEWCOMMAND"));
+
+        let ws_messages = vec![Ok(Message::Text(frame.to_string()))];
+        let mut stream = TwitchChatStream::new(stream::iter(ws_messages)).strict(true);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert!(matches!(err, Error::UnknownIrcCommand(_)));
+        let irc_message = err.irc_message().unwrap();
+        assert_eq!(irc_message.command, "NEWCOMMAND");
+        assert_eq!(irc_message.command_params, vec!["#dallas", "some content"]);
+        assert_eq!(irc_message.tags.as_ref().unwrap()["some-tag"], "1");
+        assert_eq!(
+            irc_message.prefix.as_ref().unwrap().host.as_deref(),
+            Some("tmi.twitch.tv")
+        );
+    }
+}
@@ -36,16 +36,18 @@
 //! ```
 
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use fnv::FnvHashMap;
-use nom::character::complete::{alpha1, char, digit1};
+use nom::bytes::complete::take_while1;
+use nom::character::complete::{char, digit1};
 use nom::multi::{separated_list, separated_nonempty_list};
 use nom::sequence::{separated_pair, tuple};
 use nom::IResult;
 
 use crate::event::inner_data::{
-    ClearChatEvent, ClearMsgEvent, GlobalUserStateEvent, PrivMsgEvent, RoomStateEvent,
+    ClearChatEvent, ClearMsgEvent, GlobalUserStateEvent, NoticeEvent, PrivMsgEvent, RoomStateEvent,
     UserNoticeEvent, UserStateEvent,
 };
 use crate::event::{EventData, WhisperEvent};
@@ -56,8 +58,27 @@ pub trait MessageTags<T> {
     /// Get the map of all IRCv3 tags.
     fn tags(&self) -> &Option<FnvHashMap<T, String>>;
 
+    /// Copy all IRCv3 tags into a standard `HashMap`. Empty if the message has no tags.
+    fn tags_owned(&self) -> HashMap<String, String>
+    where
+        T: Borrow<str>,
+    {
+        self.tags()
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key.borrow().to_string(), value.clone()))
+            .collect()
+    }
+
     /// Get a tag value from the message by its key. `None` for tags that are not present
     /// as well as tags that are set but empty.
+    ///
+    /// Keys are compared exactly. Most of Twitch's tag keys are lowercase, but some are not, like
+    /// `msg-param-viewerCount` on raids. To look up tags from sources with inconsistent case by
+    /// their lowercase names, enable
+    /// [`TwitchChatStream::lowercase_tag_keys`](../struct.TwitchChatStream.html#method.lowercase_tag_keys)
+    /// or normalize lines with [`lowercase_tag_keys`](../../irc/fn.lowercase_tag_keys.html)
+    /// before parsing them.
     fn tag<Q: Borrow<str>>(&self, key: Q) -> Option<&str>;
 
     /// Gets a tag value, returns an Error if the value is not set or empty. Intended for use in
@@ -120,6 +141,41 @@ pub trait BadgeTags<T: StringRef>: MessageTags<T> {
             Ok(vec![])
         }
     }
+
+    /// `badges` tag as a [`BadgeMap`](struct.BadgeMap.html) to look up badges by name
+    fn badges_map<'a>(&'a self) -> Result<BadgeMap<'a>, Error>
+    where
+        T: 'a,
+    {
+        self.badges().map(BadgeMap)
+    }
+
+    /// Number of months the user has been subscribed, `None` if the user is not a subscriber.
+    /// Taken from the exact count in `badge-info`, or from the `subscriber` badge version if that
+    /// is missing. The badge version only reflects the badge tier the user reached, like 3, 6 or
+    /// 12 months. Founders show the founder badge instead of the subscriber badge, their count is
+    /// in the `founder` entry of `badge-info`.
+    fn subscriber_months(&self) -> Result<Option<usize>, Error> {
+        let badge_info = BadgeMap(self.badge_info()?);
+        let info_months = badge_info
+            .badge_version(KnownBadge::Subscriber.name())
+            .or_else(|| badge_info.badge_version(KnownBadge::Founder.name()));
+        let version = match info_months {
+            Some(months) => Some((months, false)),
+            None => self
+                .badges_map()?
+                .badge_version(KnownBadge::Subscriber.name())
+                .map(|version| (version, true)),
+        };
+        match version {
+            Some((version, is_badge)) => usize::from_str(version)
+                // badge versions of tier 2 and 3 subscriptions are prefixed, e.g. 2012 or 3012
+                .map(|months| if is_badge { months % 1000 } else { months })
+                .map(Some)
+                .map_err(|_| Error::TagParseError("badges".to_string(), version.to_string())),
+            None => Ok(None),
+        }
+    }
 }
 impl<T: StringRef> BadgeTags<T> for EventData<T, GlobalUserStateEvent> {}
 impl<T: StringRef> BadgeTags<T> for EventData<T, PrivMsgEvent<T>> {}
@@ -188,7 +244,7 @@ impl<T: StringRef> UserIdTag<T> for EventData<T, UserNoticeEvent<T>> {}
 impl<T: StringRef> UserIdTag<T> for EventData<T, PrivMsgEvent<T>> {}
 impl<T: StringRef> UserIdTag<T> for EventData<T, WhisperEvent<T>> {}
 
-/// Access to `mod` tag
+/// Access to `mod` tag and the broadcaster badge
 pub trait ModTag<T: StringRef>: MessageTags<T> {
     /// `mod` tag
     #[inline]
@@ -198,11 +254,83 @@ pub trait ModTag<T: StringRef>: MessageTags<T> {
             _ => false,
         }
     }
+
+    /// Whether the user is the broadcaster of the channel, from the `broadcaster` badge in the
+    /// `badges` tag. `false` if the badges can't be parsed.
+    fn is_broadcaster(&self) -> bool {
+        match self.tag("badges") {
+            Some(badges) => match parse_badges(badges, "badges") {
+                Ok(badges) => badges
+                    .iter()
+                    .any(|badge| badge.known() == Some(KnownBadge::Broadcaster)),
+                Err(_) => false,
+            },
+            None => false,
+        }
+    }
 }
 impl<T: StringRef> ModTag<T> for EventData<T, UserStateEvent<T>> {}
 impl<T: StringRef> ModTag<T> for EventData<T, PrivMsgEvent<T>> {}
 impl<T: StringRef> ModTag<T> for EventData<T, UserNoticeEvent<T>> {}
 
+/// Access to the `first-msg` and `returning-chatter` tags
+pub trait ChatterTags<T: StringRef>: MessageTags<T> {
+    /// `first-msg` tag. Set when this is the user's first message in the channel.
+    #[inline]
+    fn is_first_msg(&self) -> bool {
+        matches!(self.tag("first-msg"), Some("1"))
+    }
+
+    /// `returning-chatter` tag. Set when the user is a returning chatter in the channel.
+    #[inline]
+    fn is_returning_chatter(&self) -> bool {
+        matches!(self.tag("returning-chatter"), Some("1"))
+    }
+}
+impl<T: StringRef> ChatterTags<T> for EventData<T, PrivMsgEvent<T>> {}
+
+/// Value of the `user-type` tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserType {
+    /// Regular user, the tag is empty
+    Normal,
+    /// Channel moderator
+    Mod,
+    /// Global moderator
+    GlobalMod,
+    /// Twitch administrator
+    Admin,
+    /// Twitch employee
+    Staff,
+    /// Any other value Twitch might send
+    Other(String),
+}
+
+impl From<&str> for UserType {
+    fn from(user_type: &str) -> Self {
+        match user_type {
+            "" => UserType::Normal,
+            "mod" => UserType::Mod,
+            "global_mod" => UserType::GlobalMod,
+            "admin" => UserType::Admin,
+            "staff" => UserType::Staff,
+            other => UserType::Other(other.to_string()),
+        }
+    }
+}
+
+/// Access to `user-type` tag
+pub trait UserTypeTag<T: StringRef>: MessageTags<T> {
+    /// `user-type` tag, `UserType::Normal` if it is empty or missing
+    #[inline]
+    fn user_type(&self) -> UserType {
+        UserType::from(self.tag("user-type").unwrap_or(""))
+    }
+}
+impl<T: StringRef> UserTypeTag<T> for EventData<T, GlobalUserStateEvent> {}
+impl<T: StringRef> UserTypeTag<T> for EventData<T, PrivMsgEvent<T>> {}
+impl<T: StringRef> UserTypeTag<T> for EventData<T, UserStateEvent<T>> {}
+
 /// Access to `bits` tag
 pub trait BitsTag<T: StringRef>: MessageTags<T> {
     /// `bits` tag
@@ -213,6 +341,109 @@ pub trait BitsTag<T: StringRef>: MessageTags<T> {
 }
 impl<T: StringRef> BitsTag<T> for EventData<T, PrivMsgEvent<T>> {}
 
+/// Access to `custom-reward-id` tag
+pub trait CustomRewardTag<T: StringRef>: MessageTags<T> {
+    /// `custom-reward-id` tag. ID of the channel points reward, set when the message was sent
+    /// by redeeming a reward that requires the user to enter text.
+    #[inline]
+    fn custom_reward_id(&self) -> Option<&str> {
+        self.tag("custom-reward-id")
+    }
+}
+impl<T: StringRef> CustomRewardTag<T> for EventData<T, PrivMsgEvent<T>> {}
+
+/// Access to the `pinned-chat-paid-*` tags that are set on Hype Chat messages
+pub trait HypeChatTags<T: StringRef>: MessageTags<T> {
+    /// `pinned-chat-paid-amount` tag. The amount paid, in the smallest unit of the currency
+    /// (e.g. cents for USD). None if the message is not a Hype Chat or the value is invalid.
+    #[inline]
+    fn hype_chat_amount(&self) -> Option<usize> {
+        self.tag("pinned-chat-paid-amount")
+            .and_then(|t| usize::from_str(t).ok())
+    }
+
+    /// `pinned-chat-paid-exponent` tag. Number of decimal places of the currency, the amount in
+    /// the currency's main unit is `hype_chat_amount() / 10^exponent` (e.g. 2 for USD, 0 for JPY).
+    #[inline]
+    fn hype_chat_exponent(&self) -> Option<u32> {
+        self.tag("pinned-chat-paid-exponent")
+            .and_then(|t| u32::from_str(t).ok())
+    }
+
+    /// `pinned-chat-paid-currency` tag. ISO 4217 code of the currency paid in, like `USD`.
+    #[inline]
+    fn hype_chat_currency(&self) -> Option<&str> {
+        self.tag("pinned-chat-paid-currency")
+    }
+
+    /// `pinned-chat-paid-level` tag. Level of the Hype Chat, from `ONE` to `TEN`.
+    #[inline]
+    fn hype_chat_level(&self) -> Option<&str> {
+        self.tag("pinned-chat-paid-level")
+    }
+}
+impl<T: StringRef> HypeChatTags<T> for EventData<T, PrivMsgEvent<T>> {}
+
+/// Tags that are set on PRIVMSGs that were sent with a special effect or as part of a Crowd Chant
+pub trait PrivMsgTags<T: StringRef>: MessageTags<T> {
+    /// `msg-id` tag. Kind of effect the message was sent with, like `highlighted-message` or
+    /// `gigantified-emote-message`. None for regular messages.
+    #[inline]
+    fn msg_id(&self) -> Option<&str> {
+        self.tag("msg-id")
+    }
+
+    /// `crowd-chant-parent-msg-id` tag. ID of the message that a Crowd Chant message joins in on.
+    #[inline]
+    fn crowd_chant_parent_msg_id(&self) -> Option<&str> {
+        self.tag("crowd-chant-parent-msg-id")
+    }
+}
+impl<T: StringRef> PrivMsgTags<T> for EventData<T, PrivMsgEvent<T>> {}
+
+/// Access to the `reply-parent-*` tags that are set when a message is a reply to another message
+pub trait ReplyTags<T: StringRef>: MessageTags<T> {
+    /// Whether the message is a reply to another message
+    #[inline]
+    fn is_reply(&self) -> bool {
+        self.reply_parent_msg_id().is_some()
+    }
+
+    /// `reply-parent-msg-id` tag. ID of the message that was replied to.
+    #[inline]
+    fn reply_parent_msg_id(&self) -> Option<&str> {
+        self.tag("reply-parent-msg-id")
+    }
+
+    /// `reply-parent-user-id` tag. ID of the user who sent the message that was replied to.
+    #[inline]
+    fn reply_parent_user_id(&self) -> Option<&str> {
+        self.tag("reply-parent-user-id")
+    }
+
+    /// `reply-parent-user-login` tag. Login name of the user who sent the message that was
+    /// replied to.
+    #[inline]
+    fn reply_parent_user_login(&self) -> Option<&str> {
+        self.tag("reply-parent-user-login")
+    }
+
+    /// `reply-parent-display-name` tag. Display name of the user who sent the message that was
+    /// replied to.
+    #[inline]
+    fn reply_parent_display_name(&self) -> Option<&str> {
+        self.tag("reply-parent-display-name")
+    }
+
+    /// `reply-parent-msg-body` tag. Text of the message that was replied to. Tag values are
+    /// unescaped while parsing, so this is the original text including spaces and semicolons.
+    #[inline]
+    fn reply_parent_msg_body(&self) -> Option<&str> {
+        self.tag("reply-parent-msg-body")
+    }
+}
+impl<T: StringRef> ReplyTags<T> for EventData<T, PrivMsgEvent<T>> {}
+
 /// Emote tag accessor
 pub trait EmotesTag<T: StringRef>: MessageTags<T> {
     /// `emotes` tag
@@ -227,8 +458,8 @@ pub trait EmotesTag<T: StringRef>: MessageTags<T> {
     /// // `parsed` is some `Event<&str>` message with the emote tag emotes=25:0-4,12-16/1902:6-10
     /// if let Event::PrivMsg(event) = &parsed {
     ///     assert_eq!(event.emotes().unwrap(), vec![
-    ///         EmoteReplacement { emote_id: 25, indices: vec![(0, 4), (12, 16)] },
-    ///         EmoteReplacement { emote_id: 1902, indices: vec![(6, 10)] }
+    ///         EmoteReplacement { emote_id: "25".to_string(), indices: vec![(0, 4), (12, 16)] },
+    ///         EmoteReplacement { emote_id: "1902".to_string(), indices: vec![(6, 10)] }
     ///     ])
     /// }
     /// # assert!(match &parsed {
@@ -287,12 +518,122 @@ impl<T: StringRef> RoomIdTag<T> for EventData<T, RoomStateEvent<T>> {}
 /// Replacement instruction for an emote
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EmoteReplacement {
-    /// Emote ID
-    pub emote_id: usize,
-    /// Start and end index of the emote in the message
+    /// Emote ID, either numeric like `25` or prefixed like `emotesv2_dc24652ada1e4c84a5e3ceebae4de709`
+    pub emote_id: String,
+    /// Start and end index (both inclusive) of each occurrence of the emote in the message. The
+    /// indices count Unicode code points (`char`s), not bytes, use
+    /// [`substr_in`](#method.substr_in) to get the emote text.
     pub indices: Vec<(usize, usize)>,
 }
 
+impl EmoteReplacement {
+    /// URL of the emote image on the Twitch CDN
+    ///
+    /// ```
+    /// use tmi_rs::event::tags::{EmoteFormat, EmoteReplacement, EmoteScale, EmoteTheme};
+    ///
+    /// let emote = EmoteReplacement { emote_id: "25".to_string(), indices: vec![(0, 4)] };
+    /// assert_eq!(
+    ///     emote.cdn_url(EmoteScale::Large, EmoteTheme::Dark, EmoteFormat::Default),
+    ///     "https://static-cdn.jtvnw.net/emoticons/v2/25/default/dark/3.0"
+    /// );
+    /// ```
+    pub fn cdn_url(&self, scale: EmoteScale, theme: EmoteTheme, format: EmoteFormat) -> String {
+        format!(
+            "https://static-cdn.jtvnw.net/emoticons/v2/{}/{}/{}/{}",
+            self.emote_id,
+            format.as_str(),
+            theme.as_str(),
+            scale.as_str()
+        )
+    }
+
+    /// The text of each occurrence of the emote in `message`, the message the emotes tag belongs
+    /// to. Occurrences outside of the message are skipped.
+    ///
+    /// ```
+    /// use tmi_rs::event::tags::EmoteReplacement;
+    ///
+    /// let emote = EmoteReplacement { emote_id: "25".to_string(), indices: vec![(2, 6)] };
+    /// assert_eq!(emote.substr_in("👋 Kappa"), vec!["Kappa"]);
+    /// ```
+    pub fn substr_in<'a>(&self, message: &'a str) -> Vec<&'a str> {
+        self.indices
+            .iter()
+            .filter_map(|&(start, end)| {
+                // byte offsets of every char, and of the end of the message
+                let mut offsets = message
+                    .char_indices()
+                    .map(|(offset, _)| offset)
+                    .chain(std::iter::once(message.len()));
+                let start_offset = offsets.nth(start)?;
+                let end_offset = offsets.nth(end.checked_sub(start)?)?;
+                message.get(start_offset..end_offset)
+            })
+            .collect()
+    }
+}
+
+/// Size of an emote image on the Twitch CDN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmoteScale {
+    /// 28x28 pixels
+    Small,
+    /// 56x56 pixels
+    Medium,
+    /// 112x112 pixels
+    Large,
+}
+
+impl EmoteScale {
+    fn as_str(self) -> &'static str {
+        match self {
+            EmoteScale::Small => "1.0",
+            EmoteScale::Medium => "2.0",
+            EmoteScale::Large => "3.0",
+        }
+    }
+}
+
+/// Background the emote image is intended for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmoteTheme {
+    /// Light background
+    Light,
+    /// Dark background
+    Dark,
+}
+
+impl EmoteTheme {
+    fn as_str(self) -> &'static str {
+        match self {
+            EmoteTheme::Light => "light",
+            EmoteTheme::Dark => "dark",
+        }
+    }
+}
+
+/// Image format of an emote on the Twitch CDN
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmoteFormat {
+    /// Animated version if the emote is animated, static otherwise
+    Default,
+    /// Always the static version
+    Static,
+    /// Animated version, only available for animated emotes
+    Animated,
+}
+
+impl EmoteFormat {
+    fn as_str(self) -> &'static str {
+        match self {
+            EmoteFormat::Default => "default",
+            EmoteFormat::Static => "static",
+            EmoteFormat::Animated => "animated",
+        }
+    }
+}
+
 /// Tags specific to USERNOTICE messages
 pub trait UserNoticeTags<T: StringRef>: MessageTags<T> {
     /// `msg-id` tag.
@@ -307,7 +648,8 @@ pub trait UserNoticeTags<T: StringRef>: MessageTags<T> {
 
     /// `system-msg` tag.
     ///
-    /// The message printed in chat along with this notice.
+    /// The message printed in chat along with this notice. Escape sequences like `\s` are
+    /// already unescaped.
     #[inline]
     fn system_msg(&self) -> Result<&str, Error> {
         self.required_tag("system-msg")
@@ -315,6 +657,151 @@ pub trait UserNoticeTags<T: StringRef>: MessageTags<T> {
 }
 impl<T: StringRef> UserNoticeTags<T> for EventData<T, UserNoticeEvent<T>> {}
 
+macro_rules! notice_ids {
+    ($($(#[$doc:meta])* $variant:ident => $msg_id:literal,)*) => {
+        /// Value of the `msg-id` tag on NOTICE messages
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+        pub enum NoticeId {
+            $($(#[$doc])* $variant,)*
+            /// Any value that is not covered by the other variants
+            Unknown(String),
+        }
+
+        impl From<&str> for NoticeId {
+            fn from(msg_id: &str) -> Self {
+                match msg_id {
+                    $($msg_id => NoticeId::$variant,)*
+                    other => NoticeId::Unknown(other.to_string()),
+                }
+            }
+        }
+    };
+}
+
+notice_ids! {
+    /// The user is already banned
+    AlreadyBanned => "already_banned",
+    /// Authentication failed
+    BadAuth => "bad_auth",
+    /// The user was banned
+    BanSuccess => "ban_success",
+    /// Emote only mode was turned off
+    EmoteOnlyOff => "emote_only_off",
+    /// Emote only mode was turned on
+    EmoteOnlyOn => "emote_only_on",
+    /// Followers only mode was turned off
+    FollowersOff => "followers_off",
+    /// Followers only mode was turned on with a minimum follow time
+    FollowersOn => "followers_on",
+    /// Followers only mode was turned on without a minimum follow time
+    FollowersOnZero => "followers_on_zero",
+    /// The channel stopped hosting
+    HostOff => "host_off",
+    /// The channel started hosting
+    HostOn => "host_on",
+    /// The hosted channel went offline
+    HostTargetWentOffline => "host_target_went_offline",
+    /// The message was rejected because the user is banned
+    MsgBanned => "msg_banned",
+    /// The channel is suspended
+    MsgChannelSuspended => "msg_channel_suspended",
+    /// The message was rejected because it is identical to the previous one
+    MsgDuplicate => "msg_duplicate",
+    /// The message was rejected because the channel is in emote only mode
+    MsgEmoteOnly => "msg_emoteonly",
+    /// The message was rejected because the channel is in followers only mode
+    MsgFollowersOnly => "msg_followersonly",
+    /// The message was rejected because the user does not follow the channel
+    MsgFollowersOnlyZero => "msg_followersonly_zero",
+    /// The message was rejected because it is not unique in r9k mode
+    MsgR9k => "msg_r9k",
+    /// The message was rejected because the user is sending messages too quickly
+    MsgRatelimit => "msg_ratelimit",
+    /// The message was rejected because of slow mode
+    MsgSlowMode => "msg_slowmode",
+    /// The message was rejected because the channel is in subscribers only mode
+    MsgSubsOnly => "msg_subsonly",
+    /// The message was rejected because the user's account is suspended
+    MsgSuspended => "msg_suspended",
+    /// The message was rejected because the user is timed out
+    MsgTimedOut => "msg_timedout",
+    /// The message was rejected because the channel requires a verified email
+    MsgVerifiedEmail => "msg_verified_email",
+    /// Response to `/mods` when the channel has no moderators
+    NoMods => "no_mods",
+    /// The user lacks the permission to execute the command
+    NoPermission => "no_permission",
+    /// Response to `/vips` when the channel has no VIPs
+    NoVips => "no_vips",
+    /// R9k mode was turned off
+    R9kOff => "r9k_off",
+    /// R9k mode was turned on
+    R9kOn => "r9k_on",
+    /// Response to `/mods` listing the moderators
+    RoomMods => "room_mods",
+    /// Slow mode was turned off
+    SlowOff => "slow_off",
+    /// Slow mode was turned on
+    SlowOn => "slow_on",
+    /// Subscribers only mode was turned off
+    SubsOff => "subs_off",
+    /// Subscribers only mode was turned on
+    SubsOn => "subs_on",
+    /// The user was timed out
+    TimeoutSuccess => "timeout_success",
+    /// The user was unbanned
+    UnbanSuccess => "unban_success",
+    /// The command is not recognized
+    UnrecognizedCmd => "unrecognized_cmd",
+    /// The user's timeout was removed
+    UntimeoutSuccess => "untimeout_success",
+    /// Response to `/vips` listing the VIPs
+    VipsSuccess => "vips_success",
+}
+
+impl NoticeId {
+    /// Whether the notice means that Twitch rejected a chat message, like `msg_ratelimit` or
+    /// `msg_duplicate`
+    pub fn is_message_rejection(&self) -> bool {
+        matches!(
+            self,
+            NoticeId::MsgBanned
+                | NoticeId::MsgChannelSuspended
+                | NoticeId::MsgDuplicate
+                | NoticeId::MsgEmoteOnly
+                | NoticeId::MsgFollowersOnly
+                | NoticeId::MsgFollowersOnlyZero
+                | NoticeId::MsgR9k
+                | NoticeId::MsgRatelimit
+                | NoticeId::MsgSlowMode
+                | NoticeId::MsgSubsOnly
+                | NoticeId::MsgSuspended
+                | NoticeId::MsgTimedOut
+                | NoticeId::MsgVerifiedEmail
+        )
+    }
+}
+
+/// Tags specific to NOTICE events
+pub trait NoticeTags<T: StringRef>: MessageTags<T> {
+    /// `msg-id` tag.
+    ///
+    /// Identifies the kind of notice, e.g. `slow_on`, `msg_channel_suspended` or `room_mods`.
+    /// Not set on some notices, like the response to a failed login.
+    #[inline]
+    fn msg_id(&self) -> Result<&str, Error> {
+        self.required_tag("msg-id")
+    }
+
+    /// `msg-id` tag parsed as a `NoticeId`, `None` if the tag is missing
+    #[inline]
+    fn notice_id(&self) -> Option<NoticeId> {
+        self.tag("msg-id").map(NoticeId::from)
+    }
+}
+impl<T: StringRef> NoticeTags<T> for EventData<T, NoticeEvent<T>> {}
+
 /// Tags specific to ROOMSTATE events
 pub trait RoomStateTags<T: StringRef>: MessageTags<T> {
     /// `emote-only` tag. Set when emote only mode is active.
@@ -351,13 +838,18 @@ pub trait RoomStateTags<T: StringRef>: MessageTags<T> {
     /// `slow` tag. Set to the number of seconds set for slow mode if active.
     #[inline]
     fn slow(&self) -> Option<usize> {
-        match self.tag("subs").map(|t| t.borrow()) {
-            Some(v) => usize::from_str(v).ok(),
+        match self
+            .tag("slow")
+            .and_then(|t| usize::from_str(t.borrow()).ok())
+        {
+            Some(v) if v > 0 => Some(v),
             _ => None,
         }
     }
 
-    /// `subs-only` tag. Set when emote only mode is active.
+    /// `subs-only` tag. Set when subscribers only mode is active. This is the only name Twitch
+    /// uses for it in ROOMSTATE; the similar `subscriber` tag on messages and USERSTATE marks
+    /// whether the user is subscribed instead.
     #[inline]
     fn subs_only(&self) -> bool {
         match self.tag("subs-only").map(|t| t.borrow()) {
@@ -395,10 +887,296 @@ pub struct Badge<T: StringRef> {
     pub version: T,
 }
 
+impl<T: StringRef> Badge<T> {
+    /// The badge as a [`KnownBadge`](enum.KnownBadge.html), if it is one of the common badges
+    pub fn known(&self) -> Option<KnownBadge> {
+        KnownBadge::from_name(self.badge.borrow())
+    }
+}
+
+/// Commonly used chat badges
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum KnownBadge {
+    /// `broadcaster`, the owner of the channel
+    Broadcaster,
+    /// `moderator`
+    Moderator,
+    /// `vip`
+    Vip,
+    /// `subscriber`, the version encodes the subscription length
+    Subscriber,
+    /// `founder`, one of the first subscribers of the channel
+    Founder,
+    /// `turbo`, Twitch Turbo user
+    Turbo,
+    /// `premium`, Prime Gaming user
+    Premium,
+}
+
+impl KnownBadge {
+    /// Badge name as it appears in the `badges` tag
+    pub fn name(self) -> &'static str {
+        match self {
+            KnownBadge::Broadcaster => "broadcaster",
+            KnownBadge::Moderator => "moderator",
+            KnownBadge::Vip => "vip",
+            KnownBadge::Subscriber => "subscriber",
+            KnownBadge::Founder => "founder",
+            KnownBadge::Turbo => "turbo",
+            KnownBadge::Premium => "premium",
+        }
+    }
+
+    /// The known badge with the given name
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "broadcaster" => KnownBadge::Broadcaster,
+            "moderator" => KnownBadge::Moderator,
+            "vip" => KnownBadge::Vip,
+            "subscriber" => KnownBadge::Subscriber,
+            "founder" => KnownBadge::Founder,
+            "turbo" => KnownBadge::Turbo,
+            "premium" => KnownBadge::Premium,
+            _ => return None,
+        })
+    }
+}
+
+/// Badges of a message that can be looked up by name, see
+/// [`BadgeTags::badges_map`](trait.BadgeTags.html#method.badges_map)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BadgeMap<'a>(Vec<Badge<&'a str>>);
+
+impl<'a> BadgeMap<'a> {
+    /// Whether the user has the badge with the given name
+    pub fn has_badge(&self, name: &str) -> bool {
+        self.badge_version(name).is_some()
+    }
+
+    /// Version of the badge with the given name, `None` if the user doesn't have it
+    pub fn badge_version(&self, name: &str) -> Option<&'a str> {
+        self.0
+            .iter()
+            .find(|badge| badge.badge == name)
+            .map(|badge| badge.version)
+    }
+
+    /// Whether the user has one of the common badges
+    pub fn has(&self, badge: KnownBadge) -> bool {
+        self.has_badge(badge.name())
+    }
+
+    /// All badges in the order Twitch sent them
+    pub fn iter(&self) -> impl Iterator<Item = &Badge<&'a str>> {
+        self.0.iter()
+    }
+}
+
+/// Identity of the logged in user, parsed from the tags of a GLOBALUSERSTATE event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GlobalUserInfo<'a> {
+    /// `user-id` tag
+    pub user_id: usize,
+    /// `display-name` tag
+    pub display_name: Option<&'a str>,
+    /// `color` tag, `None` if the user never set a color
+    pub color: Option<&'a str>,
+    /// `badges` tag
+    pub badges: Vec<Badge<&'a str>>,
+    /// `emote-sets` tag
+    pub emote_sets: Vec<usize>,
+    /// `user-type` tag
+    pub user_type: UserType,
+}
+
+impl<T: StringRef> EventData<T, GlobalUserStateEvent> {
+    /// Parse all tags that describe the logged in user at once
+    pub fn user_info(&self) -> Result<GlobalUserInfo<'_>, Error> {
+        Ok(GlobalUserInfo {
+            user_id: self.user_id()?,
+            display_name: self.display_name(),
+            color: self.color(),
+            badges: self.badges()?,
+            emote_sets: self.emote_sets()?,
+            user_type: self.user_type(),
+        })
+    }
+}
+
+/// Subscription tier from the `msg-param-sub-plan` tag
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SubPlan {
+    /// Twitch Prime subscription
+    Prime,
+    /// Tier 1 subscription
+    Tier1,
+    /// Tier 2 subscription
+    Tier2,
+    /// Tier 3 subscription
+    Tier3,
+    /// Any other value Twitch might send
+    Other(String),
+}
+
+impl From<&str> for SubPlan {
+    fn from(plan: &str) -> Self {
+        match plan {
+            "Prime" => SubPlan::Prime,
+            "1000" => SubPlan::Tier1,
+            "2000" => SubPlan::Tier2,
+            "3000" => SubPlan::Tier3,
+            other => SubPlan::Other(other.to_string()),
+        }
+    }
+}
+
+/// Kind of a USERNOTICE event along with the `msg-param-*` tags that belong to it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UserNoticeKind<'a> {
+    /// `sub`: a user subscribed for the first time
+    Sub {
+        /// `msg-param-sub-plan` tag
+        plan: SubPlan,
+        /// `msg-param-cumulative-months` tag
+        months: usize,
+    },
+    /// `resub`: a user renewed their subscription
+    Resub {
+        /// `msg-param-sub-plan` tag
+        plan: SubPlan,
+        /// `msg-param-cumulative-months` tag
+        cumulative_months: usize,
+        /// `msg-param-streak-months` tag, `None` if the user doesn't share their streak
+        streak: Option<usize>,
+    },
+    /// `subgift` or `anonsubgift`: a user gifted a subscription to another user
+    SubGift {
+        /// `msg-param-recipient-user-name` tag, login name of the user receiving the gift
+        recipient: &'a str,
+        /// `msg-param-sub-plan` tag
+        plan: SubPlan,
+    },
+    /// `submysterygift`: a user gifted subscriptions to random users in the channel
+    SubMysteryGift {
+        /// `msg-param-mass-gift-count` tag
+        count: usize,
+        /// `msg-param-sub-plan` tag
+        plan: SubPlan,
+    },
+    /// `raid`: another channel raided this channel
+    Raid {
+        /// `msg-param-login` tag, login name of the raiding channel
+        from: &'a str,
+        /// `msg-param-displayName` tag, display name of the raiding channel
+        display_name: Option<&'a str>,
+        /// `msg-param-viewerCount` tag
+        viewer_count: usize,
+    },
+    /// Any other `msg-id`
+    Unknown,
+}
+
+impl<T: StringRef> EventData<T, UserNoticeEvent<T>> {
+    /// Inspect the `msg-id` tag and parse the `msg-param-*` tags belonging to that kind of notice
+    pub fn sub_event(&self) -> Result<UserNoticeKind<'_>, Error> {
+        Ok(match self.msg_id()? {
+            "sub" => UserNoticeKind::Sub {
+                plan: self.sub_plan()?,
+                months: self.number_tag("msg-param-cumulative-months")?,
+            },
+            "resub" => UserNoticeKind::Resub {
+                plan: self.sub_plan()?,
+                cumulative_months: self.number_tag("msg-param-cumulative-months")?,
+                streak: if self.tag("msg-param-should-share-streak") == Some("1") {
+                    Some(self.number_tag("msg-param-streak-months")?)
+                } else {
+                    None
+                },
+            },
+            "subgift" | "anonsubgift" => UserNoticeKind::SubGift {
+                recipient: self.required_tag("msg-param-recipient-user-name")?,
+                plan: self.sub_plan()?,
+            },
+            "submysterygift" => UserNoticeKind::SubMysteryGift {
+                count: self.number_tag("msg-param-mass-gift-count")?,
+                plan: self.sub_plan()?,
+            },
+            "raid" => UserNoticeKind::Raid {
+                from: self.required_tag("msg-param-login")?,
+                display_name: self.camel_case_tag("msg-param-displayName"),
+                viewer_count: self.number_tag("msg-param-viewerCount")?,
+            },
+            _ => UserNoticeKind::Unknown,
+        })
+    }
+
+    fn sub_plan(&self) -> Result<SubPlan, Error> {
+        self.required_tag("msg-param-sub-plan").map(SubPlan::from)
+    }
+
+    /// Tag with a camel case key, also found if the keys were lowercased when parsing
+    fn camel_case_tag(&self, tag_name: &str) -> Option<&str> {
+        self.tag(tag_name)
+            .or_else(|| self.tag(tag_name.to_ascii_lowercase()))
+    }
+
+    fn number_tag(&self, tag_name: &str) -> Result<usize, Error> {
+        let value = match self.camel_case_tag(tag_name) {
+            Some(value) => value,
+            None => self.required_tag(tag_name)?,
+        };
+        usize::from_str(value)
+            .map_err(|_| Error::TagParseError(tag_name.to_string(), value.to_string()))
+    }
+}
+
+/// Parses a comma separated list of badges. Empty entries, like those left by leading or trailing
+/// commas, are skipped.
 fn parse_badges<'a>(input: &'a str, tag_name: &str) -> Result<Vec<Badge<&'a str>>, Error> {
-    separated_list(char(','), parse_badge)(input)
-        .map(|(_, badges)| badges)
-        .map_err(|_| Error::TagParseError(tag_name.to_string(), input.to_string()))
+    input
+        .split(',')
+        .filter(|badge| !badge.is_empty())
+        .map(|badge| match parse_badge(badge) {
+            Ok(("", badge)) => Some(badge),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Error::TagParseError(tag_name.to_string(), input.to_string()))
+}
+
+#[test]
+fn test_emote_cdn_url() {
+    let emotes = parse_emotes("25:0-4/emotesv2_dc24652ada1e4c84a5e3ceebae4de709:6-12").unwrap();
+    assert_eq!(emotes[0].emote_id, "25");
+    assert_eq!(
+        emotes[0].cdn_url(EmoteScale::Small, EmoteTheme::Light, EmoteFormat::Static),
+        "https://static-cdn.jtvnw.net/emoticons/v2/25/static/light/1.0"
+    );
+    assert_eq!(
+        emotes[1].cdn_url(EmoteScale::Medium, EmoteTheme::Dark, EmoteFormat::Animated),
+        "https://static-cdn.jtvnw.net/emoticons/v2/emotesv2_dc24652ada1e4c84a5e3ceebae4de709/animated/dark/2.0"
+    );
+}
+
+#[test]
+fn test_emote_substr_multibyte() {
+    use crate::event::MessageEventData;
+    use crate::util::test_events::privmsg;
+
+    // "Kappa" starts at byte 7, but at code point 3
+    let event = privmsg(
+        "@emotes=25:3-7,17-21/1902:9-13 :ronni!ronni@ronni.tmi.twitch.tv \
+         PRIVMSG #dallas :👋ü Kappa Keepo ñ Kappa",
+    );
+    let emotes = event.emotes().unwrap();
+    assert_eq!(emotes[0].substr_in(event.message()), vec!["Kappa", "Kappa"]);
+    assert_eq!(emotes[1].substr_in(event.message()), vec!["Keepo"]);
+
+    let out_of_range = EmoteReplacement {
+        emote_id: "25".to_string(),
+        indices: vec![(0, 4), (3, 10), (5, 4)],
+    };
+    assert_eq!(out_of_range.substr_in("Kappa hi"), vec!["Kappa"]);
 }
 
 #[test]
@@ -429,8 +1207,328 @@ fn test_badge_parsing_2() {
     );
 }
 
+#[test]
+fn test_badge_parsing_versions() {
+    assert_eq!(
+        parse_badges("predictions/blue-1,subscriber/12", "badges").unwrap(),
+        vec![
+            Badge {
+                badge: "predictions",
+                version: "blue-1"
+            },
+            Badge {
+                badge: "subscriber",
+                version: "12"
+            }
+        ]
+    );
+    assert_eq!(
+        parse_badges("sub-gifter/50", "badges").unwrap(),
+        vec![Badge {
+            badge: "sub-gifter",
+            version: "50"
+        }]
+    );
+}
+
+#[test]
+fn test_badges_map() {
+    use crate::util::test_events::privmsg;
+
+    let event = privmsg(
+        "@badge-info=subscriber/14;badges=moderator/1,subscriber/3012,bits/100 \
+         :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi",
+    );
+    let badges = event.badges_map().unwrap();
+    assert!(badges.has(KnownBadge::Moderator));
+    assert!(!badges.has(KnownBadge::Vip));
+    assert!(badges.has_badge("bits"));
+    assert_eq!(badges.badge_version("bits"), Some("100"));
+    assert_eq!(badges.badge_version("vip"), None);
+    let known: Vec<_> = badges.iter().filter_map(Badge::known).collect();
+    assert_eq!(known, vec![KnownBadge::Moderator, KnownBadge::Subscriber]);
+    // exact count from badge-info
+    assert_eq!(event.subscriber_months().unwrap(), Some(14));
+
+    let event =
+        privmsg("@badges=subscriber/3012 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi");
+    assert_eq!(event.subscriber_months().unwrap(), Some(12));
+
+    let event = privmsg(
+        "@badge-info=founder/20;badges=founder/0 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi",
+    );
+    assert_eq!(event.subscriber_months().unwrap(), Some(20));
+
+    let event = privmsg("@badges=turbo/1 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi");
+    assert_eq!(event.subscriber_months().unwrap(), None);
+}
+
+#[test]
+fn test_badge_parsing_separators() {
+    assert_eq!(parse_badges("", "badges").unwrap(), vec![]);
+    assert_eq!(parse_badges(",", "badges").unwrap(), vec![]);
+    let subscriber = vec![Badge {
+        badge: "subscriber",
+        version: "1",
+    }];
+    assert_eq!(parse_badges("subscriber/1,", "badges").unwrap(), subscriber);
+    assert_eq!(parse_badges(",subscriber/1", "badges").unwrap(), subscriber);
+    assert_eq!(
+        parse_badges("broadcaster/1,,subscriber/1", "badges")
+            .unwrap()
+            .len(),
+        2
+    );
+    assert!(parse_badges("subscriber", "badges").is_err());
+    assert!(parse_badges("subscriber/1 moderator/1", "badges").is_err());
+}
+
+#[test]
+fn test_notice_msg_id() {
+    use crate::util::test_events::notice;
+
+    let event = notice(
+        "@msg-id=msg_channel_suspended :tmi.twitch.tv NOTICE #dallas :This channel has been suspended.",
+    );
+    assert_eq!(event.msg_id().unwrap(), "msg_channel_suspended");
+}
+
+#[test]
+fn test_notice_id() {
+    use crate::util::test_events::notice;
+
+    let notice_id = |msg: &str| notice(msg).notice_id();
+    assert_eq!(
+        notice_id("@msg-id=slow_on :tmi.twitch.tv NOTICE #dallas :This room is now in slow mode."),
+        Some(NoticeId::SlowOn)
+    );
+    assert_eq!(
+        notice_id("@msg-id=something_new :tmi.twitch.tv NOTICE #dallas :Something new."),
+        Some(NoticeId::Unknown("something_new".to_string()))
+    );
+    assert_eq!(
+        notice_id(":tmi.twitch.tv NOTICE * :Login authentication failed"),
+        None
+    );
+}
+
+#[test]
+fn test_reply_tags() {
+    use crate::util::test_events::privmsg;
+
+    let event = privmsg(
+        "@reply-parent-display-name=Someone;reply-parent-msg-body=hello\\sthere\\:\\sgood\\\\sday;\
+         reply-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;reply-parent-user-id=123;\
+         reply-parent-user-login=someone :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :@Someone hi",
+    );
+    assert!(event.is_reply());
+    assert_eq!(
+        event.reply_parent_msg_id(),
+        Some("b34ccfc7-4977-403a-8a94-33c6bac34fb8")
+    );
+    assert_eq!(event.reply_parent_user_id(), Some("123"));
+    assert_eq!(event.reply_parent_user_login(), Some("someone"));
+    assert_eq!(event.reply_parent_display_name(), Some("Someone"));
+    assert_eq!(
+        event.reply_parent_msg_body(),
+        Some("hello there; good\\sday")
+    );
+
+    let event = privmsg(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi");
+    assert!(!event.is_reply());
+}
+
+#[test]
+fn test_chatter_tags() {
+    use crate::util::test_events::privmsg;
+
+    let chatter_tags = |msg: &str| {
+        let event = privmsg(msg);
+        (event.is_first_msg(), event.is_returning_chatter())
+    };
+    assert_eq!(
+        chatter_tags(
+            "@first-msg=1;returning-chatter=0 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"
+        ),
+        (true, false)
+    );
+    assert_eq!(
+        chatter_tags(
+            "@first-msg=0;returning-chatter=1 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"
+        ),
+        (false, true)
+    );
+    assert_eq!(
+        chatter_tags(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"),
+        (false, false)
+    );
+}
+
+#[test]
+fn test_custom_reward_id() {
+    use crate::util::test_events::privmsg;
+
+    let custom_reward_id = |msg: &str| privmsg(msg).custom_reward_id().map(str::to_string);
+    assert_eq!(
+        custom_reward_id(
+            "@custom-reward-id=f8d1d2d4-1b3e-4c5a-9e2f-1f3c4b5a6d7e :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :my text"
+        ),
+        Some("f8d1d2d4-1b3e-4c5a-9e2f-1f3c4b5a6d7e".to_string())
+    );
+    assert_eq!(
+        custom_reward_id(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"),
+        None
+    );
+}
+
+#[test]
+fn test_user_notice_kind() {
+    use crate::event::MessageEventData;
+    use crate::util::test_events::user_notice as parse;
+
+    let event = parse(
+        "@msg-id=resub;msg-param-cumulative-months=6;msg-param-should-share-streak=1;\
+         msg-param-streak-months=2;msg-param-sub-plan=Prime :tmi.twitch.tv USERNOTICE #dallas :Great stream",
+    );
+    assert_eq!(
+        event.sub_event().unwrap(),
+        UserNoticeKind::Resub {
+            plan: SubPlan::Prime,
+            cumulative_months: 6,
+            streak: Some(2)
+        }
+    );
+
+    let event = parse(
+        "@msg-id=subgift;msg-param-recipient-user-name=tww2;msg-param-sub-plan=1000 :tmi.twitch.tv USERNOTICE #forstycup :hi",
+    );
+    assert_eq!(
+        event.sub_event().unwrap(),
+        UserNoticeKind::SubGift {
+            recipient: "tww2",
+            plan: SubPlan::Tier1
+        }
+    );
+
+    let event = parse(
+        "@msg-id=raid;msg-param-login=testchannel;msg-param-viewerCount=15 :tmi.twitch.tv USERNOTICE #othertestchannel :hi",
+    );
+    assert_eq!(
+        event.sub_event().unwrap(),
+        UserNoticeKind::Raid {
+            from: "testchannel",
+            display_name: None,
+            viewer_count: 15
+        }
+    );
+
+    // raid notice exactly as sent by Twitch, without a user message
+    let event = parse(
+        "@badge-info=;badges=turbo/1;color=#9ACD32;display-name=TestChannel;emotes=;\
+         id=3d830f12-795c-447d-af3c-ea05e40fbddb;login=testchannel;mod=0;msg-id=raid;\
+         msg-param-displayName=TestChannel;msg-param-login=testchannel;msg-param-viewerCount=15;\
+         room-id=56789;subscriber=0;system-msg=15\\sraiders\\sfrom\\sTestChannel\\shave\\sjoined\\n!;\
+         tmi-sent-ts=1507246572675;turbo=1;user-id=123456;user-type= \
+         :tmi.twitch.tv USERNOTICE #othertestchannel",
+    );
+    assert_eq!(event.message(), &"");
+    assert_eq!(
+        event.system_msg().unwrap(),
+        "15 raiders from TestChannel have joined\n!"
+    );
+    assert_eq!(
+        event.sub_event().unwrap(),
+        UserNoticeKind::Raid {
+            from: "testchannel",
+            display_name: Some("TestChannel"),
+            viewer_count: 15
+        }
+    );
+
+    let event = parse("@msg-id=ritual;msg-param-ritual-name=new_chatter :tmi.twitch.tv USERNOTICE #seventoes :HeyGuys");
+    assert_eq!(event.sub_event().unwrap(), UserNoticeKind::Unknown);
+
+    let event = parse("@msg-id=sub;msg-param-sub-plan=2000 :tmi.twitch.tv USERNOTICE #dallas :hi");
+    assert!(matches!(event.sub_event(), Err(Error::MissingTag { .. })));
+}
+
+#[test]
+fn test_tag_case_insensitive() {
+    use crate::irc::lowercase_tag_keys;
+    use crate::util::test_events::privmsg;
+
+    let mut line =
+        "@Display-Name=Ronni;COLOR=#0D4200 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"
+            .to_string();
+    lowercase_tag_keys(&mut line);
+    let event = privmsg(&line);
+    assert_eq!(event.display_name(), Some("Ronni"));
+    assert_eq!(event.color(), Some("#0D4200"));
+    assert_eq!(event.tag("display-name"), Some("Ronni"));
+}
+
+#[test]
+fn test_global_user_info() {
+    use crate::util::test_events::global_user_state;
+
+    let event = global_user_state(
+        "@badge-info=;badges=admin/1;color=#0D4200;display-name=ronni;emote-sets=0,33,50,237;\
+         user-id=1337;user-type=admin :tmi.twitch.tv GLOBALUSERSTATE",
+    );
+    assert_eq!(
+        event.user_info().unwrap(),
+        GlobalUserInfo {
+            user_id: 1337,
+            display_name: Some("ronni"),
+            color: Some("#0D4200"),
+            badges: vec![Badge {
+                badge: "admin",
+                version: "1"
+            }],
+            emote_sets: vec![0, 33, 50, 237],
+            user_type: UserType::Admin,
+        }
+    );
+}
+
+#[test]
+fn test_user_type() {
+    use crate::util::test_events::privmsg;
+
+    let user_type = |line: &str| privmsg(line).user_type();
+    assert_eq!(
+        user_type("@user-type=global_mod :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"),
+        UserType::GlobalMod
+    );
+    assert_eq!(
+        user_type("@user-type= :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"),
+        UserType::Normal
+    );
+}
+
+#[test]
+fn test_tags_owned() {
+    use crate::util::test_events::privmsg;
+
+    let event = privmsg(
+        "@display-name=Ronni;color=#0D4200;mod=0 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi",
+    );
+    let tags = event.tags_owned();
+    assert_eq!(tags.len(), 3);
+    assert_eq!(tags.get("display-name").map(String::as_str), Some("Ronni"));
+    assert_eq!(tags.get("color").map(String::as_str), Some("#0D4200"));
+    assert_eq!(tags.get("mod").map(String::as_str), Some("0"));
+}
+
+/// Badge names and versions consist of alphanumerics, dashes and underscores, e.g. `bits-leader/1`
+/// or `predictions/blue-1`
+fn take_badge_part(input: &str) -> IResult<&str, &str> {
+    take_while1(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')(input)
+}
+
 fn parse_badge(input: &str) -> IResult<&str, Badge<&str>> {
-    let (remaining, (badge, _, version)) = tuple((alpha1, char('/'), digit1))(input)?;
+    let (remaining, (badge, _, version)) =
+        tuple((take_badge_part, char('/'), take_badge_part))(input)?;
     Ok((remaining, Badge { badge, version }))
 }
 
@@ -438,9 +1536,16 @@ fn take_usize(input: &str) -> IResult<&str, usize> {
     ::nom::combinator::map(digit1, |s| usize::from_str(s).unwrap())(input)
 }
 
+fn take_emote_id(input: &str) -> IResult<&str, String> {
+    ::nom::combinator::map(
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+        String::from,
+    )(input)
+}
+
 fn parse_emote(input: &str) -> IResult<&str, EmoteReplacement> {
     let (rem, (emote_id, indices)) = separated_pair(
-        take_usize,
+        take_emote_id,
         char(':'),
         separated_nonempty_list(char(','), separated_pair(take_usize, char('-'), take_usize)),
     )(input)?;
@@ -452,3 +1557,123 @@ fn parse_emotes(input: &str) -> Result<Vec<EmoteReplacement>, Error> {
         .map_err(|_| Error::TagParseError("emotes".to_string(), input.to_string()))?;
     Ok(replacements)
 }
+
+#[test]
+fn test_subs_only() {
+    use crate::util::test_events::room_state;
+
+    let subs_only = |msg: &str| room_state(msg).subs_only();
+    assert!(subs_only(
+        "@room-id=1;subs-only=1 :tmi.twitch.tv ROOMSTATE #dallas"
+    ));
+    assert!(!subs_only(
+        "@emote-only=0;followers-only=0;r9k=0;slow=0;subs-only=0 :tmi.twitch.tv ROOMSTATE #dallas"
+    ));
+    // partial ROOMSTATE updates only contain the changed setting
+    assert!(!subs_only(
+        "@room-id=1;slow=10 :tmi.twitch.tv ROOMSTATE #dallas"
+    ));
+    // not a ROOMSTATE tag, must not be mistaken for subscribers only mode
+    assert!(!subs_only(
+        "@room-id=1;subscriber=1 :tmi.twitch.tv ROOMSTATE #dallas"
+    ));
+}
+
+#[test]
+fn test_slow_and_followers_only() {
+    use crate::util::test_events;
+
+    let room_state = |msg: &str| {
+        let event = test_events::room_state(msg);
+        (event.slow(), event.followers_only())
+    };
+    assert_eq!(
+        room_state("@room-id=1;slow=30 :tmi.twitch.tv ROOMSTATE #dallas"),
+        (Some(30), None)
+    );
+    // must not pick up subscribers only mode
+    assert_eq!(
+        room_state("@room-id=1;subs-only=1 :tmi.twitch.tv ROOMSTATE #dallas"),
+        (None, None)
+    );
+    assert_eq!(
+        room_state("@followers-only=-1;slow=0 :tmi.twitch.tv ROOMSTATE #dallas"),
+        (None, None)
+    );
+    assert_eq!(
+        room_state("@followers-only=0;slow=0 :tmi.twitch.tv ROOMSTATE #dallas"),
+        (None, Some(0))
+    );
+    assert_eq!(
+        room_state("@followers-only=10 :tmi.twitch.tv ROOMSTATE #dallas"),
+        (None, Some(10))
+    );
+}
+
+#[test]
+fn test_hype_chat() {
+    use crate::util::test_events::privmsg;
+
+    let hype_chat = |msg: &str| {
+        let event = privmsg(msg);
+        (
+            event.hype_chat_amount(),
+            event.hype_chat_exponent(),
+            event.hype_chat_currency().map(str::to_string),
+            event.hype_chat_level().map(str::to_string),
+        )
+    };
+    assert_eq!(
+        hype_chat(
+            "@pinned-chat-paid-amount=500;pinned-chat-paid-currency=USD;pinned-chat-paid-exponent=2;\
+             pinned-chat-paid-level=ONE :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hype"
+        ),
+        (Some(500), Some(2), Some("USD".to_string()), Some("ONE".to_string()))
+    );
+    assert_eq!(
+        hype_chat(
+            "@pinned-chat-paid-amount=1500;pinned-chat-paid-currency=JPY;pinned-chat-paid-exponent=0;\
+             pinned-chat-paid-level=TWO :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hype"
+        ),
+        (Some(1500), Some(0), Some("JPY".to_string()), Some("TWO".to_string()))
+    );
+    assert_eq!(
+        hype_chat("@badges= :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello"),
+        (None, None, None, None)
+    );
+}
+
+#[test]
+fn test_privmsg_tags() {
+    use crate::util::test_events::privmsg;
+
+    let event = privmsg(
+        "@crowd-chant-parent-msg-id=b34ccfc7-4977-403a-8a94-33c6bac34fb8;msg-id=highlighted-message \
+         :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :chant",
+    );
+    assert_eq!(event.msg_id(), Some("highlighted-message"));
+    assert_eq!(
+        event.crowd_chant_parent_msg_id(),
+        Some("b34ccfc7-4977-403a-8a94-33c6bac34fb8")
+    );
+
+    let event = privmsg(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi");
+    assert_eq!(event.msg_id(), None);
+    assert_eq!(event.crowd_chant_parent_msg_id(), None);
+}
+
+#[test]
+fn test_is_broadcaster() {
+    use crate::util::test_events::privmsg;
+
+    let is_broadcaster = |msg: &str| privmsg(msg).is_broadcaster();
+    assert!(is_broadcaster(
+        "@badges=broadcaster/1,subscriber/0;mod=0 :dallas!dallas@dallas.tmi.twitch.tv PRIVMSG #dallas :hi"
+    ));
+    assert!(!is_broadcaster(
+        "@badges=moderator/1;mod=1 :ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"
+    ));
+    assert!(!is_broadcaster(
+        ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hi"
+    ));
+}
@@ -1,6 +1,8 @@
 //! Parser for twitch flavored IRC
 
+use std::borrow::Cow;
 use std::convert::identity;
+use std::fmt;
 use std::iter::FromIterator;
 
 use fnv::FnvHashMap;
@@ -111,6 +113,59 @@ where
     }
 }
 
+impl<T: StringRef> IrcMessage<T> {
+    /// Formats the message as a line of IRC, without the trailing line break. Tags are stored in a
+    /// hash map, so their order is arbitrary unless `sort_tags` is set, in which case they are
+    /// written ordered by key. Sorting produces stable output, e.g. for snapshot tests.
+    pub fn to_irc_string(&self, sort_tags: bool) -> String {
+        let mut out = String::new();
+        if let Some(tags) = &self.tags {
+            let mut tags: Vec<(&str, &str)> = tags
+                .iter()
+                .map(|(key, value)| (key.borrow(), value.as_str()))
+                .collect();
+            if sort_tags {
+                tags.sort_unstable_by_key(|&(key, _)| key);
+            }
+            out.push('@');
+            for (i, (key, value)) in tags.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(';');
+                }
+                out.push_str(key);
+                out.push('=');
+                out.push_str(&escape_tag_value(value));
+            }
+            out.push(' ');
+        }
+        if let Some(prefix) = &self.prefix {
+            out.push(':');
+            out.push_str(&prefix.to_string());
+            out.push(' ');
+        }
+        out.push_str(self.command.borrow());
+        if let Some((last, middle)) = self.command_params.split_last() {
+            for param in middle {
+                out.push(' ');
+                out.push_str(param.borrow());
+            }
+            let last: &str = last.borrow();
+            out.push(' ');
+            if last.is_empty() || last.starts_with(':') || last.contains(' ') {
+                out.push(':');
+            }
+            out.push_str(last);
+        }
+        out
+    }
+}
+
+impl<T: StringRef> fmt::Display for IrcMessage<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_irc_string(false))
+    }
+}
+
 /// Struct containing the parts of the IRC message prefix
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct IrcPrefix<T> {
@@ -130,6 +185,28 @@ impl<T: StringRef> IrcPrefix<T> {
     }
 }
 
+impl<T: StringRef> fmt::Display for IrcPrefix<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.nick, &self.host) {
+            (Some(nick), Some(host)) => {
+                write!(f, "{}", nick)?;
+                if let Some(user) = &self.user {
+                    write!(f, "!{}", user)?;
+                }
+                write!(f, "@{}", host)
+            }
+            (Some(name), None) | (None, Some(name)) => {
+                write!(f, "{}", name)?;
+                if let Some(user) = &self.user {
+                    write!(f, "!{}", user)?;
+                }
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+}
+
 impl<T> From<&IrcPrefix<T>> for IrcPrefix<String>
 where
     T: StringRef,
@@ -279,6 +356,15 @@ fn spaces0(input: &str) -> IResult<&str, &str> {
 
 /// Unescape tag values according to irc spec
 pub fn unescape_tag_value(value: &str) -> String {
+    unescape_tag_value_cow(value).into_owned()
+}
+
+/// Unescape tag values according to irc spec, like [`unescape_tag_value`], but values without
+/// escape sequences are returned without copying them.
+pub fn unescape_tag_value_cow(value: &str) -> Cow<'_, str> {
+    if !value.contains('\\') {
+        return Cow::Borrowed(value);
+    }
     let mut unescaped = String::with_capacity(value.len());
     let mut iter = value.chars();
     while let Some(chr) = iter.next() {
@@ -295,7 +381,52 @@ pub fn unescape_tag_value(value: &str) -> String {
             other => unescaped.push(other),
         }
     }
-    unescaped
+    Cow::Owned(unescaped)
+}
+
+/// Lowercase the IRCv3 tag keys of all lines in `input` in place. Tag values and the rest of
+/// the lines are left unchanged. Makes tag lookups by lowercase names work for messages whose
+/// keys use a different case.
+pub fn lowercase_tag_keys(input: &mut str) {
+    let mut line_start = 0;
+    while line_start < input.len() {
+        let line_end = input[line_start..]
+            .find("\r\n")
+            .map_or(input.len(), |i| line_start + i);
+        if input[line_start..line_end].starts_with('@') {
+            let tags_end = input[line_start..line_end]
+                .find(' ')
+                .map_or(line_end, |i| line_start + i);
+            let mut key_start = line_start + 1;
+            while key_start < tags_end {
+                let tag_end = input[key_start..tags_end]
+                    .find(';')
+                    .map_or(tags_end, |i| key_start + i);
+                let key_end = input[key_start..tag_end]
+                    .find('=')
+                    .map_or(tag_end, |i| key_start + i);
+                input[key_start..key_end].make_ascii_lowercase();
+                key_start = tag_end + 1;
+            }
+        }
+        line_start = line_end + 2;
+    }
+}
+
+/// Escape tag values according to irc spec
+pub fn escape_tag_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for chr in value.chars() {
+        match chr {
+            ';' => escaped.push_str("\\:"),
+            ' ' => escaped.push_str("\\s"),
+            '\\' => escaped.push_str("\\\\"),
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
 }
 
 // ------------------------------ TESTS ------------------------------
@@ -320,6 +451,34 @@ fn test_irc_tags_escaped() {
     assert_eq!(map["tag-name"], "escape test\r\n; \\");
 }
 
+#[test]
+fn test_unescape_tag_value() {
+    assert_eq!(unescape_tag_value("plain"), "plain");
+    assert!(matches!(
+        unescape_tag_value_cow("plain"),
+        Cow::Borrowed("plain")
+    ));
+    assert!(matches!(
+        unescape_tag_value_cow("a\\sb"),
+        Cow::Owned(value) if value == "a b"
+    ));
+    assert_eq!(
+        unescape_tag_value("5\\sraiders\\sfrom\\sTestChannel"),
+        "5 raiders from TestChannel"
+    );
+    assert_eq!(unescape_tag_value(&escape_tag_value("a; b\\c")), "a; b\\c");
+}
+
+#[test]
+fn test_lowercase_tag_keys() {
+    let mut input = "@Display-Name=Ronni;COLOR=#0D4200;mod= :Ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :Hi\r\nPING :tmi.twitch.tv\r\n@Badges=;Emotes :tmi.twitch.tv USERSTATE #dallas".to_string();
+    lowercase_tag_keys(&mut input);
+    assert_eq!(
+        input,
+        "@display-name=Ronni;color=#0D4200;mod= :Ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :Hi\r\nPING :tmi.twitch.tv\r\n@badges=;emotes :tmi.twitch.tv USERSTATE #dallas"
+    );
+}
+
 #[test]
 fn test_command_params() {
     let result = command_params("  middle1 middle2  middle3 :trailing");
@@ -377,3 +536,17 @@ fn test_irc_prefix() {
         )
     );
 }
+
+#[test]
+fn test_sorted_tags() {
+    let msg = "@user-id=1;color=#FF0000;badges=vip/1;display-name=Some\\sUser :someuser!someuser@someuser.tmi.twitch.tv PRIVMSG #channel :hello there";
+    let (_, parsed) = IrcMessage::parse(msg).unwrap();
+    let owned = IrcMessage::<String>::from(&parsed);
+    let expected = "@badges=vip/1;color=#FF0000;display-name=Some\\sUser;user-id=1 :someuser!someuser@someuser.tmi.twitch.tv PRIVMSG #channel :hello there";
+    assert_eq!(parsed.to_irc_string(true), expected);
+    assert_eq!(
+        owned.to_irc_string(true).as_bytes(),
+        parsed.to_irc_string(true).as_bytes()
+    );
+    assert_eq!(IrcMessage::parse(&owned.to_string()).unwrap().1, parsed);
+}
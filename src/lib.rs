@@ -74,8 +74,6 @@
     missing_docs
 )]
 
-#[macro_use]
-extern crate log;
 #[macro_use]
 extern crate smallvec;
 
@@ -88,16 +86,20 @@ pub use client_messages::*;
 pub use errors::*;
 pub use sender::*;
 
+#[macro_use]
+pub(crate) mod util;
+
 mod client;
 pub mod client_messages;
+pub mod commands;
 mod errors;
 pub mod event;
 pub mod irc;
 pub mod irc_constants;
+mod runtime;
 pub mod selectors;
 mod sender;
 pub mod stream;
-pub(crate) mod util;
 
 /// Trait that is used when generically referring to a &str, String, or other type that can be
 /// used like a borrowed string
@@ -0,0 +1,138 @@
+//! Runtime specific functionality used by the client. Spawning tasks and timers go through
+//! this module instead of calling tokio directly.
+//!
+//! With the `async-std` feature, tasks are spawned on async-std's executor and the timers are
+//! async-std timers. The websocket connection from tokio-tungstenite still needs tokio's
+//! reactor, async-std provides it through its `tokio02` compatibility feature. The types used in
+//! the public API, like `Instant` and the `Delay` returned by `SlowModeLimit::next_delay`, are
+//! tokio's in both cases, so the public API doesn't change with the feature.
+//!
+//! The channels from `tokio::sync` are not included here, they work with any executor.
+
+pub(crate) use tokio::time::Instant;
+#[cfg(not(feature = "async-std"))]
+pub(crate) use tokio::{
+    spawn,
+    time::{delay_for, delay_until, interval_at, timeout, timeout_at, Delay},
+};
+
+#[cfg(feature = "async-std")]
+pub(crate) use self::async_std_runtime::*;
+
+#[cfg(feature = "async-std")]
+mod async_std_runtime {
+    use std::fmt;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::Once;
+    use std::task::{Context, Poll};
+    use std::thread;
+    use std::time::Duration;
+
+    pub(crate) use async_std::future::{timeout, TimeoutError};
+    use async_std::task::JoinHandle;
+
+    use super::Instant;
+
+    /// Spawn a task on async-std's executor
+    pub(crate) fn spawn<F>(future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        start_executor();
+        async_std::task::spawn(future)
+    }
+
+    /// Start async-std's executor from a thread outside of any tokio runtime. Otherwise the
+    /// executor picks up the tokio runtime of the thread that first spawns a task as its reactor,
+    /// and the connections stop working once that runtime shuts down.
+    fn start_executor() {
+        static START: Once = Once::new();
+        START.call_once(|| {
+            thread::spawn(|| async_std::task::block_on(async {}))
+                .join()
+                .expect("start async-std executor");
+        });
+    }
+
+    /// Future that completes at a deadline, like tokio's `Delay`
+    pub(crate) struct Delay {
+        deadline: Instant,
+        timer: Pin<Box<dyn Future<Output = ()> + Send + Sync>>,
+    }
+
+    impl Delay {
+        pub(crate) fn deadline(&self) -> Instant {
+            self.deadline
+        }
+
+        pub(crate) fn reset(&mut self, deadline: Instant) {
+            *self = delay_until(deadline);
+        }
+    }
+
+    impl Future for Delay {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            self.timer.as_mut().poll(cx)
+        }
+    }
+
+    impl fmt::Debug for Delay {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Delay")
+                .field("deadline", &self.deadline)
+                .finish()
+        }
+    }
+
+    pub(crate) fn delay_until(deadline: Instant) -> Delay {
+        Delay {
+            deadline,
+            // the remaining time is measured when the delay is first polled
+            timer: Box::pin(async move {
+                let now = Instant::now();
+                if deadline > now {
+                    async_std::task::sleep(deadline - now).await;
+                }
+            }),
+        }
+    }
+
+    pub(crate) fn delay_for(duration: Duration) -> Delay {
+        delay_until(Instant::now() + duration)
+    }
+
+    pub(crate) async fn timeout_at<F: Future>(
+        deadline: Instant,
+        future: F,
+    ) -> Result<F::Output, TimeoutError> {
+        timeout(deadline.saturating_duration_since(Instant::now()), future).await
+    }
+
+    /// Ticks at a fixed period, like tokio's `Interval`
+    #[derive(Debug)]
+    pub(crate) struct Interval {
+        next: Instant,
+        period: Duration,
+    }
+
+    impl Interval {
+        /// Wait for the next tick. Missed ticks complete immediately, one at a time.
+        pub(crate) async fn tick(&mut self) -> Instant {
+            delay_until(self.next).await;
+            let tick = self.next;
+            self.next = tick + self.period;
+            tick
+        }
+    }
+
+    pub(crate) fn interval_at(start: Instant, period: Duration) -> Interval {
+        Interval {
+            next: start,
+            period,
+        }
+    }
+}
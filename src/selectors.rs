@@ -10,7 +10,7 @@ use std::ops::Deref;
 
 use crate::event::*;
 
-/// Contains a PRIVMSG event
+/// Contains an event of the variant chosen by the selector function that returned it
 #[derive(Clone, Debug)]
 pub struct SelectorResult<E, Inner>(E, PhantomData<Inner>);
 
@@ -22,8 +22,16 @@ impl<E, Inner> SelectorResult<E, Inner> {
 
 macro_rules! impl_selector {
     ($fn_name: ident, $enum_variant: ident, $inner_type: ty) => {
+        impl_selector!(
+            $fn_name,
+            $enum_variant,
+            $inner_type,
+            EventData<String, $inner_type>
+        );
+    };
+    ($fn_name: ident, $enum_variant: ident, $inner_type: ty, $target: ty) => {
         impl<E: Borrow<Event<String>>> Deref for SelectorResult<E, $inner_type> {
-            type Target = EventData<String, $inner_type>;
+            type Target = $target;
 
             fn deref(&self) -> &Self::Target {
                 match self.0.borrow() {
@@ -62,3 +70,69 @@ impl_selector!(user_notice, UserNotice, UserNoticeEvent<String>);
 impl_selector!(user_state, UserState, UserStateEvent<String>);
 impl_selector!(capability, Capability, CapabilityEvent<String>);
 impl_selector!(connect_message, ConnectMessage, ConnectMessageEvent<String>);
+impl_selector!(global_user_state, GlobalUserState, GlobalUserStateEvent);
+impl_selector!(close, Close, CloseEvent, CloseEvent);
+impl_selector!(
+    reconnecting,
+    Reconnecting,
+    ReconnectingEvent,
+    ReconnectingEvent
+);
+impl_selector!(connected, Connected, ConnectedEvent, ConnectedEvent);
+impl_selector!(
+    authenticated,
+    Authenticated,
+    AuthenticatedEvent,
+    AuthenticatedEvent
+);
+impl_selector!(
+    message_rejected,
+    MessageRejected,
+    MessageRejectedEvent<String>,
+    MessageRejectedEvent<String>
+);
+impl_selector!(ping, Ping, PingEvent<String>, PingEvent<String>);
+impl_selector!(pong, Pong, PongEvent, PongEvent);
+impl_selector!(unknown, Unknown, UnknownEvent<String>);
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::{stream, StreamExt};
+
+    use crate::event::*;
+    use crate::selectors::{global_user_state, ping, priv_msg, reconnecting};
+    use crate::util::test_events::event;
+
+    fn events() -> Vec<Arc<Event<String>>> {
+        vec![
+            event(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello"),
+            event("PING :tmi.twitch.tv"),
+            event("@display-name=Tester;user-id=12345 :tmi.twitch.tv GLOBALUSERSTATE"),
+            Arc::new(ReconnectingEvent { attempt: 3 }.into()),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_selectors() {
+        let messages = stream::iter(events())
+            .filter_map(|event| async { priv_msg(event) })
+            .map(|msg| msg.message().clone())
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(messages, vec!["hello".to_string()]);
+
+        assert_eq!(events().into_iter().filter_map(ping).count(), 1);
+        assert_eq!(
+            events().into_iter().filter_map(global_user_state).count(),
+            1
+        );
+        let attempts = events()
+            .into_iter()
+            .filter_map(reconnecting)
+            .map(|event| event.attempt)
+            .collect::<Vec<_>>();
+        assert_eq!(attempts, vec![3]);
+    }
+}
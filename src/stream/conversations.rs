@@ -0,0 +1,93 @@
+//! Groups whispers into conversations
+
+use std::ops::Deref;
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::event::tags::WhisperTags;
+use crate::event::Event;
+
+/// Identifies a whisper conversation, taken from the `thread-id` tag. All whispers between the
+/// same two users have the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConversationKey(pub String);
+
+/// Pairs whisper events with the key of their conversation. See
+/// [`whisper_conversations`](../trait.ReceiveStreamExt.html#method.whisper_conversations).
+#[derive(Debug)]
+pub struct WhisperConversations<St> {
+    stream: St,
+}
+
+impl<St> WhisperConversations<St> {
+    pub(crate) fn new(stream: St) -> Self {
+        WhisperConversations { stream }
+    }
+}
+
+/// Conversation key of an event, `None` for events that aren't whispers or whispers without a
+/// `thread-id` tag
+fn conversation_key(event: &Event<String>) -> Option<ConversationKey> {
+    match event {
+        Event::Whisper(whisper) => whisper
+            .thread_id()
+            .ok()
+            .map(|thread_id| ConversationKey(thread_id.to_string())),
+        _ => None,
+    }
+}
+
+impl<St, E> Stream for WhisperConversations<St>
+where
+    St: Stream<Item = E> + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Item = (Option<ConversationKey>, E);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream
+            .poll_next_unpin(cx)
+            .map(|event| event.map(|event| (conversation_key(&event), event)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::{stream, StreamExt};
+
+    use crate::stream::conversations::ConversationKey;
+    use crate::stream::ReceiveStreamExt;
+    use crate::util::test_events::event;
+
+    #[tokio::test]
+    async fn test_whisper_conversations() {
+        let events = vec![
+            event("@message-id=1;thread-id=1234_5678 :ronni!ronni@ronni.tmi.twitch.tv WHISPER bot :hi"),
+            event(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello"),
+            event("@message-id=2;thread-id=1234_5678 :ronni!ronni@ronni.tmi.twitch.tv WHISPER bot :still there?"),
+            event("@message-id=1;thread-id=1234_9999 :fred!fred@fred.tmi.twitch.tv WHISPER bot :hey"),
+        ];
+        let keys = stream::iter(events)
+            .whisper_conversations()
+            .map(|(key, _event)| key)
+            .collect::<Vec<_>>()
+            .await;
+        let conversation = Some(ConversationKey("1234_5678".to_string()));
+        assert_eq!(
+            keys,
+            vec![
+                conversation.clone(),
+                None,
+                conversation,
+                Some(ConversationKey("1234_9999".to_string())),
+            ]
+        );
+    }
+}
@@ -1,4 +1,5 @@
-//! Deduplicator to bypass Twitch's 30 second duplicate message detection
+//! Deduplicator to bypass Twitch's 30 second duplicate message detection, and suppression of
+//! repeated commands
 
 use std::pin::Pin;
 use std::time::Duration;
@@ -7,13 +8,31 @@ use fnv::FnvHashMap;
 use futures_core::task::{Context, Poll};
 use futures_core::Stream;
 use futures_util::StreamExt;
-use tokio::time::Instant;
 
+use crate::runtime::Instant;
 use crate::stream::SentClientMessage;
-use crate::ClientMessage;
+use crate::{ClientMessage, MessageResponse};
 
-// invisible character to add to duplicate messages
-const INVIS_CHAR: char = '\u{0}';
+/// Character appended to a message that repeats the previous message to the same channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DedupStrategy {
+    /// Append a NUL character (`\u{0}`)
+    NullByte,
+    /// Append a space
+    TrailingSpace,
+    /// Append the invisible tag character `\u{e0000}`
+    Invisible,
+}
+
+impl DedupStrategy {
+    fn suffix(self) -> char {
+        match self {
+            DedupStrategy::NullByte => '\u{0}',
+            DedupStrategy::TrailingSpace => ' ',
+            DedupStrategy::Invisible => '\u{e0000}',
+        }
+    }
+}
 
 struct MessageRecord {
     sent_at: Instant,
@@ -27,6 +46,8 @@ where
 {
     // map that holds the last message for each channel
     sent_messages: FnvHashMap<String, MessageRecord>,
+    // how duplicates are altered
+    strategy: DedupStrategy,
     // source stream
     stream: St,
 }
@@ -50,6 +71,24 @@ where
                             ref mut message,
                         },
                     ..
+                }
+                | SentClientMessage {
+                    message:
+                        ClientMessage::Reply {
+                            ref channel,
+                            ref mut message,
+                            ..
+                        },
+                    ..
+                }
+                | SentClientMessage {
+                    message:
+                        ClientMessage::CustomPrivMsg {
+                            ref channel,
+                            ref mut message,
+                            ..
+                        },
+                    ..
                 } => {
                     (&mut self).dedup_message(channel, message);
                     Poll::Ready(Some(msg))
@@ -69,9 +108,10 @@ impl<St> DedupMessages<St>
 where
     St: Stream<Item = SentClientMessage> + Unpin,
 {
-    pub(crate) fn new(stream: St) -> Self {
+    pub(crate) fn new(stream: St, strategy: DedupStrategy) -> Self {
         Self {
             sent_messages: Default::default(),
+            strategy,
             stream,
         }
     }
@@ -87,7 +127,7 @@ where
             *sent_at >= instant - DEDUP_DURATION && past_message == msg.as_str()
         });
         if matching_message.is_some() {
-            msg.push(INVIS_CHAR);
+            msg.push(self.strategy.suffix());
         }
 
         self.sent_messages.insert(
@@ -100,6 +140,98 @@ where
     }
 }
 
+/// Suppresses identical consecutive commands (like `/slow 30`) to the same channel within a time
+/// window. See [`dedup_commands`](../trait.SendStreamExt.html#method.dedup_commands).
+pub struct DedupCommands<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    // map that holds the last command for each channel
+    sent_commands: FnvHashMap<String, MessageRecord>,
+    // commands repeated within this duration are suppressed
+    window: Duration,
+    // source stream
+    stream: St,
+}
+
+impl<St> Stream for DedupCommands<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    type Item = SentClientMessage;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<SentClientMessage>> {
+        loop {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(msg)) => {
+                    if let ClientMessage::PrivMsg {
+                        ref channel,
+                        ref message,
+                    }
+                    | ClientMessage::CustomPrivMsg {
+                        ref channel,
+                        ref message,
+                        ..
+                    } = msg.message
+                    {
+                        if is_command(message) && self.is_repeated(channel, message) {
+                            // the command is already in effect, report success without sending
+                            msg.responder.send(Ok(MessageResponse::Ok)).ok();
+                            continue;
+                        }
+                    }
+                    return Poll::Ready(Some(msg));
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// Whether a chat message is interpreted as a command by Twitch
+fn is_command(message: &str) -> bool {
+    message.starts_with('/') || message.starts_with('.')
+}
+
+impl<St> DedupCommands<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    pub(crate) fn new(stream: St, window: Duration) -> Self {
+        Self {
+            sent_commands: Default::default(),
+            window,
+            stream,
+        }
+    }
+
+    /// Checks whether the command repeats the last one sent to the channel within the window, and
+    /// records it as the last command otherwise
+    fn is_repeated(&mut self, channel: &str, command: &str) -> bool {
+        let instant = Instant::now();
+
+        let repeated = self
+            .sent_commands
+            .get(channel)
+            .filter(|record| record.sent_at + self.window > instant && record.message == command)
+            .is_some();
+        if !repeated {
+            self.sent_commands.insert(
+                channel.to_string(),
+                MessageRecord {
+                    sent_at: instant,
+                    message: command.to_string(),
+                },
+            );
+        }
+        repeated
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::iter::repeat;
@@ -109,8 +241,16 @@ mod test {
     use futures::{stream, SinkExt, StreamExt};
     use tokio::time::{advance, delay_for, pause};
 
+    use crate::stream::dedup::DedupStrategy;
     use crate::stream::{message_responder_channel, SendStreamExt, SentClientMessage};
-    use crate::ClientMessage;
+    use crate::{ClientMessage, MessageResponse};
+
+    fn sent(message: ClientMessage) -> SentClientMessage {
+        SentClientMessage {
+            message,
+            responder: message_responder_channel().0,
+        }
+    }
 
     #[tokio::test]
     async fn test_dedup() {
@@ -126,7 +266,7 @@ mod test {
             );
 
         let received = input_stream
-            .dedup()
+            .dedup(DedupStrategy::NullByte)
             .map(|m| m.message)
             .collect::<Vec<_>>()
             .await;
@@ -134,12 +274,107 @@ mod test {
             received,
             vec![
                 ClientMessage::message("#channel", "test"),
-                ClientMessage::message("#channel", "test\u{0}"),
+                ClientMessage::PrivMsg {
+                    channel: "#channel".to_string(),
+                    message: "test\u{0}".to_string()
+                },
                 ClientMessage::message("#channel", "test"),
             ]
         );
     }
 
+    #[tokio::test]
+    async fn test_dedup_strategies() {
+        for (strategy, suffix) in &[
+            (DedupStrategy::TrailingSpace, " "),
+            (DedupStrategy::Invisible, "\u{e0000}"),
+        ] {
+            let messages = vec![
+                ClientMessage::message("#channel", "test"),
+                ClientMessage::message("#other", "test"),
+                ClientMessage::message("#channel", "test"),
+                ClientMessage::message("#other", "different"),
+                ClientMessage::message("#channel", "test"),
+            ];
+            let received = stream::iter(messages.into_iter().map(sent))
+                .dedup(*strategy)
+                .map(|m| m.message)
+                .collect::<Vec<_>>()
+                .await;
+            let duplicate = ClientMessage::PrivMsg {
+                channel: "#channel".to_string(),
+                message: format!("test{}", suffix),
+            };
+            assert_eq!(
+                received,
+                vec![
+                    ClientMessage::message("#channel", "test"),
+                    ClientMessage::message("#other", "test"),
+                    duplicate,
+                    ClientMessage::message("#other", "different"),
+                    ClientMessage::message("#channel", "test"),
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dedup_commands() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut recv = recv.dedup_commands(Duration::from_secs(5));
+
+        let send = |message: ClientMessage| {
+            let (tx, rx) = message_responder_channel();
+            snd.unbounded_send(SentClientMessage {
+                message,
+                responder: tx,
+            })
+            .unwrap();
+            rx
+        };
+        send(ClientMessage::emote_only("#channel", true));
+        let suppressed = send(ClientMessage::emote_only("#channel", true));
+        send(ClientMessage::emote_only("#other", true));
+        let custom = ClientMessage::with_command("NOTICE", "#channel", "/slow 30").unwrap();
+        send(custom.clone());
+        let custom_suppressed = send(custom.clone());
+        // regular messages are left to the text dedup
+        send(ClientMessage::message("#channel", "test"));
+        send(ClientMessage::message("#channel", "test"));
+
+        assert_eq!(
+            recv.next().await.unwrap().message,
+            ClientMessage::emote_only("#channel", true)
+        );
+        assert_eq!(
+            recv.next().await.unwrap().message,
+            ClientMessage::emote_only("#other", true)
+        );
+        assert_eq!(recv.next().await.unwrap().message, custom);
+        assert!(matches!(suppressed.await.unwrap(), Ok(MessageResponse::Ok)));
+        assert_eq!(
+            recv.next().await.unwrap().message,
+            ClientMessage::message("#channel", "test")
+        );
+        assert!(matches!(
+            custom_suppressed.await.unwrap(),
+            Ok(MessageResponse::Ok)
+        ));
+        assert_eq!(
+            recv.next().await.unwrap().message,
+            ClientMessage::message("#channel", "test")
+        );
+
+        // sent again after the window passed
+        advance(Duration::from_secs(5)).await;
+        send(ClientMessage::emote_only("#channel", true));
+        assert_eq!(
+            recv.next().await.unwrap().message,
+            ClientMessage::emote_only("#channel", true)
+        );
+    }
+
     #[tokio::test]
     async fn test_dedup_timed() {
         pause();
@@ -159,7 +394,7 @@ mod test {
             }
         });
 
-        let mut recv = recv.dedup();
+        let mut recv = recv.dedup(DedupStrategy::NullByte);
 
         assert_eq!(
             recv.next().await.unwrap().message,
@@ -0,0 +1,119 @@
+//! Filters events by channel
+
+use std::ops::Deref;
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::event::{Event, HasChannel};
+
+/// Passes on only the events of a single channel. See
+/// [`for_channel`](../trait.ReceiveStreamExt.html#method.for_channel).
+#[derive(Debug)]
+pub struct ForChannel<St> {
+    stream: St,
+    channel: String,
+    pass_channelless: bool,
+}
+
+impl<St> ForChannel<St> {
+    pub(crate) fn new(stream: St, channel: String) -> Self {
+        ForChannel {
+            stream,
+            channel,
+            pass_channelless: true,
+        }
+    }
+
+    /// Also drop events that don't belong to any channel, like pings, whispers and connection
+    /// events, so that only events of the selected channel are passed on
+    pub fn channel_events_only(mut self) -> Self {
+        self.pass_channelless = false;
+        self
+    }
+
+    fn is_included(&self, event: &Event<String>) -> bool {
+        match event.channel() {
+            Some(channel) => *channel == self.channel,
+            None => self.pass_channelless,
+        }
+    }
+}
+
+impl<St, E> Stream for ForChannel<St>
+where
+    St: Stream<Item = E> + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => {
+                    if self.is_included(&event) {
+                        return Poll::Ready(Some(event));
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.stream.size_hint().1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::{stream, StreamExt};
+
+    use crate::event::Event;
+    use crate::stream::ReceiveStreamExt;
+    use crate::util::test_events::event;
+
+    fn events() -> Vec<Arc<Event<String>>> {
+        vec![
+            event(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello"),
+            event(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #other :hi"),
+            event("PING :tmi.twitch.tv"),
+            event(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas"),
+            event("@message-id=1;thread-id=1234_5678 :ronni!ronni@ronni.tmi.twitch.tv WHISPER bot :hi"),
+            event(":ronni!ronni@ronni.tmi.twitch.tv PART #other"),
+        ]
+    }
+
+    fn describe(event: Arc<Event<String>>) -> &'static str {
+        match *event {
+            Event::PrivMsg(_) => "privmsg",
+            Event::Ping(_) => "ping",
+            Event::Join(_) => "join",
+            Event::Whisper(_) => "whisper",
+            _ => "other",
+        }
+    }
+
+    #[tokio::test]
+    async fn test_for_channel() {
+        let passed = stream::iter(events())
+            .for_channel("#dallas")
+            .map(describe)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(passed, vec!["privmsg", "ping", "join", "whisper"]);
+
+        let passed = stream::iter(events())
+            .for_channel("#dallas")
+            .channel_events_only()
+            .map(describe)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(passed, vec!["privmsg", "join"]);
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures_core::Stream;
 
@@ -9,14 +10,28 @@ use dedup::*;
 use rate_limits::*;
 
 use crate::event::Event;
+use crate::stream::conversations::WhisperConversations;
+use crate::stream::for_channel::ForChannel;
+use crate::stream::pause::{Pausable, PauseHandle};
+use crate::stream::record::RecordLast;
 use crate::stream::split_oversize::SplitOversize;
+use crate::stream::throttle::{ThrottleMode, ThrottlePerChannel};
+use crate::stream::timestamped::Timestamped;
+use crate::stream::wait_for::WaitFor;
 use crate::{ClientMessage, Error, MessageResponse, MessageSendError};
 use std::borrow::Borrow;
 use tokio::sync::oneshot;
 
+pub mod conversations;
 pub mod dedup;
+pub mod for_channel;
+pub mod pause;
 pub mod rate_limits;
+pub mod record;
 pub mod split_oversize;
+pub mod throttle;
+pub mod timestamped;
+pub mod wait_for;
 
 /// A message and a receiver for its result/response
 #[derive(Debug)]
@@ -41,6 +56,14 @@ impl RateLimitable for SentClientMessage {
     fn channel_limits(&self) -> Option<&str> {
         self.message.channel_limits()
     }
+
+    fn global_limits(&self) -> &'static [&'static str] {
+        self.message.global_limits()
+    }
+
+    fn global_tokens(&self) -> usize {
+        self.message.global_tokens()
+    }
 }
 
 pub(crate) trait RespondWithErrors {
@@ -74,13 +97,24 @@ pub trait SendStreamExt: Stream<Item = SentClientMessage> {
         BufferedRateLimiter::new(self, capacity, rate_limiter)
     }
 
-    /// Automatically add `\u{0}` to de-duplicate subsequent messages, to bypass Twitch's 30 second
-    /// duplicate message detection
-    fn dedup(self) -> DedupMessages<Self>
+    /// Automatically alter a message that repeats the last message to the same channel within 30
+    /// seconds, to bypass Twitch's duplicate message detection. The character appended to the
+    /// duplicate is chosen by `strategy`, the message after it is sent unchanged again.
+    fn dedup(self, strategy: DedupStrategy) -> DedupMessages<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        DedupMessages::new(self, strategy)
+    }
+
+    /// Drops commands like `/slow 30` that repeat the last command sent to the same channel
+    /// within `window`, reporting them as sent. Unlike [`dedup`](#method.dedup), this only
+    /// applies to commands and suppresses them instead of altering them.
+    fn dedup_commands(self, window: Duration) -> DedupCommands<Self>
     where
         Self: Sized + Unpin,
     {
-        DedupMessages::new(self)
+        DedupCommands::new(self, window)
     }
 
     /// Splits messages over the given size limit into separate messages
@@ -90,12 +124,95 @@ pub trait SendStreamExt: Stream<Item = SentClientMessage> {
     {
         SplitOversize::new(self, max_len)
     }
+
+    /// Holds back channel messages and whispers while the given handle is paused and sends them
+    /// in their original order once it is resumed. Other messages are not affected and keep
+    /// passing through while messages are held back. The stream doesn't limit how many messages
+    /// it holds; a [`MessageSender`](../struct.MessageSender.html) sharing the handle waits for
+    /// resume before sending chat messages, so only those sent right before pausing are held.
+    fn pausable(self, handle: Arc<PauseHandle>) -> Pausable<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        Pausable::new(self, handle)
+    }
 }
 
 impl<St, E: Deref<Target = Event<String>>> ReceiveStreamExt<E> for St where St: Stream<Item = E> {}
 
 /// Extension trait with functions to manipulate the incoming stream of events
-pub trait ReceiveStreamExt<E: Deref<Target = Event<String>>>: Stream<Item = E> {}
+pub trait ReceiveStreamExt<E: Deref<Target = Event<String>>>: Stream<Item = E> {
+    /// Keep a copy of the last `n` events passing through the stream. The recorded events can be
+    /// read at any time using the handle from
+    /// [`RecordLast::recent_events`](record/struct.RecordLast.html#method.recent_events), for
+    /// example to include them in an error report.
+    fn record_last(self, n: usize) -> RecordLast<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        RecordLast::new(self, n)
+    }
+
+    /// Pair each event with the key of the whisper conversation it belongs to, derived from the
+    /// `thread-id` tag. Events other than whispers are paired with `None`. Useful to keep state
+    /// for each conversation.
+    fn whisper_conversations(self) -> WhisperConversations<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        WhisperConversations::new(self)
+    }
+
+    /// Pair each event with the local time it was received from this stream, for example to
+    /// measure latency or to order events received on different connections. The events are
+    /// passed on unchanged.
+    fn timestamped(self) -> Timestamped<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        Timestamped::new(self)
+    }
+
+    /// Only pass on events that belong to `channel` (including the leading `#`), such as its
+    /// messages, joins and room states. Events without a channel, like pings, whispers and
+    /// connection events, are passed on as well unless
+    /// [`ForChannel::channel_events_only`](for_channel/struct.ForChannel.html#method.channel_events_only)
+    /// is used.
+    fn for_channel(self, channel: impl Into<String>) -> ForChannel<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        ForChannel::new(self, channel.into())
+    }
+
+    /// Pass on at most one event per channel every `min_interval`, for example to keep a burst of
+    /// messages in one channel from overwhelming a bot. Depending on `mode`, events over the
+    /// limit are dropped or delayed. Events without a channel, like pings and connection events,
+    /// are always passed on immediately.
+    fn throttle_per_channel(
+        self,
+        min_interval: Duration,
+        mode: ThrottleMode,
+    ) -> ThrottlePerChannel<Self, E>
+    where
+        Self: Sized + Unpin,
+    {
+        ThrottlePerChannel::new(self, min_interval, mode)
+    }
+
+    /// Wait for the first event matching `predicate`, for example the first `ROOMSTATE` of a
+    /// channel after joining it. Resolves with `None` if the stream ends first. Events that don't
+    /// match are dropped unless they are forwarded using
+    /// [`WaitFor::forward_skipped`](wait_for/struct.WaitFor.html#method.forward_skipped); events
+    /// after the match are left in the stream.
+    fn wait_for<F>(&mut self, predicate: F) -> WaitFor<'_, Self, F, E>
+    where
+        Self: Sized + Unpin,
+        F: FnMut(&Event<String>) -> bool + Unpin,
+    {
+        WaitFor::new(self, predicate)
+    }
+}
 
 /// Auto-implemented shortctut trait for a stream of `Event<String>`, used in receiver middlewares
 pub trait EventStream: Stream<Item = Result<Event<String>, Error>> + Unpin + Send {}
@@ -111,6 +228,11 @@ pub trait ClientMessageStream: Stream<Item = SentClientMessage> + Unpin + Send +
 
 impl<T: Stream<Item = SentClientMessage> + Unpin + Send + Sync> ClientMessageStream for T {}
 
-/// Setup function for message sender middleware
-pub type SendMiddleware =
-    Arc<dyn Fn(ClientMessage) -> Result<Option<SentClientMessage>, Error> + Send + Sync + 'static>;
+/// Setup function for message sender middleware. Wraps the stream of sent messages before it is
+/// paused and rate limited, so messages it adds, like the chunks of
+/// [`split_oversize`](trait.SendStreamExt.html#method.split_oversize), are rate limited as well.
+/// Only messages sent through the client's `MessageSender` pass through it, internal messages
+/// like the login, rejoins and PONGs are sent without it.
+pub type SendMiddleware = Arc<
+    dyn Fn(Box<dyn ClientMessageStream>) -> Box<dyn ClientMessageStream> + Send + Sync + 'static,
+>;
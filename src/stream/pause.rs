@@ -0,0 +1,286 @@
+//! Holds back chat messages while sending is paused
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::future::poll_fn;
+use futures_util::task::AtomicWaker;
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+
+use crate::stream::SentClientMessage;
+use crate::ClientMessage;
+
+/// Shared switch to pause and resume sending chat messages, see
+/// [`pausable`](../trait.SendStreamExt.html#method.pausable)
+#[derive(Debug, Default)]
+pub struct PauseHandle {
+    paused: AtomicBool,
+    // one waker for each stream and each sender waiting for this handle
+    wakers: Mutex<Vec<Weak<AtomicWaker>>>,
+}
+
+impl PauseHandle {
+    /// Hold back chat messages until `resume` is called
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Send all held back messages and continue sending normally
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.wakers.lock().retain(|waker| match waker.upgrade() {
+            Some(waker) => {
+                waker.wake();
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Whether sending is currently paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Completes once sending is not paused (anymore)
+    pub(crate) fn resumed(&self) -> impl Future<Output = ()> + '_ {
+        let waker = Arc::new(AtomicWaker::new());
+        self.register(&waker);
+        poll_fn(move |cx| {
+            waker.register(cx.waker());
+            if self.is_paused() {
+                Poll::Pending
+            } else {
+                Poll::Ready(())
+            }
+        })
+    }
+
+    /// Wake `waker` on resume, until it is dropped. Wakers that were dropped already are removed
+    /// here as well as on resume, so the list doesn't grow while the handle stays paused.
+    fn register(&self, waker: &Arc<AtomicWaker>) {
+        let waker = Arc::downgrade(waker);
+        let mut wakers = self.wakers.lock();
+        wakers.retain(|registered| registered.upgrade().is_some());
+        if !wakers.iter().any(|registered| registered.ptr_eq(&waker)) {
+            wakers.push(waker);
+        }
+    }
+}
+
+/// Holds back channel messages and whispers while paused. See
+/// [`pausable`](../trait.SendStreamExt.html#method.pausable).
+pub struct Pausable<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    stream: St,
+    handle: Arc<PauseHandle>,
+    waker: Arc<AtomicWaker>,
+    // messages held back while paused, in the order they were sent
+    held: VecDeque<SentClientMessage>,
+    stream_done: bool,
+}
+
+impl<St> Pausable<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    pub(crate) fn new(stream: St, handle: Arc<PauseHandle>) -> Self {
+        let waker = Arc::new(AtomicWaker::new());
+        handle.register(&waker);
+        Pausable {
+            stream,
+            handle,
+            waker,
+            held: VecDeque::new(),
+            stream_done: false,
+        }
+    }
+}
+
+/// Messages that are affected by pausing. Everything else is needed to keep the connection
+/// working and is passed on.
+pub(crate) fn is_pausable(message: &ClientMessage) -> bool {
+    matches!(
+        message,
+        ClientMessage::PrivMsg { .. }
+            | ClientMessage::Reply { .. }
+            | ClientMessage::CustomPrivMsg { .. }
+            | ClientMessage::Whisper { .. }
+    )
+}
+
+impl<St> Stream for Pausable<St>
+where
+    St: Stream<Item = SentClientMessage> + Unpin,
+{
+    type Item = SentClientMessage;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.waker.register(cx.waker());
+        let paused = self.handle.is_paused();
+
+        if !paused {
+            if let Some(msg) = self.held.pop_front() {
+                return Poll::Ready(Some(msg));
+            }
+        }
+
+        // keep reading while paused, messages like PONGs and JOINs must not wait behind the held
+        // ones. Senders wait for resume before sending chat messages, which limits what is held.
+        while !self.stream_done {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(msg)) => {
+                    if paused && is_pausable(&msg.message) {
+                        self.held.push_back(msg);
+                    } else {
+                        return Poll::Ready(Some(msg));
+                    }
+                }
+                Poll::Ready(None) => self.stream_done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        if self.held.is_empty() {
+            Poll::Ready(None)
+        } else {
+            // wait for resume to send the held messages
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::{stream, StreamExt};
+    use futures_util::task::AtomicWaker;
+    use futures_util::FutureExt;
+
+    use crate::stream::pause::PauseHandle;
+    use crate::stream::{message_responder_channel, SendStreamExt, SentClientMessage};
+    use crate::ClientMessage;
+
+    #[tokio::test]
+    async fn test_pause() {
+        let handle = Arc::new(PauseHandle::default());
+        handle.pause();
+        let messages = vec![
+            ClientMessage::message("#channel", "first"),
+            ClientMessage::Ping,
+            ClientMessage::message("#channel", "second"),
+        ];
+        let mut stream = stream::iter(messages.into_iter().map(|message| SentClientMessage {
+            message,
+            responder: message_responder_channel().0,
+        }))
+        .pausable(handle.clone());
+
+        assert_eq!(
+            stream.next().now_or_never().unwrap().unwrap().message,
+            ClientMessage::Ping
+        );
+        assert!(stream.next().now_or_never().is_none());
+
+        handle.resume();
+        let received = stream.map(|msg| msg.message).collect::<Vec<_>>().await;
+        assert_eq!(
+            received,
+            vec![
+                ClientMessage::message("#channel", "first"),
+                ClientMessage::message("#channel", "second"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause_passes_internal_messages() {
+        let handle = Arc::new(PauseHandle::default());
+        handle.pause();
+        let (mut snd, recv) = futures::channel::mpsc::channel(0);
+        let mut stream = recv.pausable(handle.clone());
+        let sent = |message| SentClientMessage {
+            message,
+            responder: message_responder_channel().0,
+        };
+
+        for i in 0..100 {
+            snd.try_send(sent(ClientMessage::message("#channel", i.to_string())))
+                .unwrap();
+            assert!(stream.next().now_or_never().is_none());
+        }
+        // held messages don't keep the stream from reading
+        snd.try_send(sent(ClientMessage::Pong(vec![]))).unwrap();
+        assert_eq!(
+            stream.next().now_or_never().unwrap().unwrap().message,
+            ClientMessage::Pong(vec![])
+        );
+
+        handle.resume();
+        assert_eq!(
+            stream.next().await.unwrap().message,
+            ClientMessage::message("#channel", "0")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resumed() {
+        let handle = Arc::new(PauseHandle::default());
+        assert!(handle.resumed().now_or_never().is_some());
+
+        handle.pause();
+        let mut resumed = Box::pin(handle.resumed());
+        assert!((&mut resumed).now_or_never().is_none());
+        handle.resume();
+        assert!(resumed.now_or_never().is_some());
+    }
+
+    #[test]
+    fn test_register_prunes_wakers() {
+        let handle = PauseHandle::default();
+        let waker = Arc::new(AtomicWaker::new());
+        handle.register(&waker);
+        handle.register(&waker);
+        assert_eq!(handle.wakers.lock().len(), 1);
+
+        drop(waker);
+        handle.register(&Arc::new(AtomicWaker::new()));
+        assert_eq!(handle.wakers.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resume_wakes_all_streams() {
+        let handle = Arc::new(PauseHandle::default());
+        handle.pause();
+        let spawn_stream = |text: &'static str| {
+            let stream = stream::iter(vec![SentClientMessage {
+                message: ClientMessage::message("#channel", text),
+                responder: message_responder_channel().0,
+            }])
+            .pausable(handle.clone());
+            tokio::spawn(stream.map(|msg| msg.message).collect::<Vec<_>>())
+        };
+        let first = spawn_stream("first");
+        let second = spawn_stream("second");
+        tokio::time::delay_for(std::time::Duration::from_millis(10)).await;
+
+        handle.resume();
+        assert_eq!(
+            first.await.unwrap(),
+            vec![ClientMessage::message("#channel", "first")]
+        );
+        assert_eq!(
+            second.await.unwrap(),
+            vec![ClientMessage::message("#channel", "second")]
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! Helpers for rate limiting
 
 use core::pin::Pin;
+use std::cmp::Ordering;
 use std::collections::vec_deque::VecDeque;
 use std::iter::FromIterator;
 use std::time::Duration;
@@ -8,12 +9,12 @@ use std::time::Duration;
 use fnv::FnvHashMap;
 use futures_core::stream::{FusedStream, Stream};
 use futures_core::task::{Context, Poll};
-use futures_util::stream::StreamExt;
 use futures_util::FutureExt;
 use parking_lot::{Mutex, RwLock};
 use pin_utils::{unsafe_pinned, unsafe_unpinned};
 use std::borrow::Borrow;
-use tokio::time::{delay_for, Delay, Instant};
+
+use crate::runtime::{delay_for, delay_until, Delay, Instant};
 
 /// Trait to apply to messages that contains information about which rate limits apply
 /// to the message
@@ -22,34 +23,134 @@ pub trait RateLimitable {
     /// and rate limit buckets configured for that channel
     fn channel_limits(&self) -> Option<&str>;
 
+    /// Names of rate limit buckets that apply to the item regardless of the channel, used for
+    /// messages without a channel like whispers. Only used if `channel_limits` returns `None`.
+    fn global_limits(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Number of tokens the item takes from each of its global buckets, like the number of
+    /// channels joined by a single `JOIN` line
+    fn global_tokens(&self) -> usize {
+        1
+    }
+
     /// Poll for sending the item using the given rate limiter instance
     fn poll(&self, rate_limiter: &RateLimiter, cx: &mut Context<'_>) -> Poll<()> {
+        match self.poll_limits(rate_limiter, cx) {
+            Ok(()) => Poll::Ready(()),
+            Err(_) => Poll::Pending,
+        }
+    }
+
+    /// Like `poll`, but reports which limit is holding back the item. `Err` is returned in the
+    /// same cases where `poll` would return `Poll::Pending`. The reason is also stored in the rate
+    /// limiter, see [`RateLimiter::delay_reason`](struct.RateLimiter.html#method.delay_reason).
+    fn poll_limits(
+        &self,
+        rate_limiter: &RateLimiter,
+        cx: &mut Context<'_>,
+    ) -> Result<(), DelayReason> {
         if let Some(channel) = self.channel_limits() {
             rate_limiter.init_channel(channel);
 
-            let limits = rate_limiter.limits_map.read();
-            let buckets = rate_limiter.buckets.read();
+            let result = {
+                let limits = rate_limiter.limits_map.read();
+                let buckets = rate_limiter.buckets.read();
+
+                let channel_limits = limits.get(channel).expect("Get channel rate limits").read();
+                // nothing is used up unless every limit lets the message through
+                if channel_limits.poll_slow_mode(cx).is_pending() {
+                    Err(DelayReason::SlowMode)
+                } else if let Some(bucket_name) =
+                    exhausted_bucket(&buckets, &channel_limits.limit_buckets, 1)
+                {
+                    Err(DelayReason::Bucket(bucket_name))
+                } else {
+                    take_tokens(&buckets, &channel_limits.limit_buckets, 1);
+                    channel_limits.start_slow_mode_interval();
+                    Ok(())
+                }
+            };
+            rate_limiter.set_delay_reason(channel, result.err());
+            result
+        } else {
+            // no limits apply if there are no global buckets, always return ready
+            rate_limiter.poll_global_buckets(self.global_limits(), self.global_tokens())
+        }
+    }
+}
 
-            let channel_limits = limits.get(channel).expect("Get channel rate limits");
-            let slow_ready = channel_limits.read().poll_slow_mode(cx).is_ready();
-            if !slow_ready {
-                return Poll::Pending;
-            }
+/// The first of the named buckets that doesn't have enough tokens left. Buckets are checked
+/// before any token is taken, so a message held back by one bucket doesn't use up the others.
+fn exhausted_bucket(
+    buckets: &FnvHashMap<&'static str, RateLimitBucket>,
+    names: &[&'static str],
+    tokens: usize,
+) -> Option<&'static str> {
+    names.iter().copied().find(|&name| {
+        buckets
+            .get(name)
+            .map_or(false, |bucket| !bucket.has_tokens(tokens))
+    })
+}
 
-            let buckets_ready = channel_limits
-                .read()
-                .limit_buckets
-                .iter()
-                .filter_map(|&bucket_name| buckets.get(bucket_name))
-                .all(|mut bucket| (&mut bucket).poll_next_unpin(cx).is_ready());
-            if !buckets_ready {
-                return Poll::Pending;
-            }
+/// When an item held back for `reason` can be let through again, as far as the buckets are
+/// concerned. `None` for slow mode, its delay wakes the task by itself.
+fn refill_at<Item: RateLimitable>(
+    item: &Item,
+    rate_limiter: &RateLimiter,
+    reason: DelayReason,
+) -> Option<Instant> {
+    match reason {
+        DelayReason::Bucket(bucket_name) => {
+            // channel messages take a single token from each of their buckets
+            let tokens = if item.channel_limits().is_some() {
+                1
+            } else {
+                item.global_tokens()
+            };
+            rate_limiter.refill_at(bucket_name, tokens)
+        }
+        DelayReason::SlowMode => None,
+    }
+}
 
-            Poll::Ready(())
-        } else {
-            // no limits apply, always return ready
-            Poll::Ready(())
+/// The earlier of two optional points in time
+fn earliest(a: Option<Instant>, b: Option<Instant>) -> Option<Instant> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Take the given number of tokens from each of the named buckets
+fn take_tokens(
+    buckets: &FnvHashMap<&'static str, RateLimitBucket>,
+    names: &[&'static str],
+    tokens: usize,
+) {
+    for bucket in names.iter().filter_map(|&name| buckets.get(name)) {
+        bucket.take_tokens(tokens);
+    }
+}
+
+/// The limit that is currently holding back messages to a channel
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DelayReason {
+    /// Waiting for the slow mode interval to pass
+    SlowMode,
+    /// The rate limit bucket with the given name is exhausted
+    Bucket(&'static str),
+}
+
+impl DelayReason {
+    /// `slow_mode` or the name of the bucket, used as the `reason` label of the
+    /// `tmi.rate_limit.delayed` metric
+    pub fn name(&self) -> &'static str {
+        match self {
+            DelayReason::SlowMode => "slow_mode",
+            DelayReason::Bucket(bucket_name) => bucket_name,
         }
     }
 }
@@ -63,9 +164,13 @@ pub struct BufferedRateLimiter<
     Rl: Borrow<RateLimiter>,
 > {
     stream: St,
-    buf: VecDeque<Item>,
+    // items held back by the rate limits, with the time they were queued
+    buf: VecDeque<(Instant, Item)>,
     capacity: usize,
     rate_limiter: Rl,
+    /// wakes the task when an exhausted bucket holding back a buffered item is refilled,
+    /// nothing else would poll the stream again otherwise
+    refill_timer: Option<Delay>,
 }
 
 impl<St: Stream<Item = Item> + Unpin, Item: RateLimitable, Rl: Borrow<RateLimiter>> Unpin
@@ -77,7 +182,7 @@ impl<St: Stream<Item = Item> + Unpin, Item: RateLimitable, Rl: Borrow<RateLimite
     BufferedRateLimiter<St, Item, Rl>
 {
     unsafe_pinned!(stream: St);
-    unsafe_unpinned!(buf: VecDeque<Item>);
+    unsafe_unpinned!(buf: VecDeque<(Instant, Item)>);
     unsafe_unpinned!(capacity: usize);
 
     pub(super) fn new(stream: St, capacity: usize, rate_limiter: Rl) -> Self {
@@ -86,24 +191,58 @@ impl<St: Stream<Item = Item> + Unpin, Item: RateLimitable, Rl: Borrow<RateLimite
             buf: VecDeque::with_capacity(capacity),
             capacity,
             rate_limiter,
+            refill_timer: None,
         }
     }
 
-    fn pop_ready_item(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Option<Item> {
+    /// Take the first buffered item that can be sent now. Otherwise returns the earliest time
+    /// a bucket holding back one of the items is refilled.
+    fn pop_ready_item(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Result<Item, Option<Instant>> {
         let Self {
             buf,
             ref rate_limiter,
             ..
         } = Pin::into_inner(self);
-        let ready_item_idx = buf.iter().enumerate().find_map(|(i, item)| {
-            if item.poll(rate_limiter.borrow(), cx).is_ready() {
-                Some(i)
-            } else {
-                None
+        let rate_limiter = rate_limiter.borrow();
+        let mut next_refill = None;
+        let ready_item_idx = buf.iter().enumerate().find_map(|(i, (_, item))| {
+            match item.poll_limits(rate_limiter, cx) {
+                Ok(()) => Some(i),
+                Err(reason) => {
+                    next_refill = earliest(next_refill, refill_at(item, rate_limiter, reason));
+                    None
+                }
             }
         });
 
-        ready_item_idx.and_then(|i| buf.swap_remove_back(i))
+        match ready_item_idx.and_then(|i| buf.swap_remove_back(i)) {
+            Some((queued_at, item)) => {
+                record_duration!("tmi.rate_limit.delay", queued_at.elapsed());
+                Ok(item)
+            }
+            None => Err(next_refill),
+        }
+    }
+
+    /// Wait for the next bucket refill, or stop waiting if no buffered item needs one
+    fn poll_refill(self: Pin<&mut Self>, next_refill: Option<Instant>, cx: &mut Context<'_>) {
+        let refill_timer = &mut Pin::into_inner(self).refill_timer;
+        let deadline = match next_refill {
+            Some(deadline) => deadline,
+            None => {
+                refill_timer.take();
+                return;
+            }
+        };
+        let timer = refill_timer.get_or_insert_with(|| delay_until(deadline));
+        if timer.deadline() != deadline {
+            timer.reset(deadline);
+        }
+        if timer.poll_unpin(cx).is_ready() {
+            // refilled in the meantime, check the buffered items again
+            refill_timer.take();
+            cx.waker().wake_by_ref();
+        }
     }
 
     /// Get a shared reference to the inner sink.
@@ -140,30 +279,34 @@ where
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
         // return any ready items in the buffer if available
+        let mut next_refill = None;
         if !self.as_ref().buf.is_empty() {
-            let ready_item = self.as_mut().pop_ready_item(cx);
-            if ready_item.is_some() {
-                return Poll::Ready(ready_item);
+            match self.as_mut().pop_ready_item(cx) {
+                Ok(item) => return Poll::Ready(Some(item)),
+                Err(refill) => next_refill = refill,
             }
         }
-        match self.as_mut().stream().poll_next(cx) {
-            Poll::Ready(Some(item)) => {
-                if item.poll(self.rate_limiter.borrow(), cx).is_ready() {
-                    Poll::Ready(Some(item))
-                } else {
-                    self.as_mut().buf().push_back(item);
+        let result = match self.as_mut().stream().poll_next(cx) {
+            Poll::Ready(Some(item)) => match item.poll_limits(self.rate_limiter.borrow(), cx) {
+                Ok(()) => return Poll::Ready(Some(item)),
+                Err(reason) => {
+                    record_counter!("tmi.rate_limit.delayed", 1, "reason" => reason.name());
+                    let refill = refill_at(&item, self.rate_limiter.borrow(), reason);
+                    next_refill = earliest(next_refill, refill);
+                    self.as_mut().buf().push_back((Instant::now(), item));
                     Poll::Pending
                 }
-            }
+            },
             Poll::Ready(None) => {
                 if self.buf.is_empty() {
-                    Poll::Ready(None)
-                } else {
-                    Poll::Pending
+                    return Poll::Ready(None);
                 }
+                Poll::Pending
             }
             Poll::Pending => Poll::Pending,
-        }
+        };
+        self.poll_refill(next_refill, cx);
+        result
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -191,6 +334,31 @@ pub struct RateLimiterConfig {
     pub default_slow: SlowModeLimit,
     /// Default bucket names that apply to a message
     pub default_buckets: Vec<&'static str>,
+    /// Privileges in channels that are known up front. They are applied when the rate limiter is
+    /// created, before the first USERSTATE of the channel is received.
+    pub channel_privileges: FnvHashMap<String, ChannelPrivilege>,
+}
+
+/// Buckets applied to all whispers, see [`RateLimitable::global_limits`]. Twitch's limit on the
+/// number of distinct recipients per day is not enforced.
+pub(crate) const WHISPER_BUCKETS: &[&str] = &["whisper", "whisper-minute"];
+
+/// Bucket applied to all `JOIN` messages, see [`RateLimitable::global_limits`]
+pub(crate) const JOIN_BUCKETS: &[&str] = &["join"];
+
+fn insert_whisper_buckets(
+    map: &mut FnvHashMap<&'static str, RateLimitBucketConfig>,
+    per_second: usize,
+    per_minute: usize,
+) {
+    map.insert(
+        "whisper",
+        RateLimitBucketConfig::new(per_second, Duration::from_secs(1)),
+    );
+    map.insert(
+        "whisper-minute",
+        RateLimitBucketConfig::new(per_minute, Duration::from_secs(60)),
+    );
 }
 
 impl Default for RateLimiterConfig {
@@ -206,10 +374,16 @@ impl Default for RateLimiterConfig {
                     "privmsg",
                     RateLimitBucketConfig::new(20, Duration::from_secs(30)),
                 );
+                insert_whisper_buckets(&mut map, 3, 100);
+                map.insert(
+                    "join",
+                    RateLimitBucketConfig::new(20, Duration::from_secs(10)),
+                );
                 map
             },
             default_slow: SlowModeLimit::Global,
             default_buckets: vec!["privmsg", "privmsg-moderator"],
+            channel_privileges: Default::default(),
         }
     }
 }
@@ -228,10 +402,16 @@ impl RateLimiterConfig {
                     "privmsg",
                     RateLimitBucketConfig::new(50, Duration::from_secs(30)),
                 );
+                insert_whisper_buckets(&mut map, 10, 200);
+                map.insert(
+                    "join",
+                    RateLimitBucketConfig::new(20, Duration::from_secs(10)),
+                );
                 map
             },
             default_slow: SlowModeLimit::Global,
             default_buckets: vec!["privmsg", "privmsg-moderator"],
+            channel_privileges: Default::default(),
         }
     }
 
@@ -248,14 +428,89 @@ impl RateLimiterConfig {
                     "privmsg",
                     RateLimitBucketConfig::new(7500, Duration::from_secs(30)),
                 );
+                insert_whisper_buckets(&mut map, 20, 1200);
+                map.insert(
+                    "join",
+                    RateLimitBucketConfig::new(2000, Duration::from_secs(10)),
+                );
                 map
             },
             default_slow: SlowModeLimit::Global,
             default_buckets: vec!["privmsg", "privmsg-moderator"],
+            channel_privileges: Default::default(),
         }
     }
 }
 
+/// Type of the account used to connect, decides the global rate limits
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AccountType {
+    /// Regular user account
+    Normal,
+    /// Account that is a known bot
+    KnownBot,
+    /// Account that is a verified bot
+    VerifiedBot,
+}
+
+/// Builds the rate limiter configuration for an account type and the channels where the account
+/// is known to be a moderator or the broadcaster, so the higher limits apply from the start.
+///
+/// ```
+/// use tmi_rs::stream::rate_limits::{AccountType, RateLimiter, RateLimiterBuilder};
+///
+/// let rate_limiter: RateLimiter = RateLimiterBuilder::new(AccountType::KnownBot)
+///     .moderator_in(vec!["#somechannel", "#otherchannel"])
+///     .broadcaster_in("#mychannel")
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiterBuilder {
+    config: RateLimiterConfig,
+}
+
+impl RateLimiterBuilder {
+    /// Start with the default limits for the account type
+    pub fn new(account: AccountType) -> Self {
+        let config = match account {
+            AccountType::Normal => RateLimiterConfig::default(),
+            AccountType::KnownBot => RateLimiterConfig::known_bot(),
+            AccountType::VerifiedBot => RateLimiterConfig::verified_bot(),
+        };
+        RateLimiterBuilder { config }
+    }
+
+    /// Channels where the account is a moderator
+    pub fn moderator_in<S: Into<String>>(self, channels: impl IntoIterator<Item = S>) -> Self {
+        channels.into_iter().fold(self, |builder, channel| {
+            builder.privilege(channel, ChannelPrivilege::Moderator)
+        })
+    }
+
+    /// Channel that the account is the broadcaster of
+    pub fn broadcaster_in(self, channel: impl Into<String>) -> Self {
+        self.privilege(channel, ChannelPrivilege::Broadcaster)
+    }
+
+    /// Set the privilege of the account in a channel
+    pub fn privilege(mut self, channel: impl Into<String>, privilege: ChannelPrivilege) -> Self {
+        self.config
+            .channel_privileges
+            .insert(channel.into(), privilege);
+        self
+    }
+
+    /// The configuration, e.g. to use it as `TwitchClientConfig::rate_limiter`
+    pub fn config(self) -> RateLimiterConfig {
+        self.config
+    }
+
+    /// Create the rate limiter
+    pub fn build(self) -> RateLimiter {
+        RateLimiter::from(&self.config)
+    }
+}
+
 /// A reusable, thread-safe rate limiter that allows "slow mode" and bucket based rate limiting. It
 /// can be reconfigured at runtime, although this requires locking/mutexes so it shouldn't be done
 /// constantly to avoid performance issues.
@@ -265,13 +520,17 @@ impl RateLimiterConfig {
 pub struct RateLimiter {
     buckets: RwLock<FnvHashMap<&'static str, RateLimitBucket>>,
     limits_map: RwLock<FnvHashMap<String, RwLock<ChannelLimits>>>,
+    delay_reasons: RwLock<FnvHashMap<String, DelayReason>>,
     default_slow: SlowModeLimit,
     default_buckets: Vec<&'static str>,
+    /// privileges known up front from the configuration, applied again whenever a channel's
+    /// limits are reset
+    channel_privileges: FnvHashMap<String, ChannelPrivilege>,
 }
 
 impl From<&RateLimiterConfig> for RateLimiter {
     fn from(cfg: &RateLimiterConfig) -> Self {
-        RateLimiter {
+        let rate_limiter = RateLimiter {
             buckets: RwLock::new(
                 cfg.buckets
                     .iter()
@@ -279,9 +538,15 @@ impl From<&RateLimiterConfig> for RateLimiter {
                     .collect(),
             ),
             limits_map: Default::default(),
+            delay_reasons: Default::default(),
             default_slow: cfg.default_slow,
             default_buckets: cfg.default_buckets.clone(),
+            channel_privileges: cfg.channel_privileges.clone(),
+        };
+        for channel in cfg.channel_privileges.keys() {
+            rate_limiter.init_channel(channel);
         }
+        rate_limiter
     }
 }
 
@@ -299,42 +564,41 @@ impl RateLimiter {
         }
     }
 
-    /// Check whether the rate limiting buckets need an update to reflect the given mod status.
+    /// Check whether the rate limiting buckets need an update to reflect the given privilege.
     /// This check only needs a read lock from the RwLock, so it is always done before acquiring
     /// the (exclusive) write lock needed to actually update the limits.
-    fn limit_update_required(&self, channel: &str, is_mod: bool) -> bool {
+    fn limit_update_required(&self, channel: &str, privilege: ChannelPrivilege) -> bool {
         let limits_map = self.limits_map.read();
         let limits = limits_map.get(channel).unwrap().read();
-        let non_privileged_bucket = limits
-            .limit_buckets
-            .iter()
-            .enumerate()
-            .find_map(|(idx, b)| if *b == "privmsg" { Some(idx) } else { None });
-        match non_privileged_bucket {
-            Some(_) if is_mod => return true,
-            None if !is_mod => return true,
-            _ => {}
-        };
-        match limits.slow_mode {
-            SlowModeLimit::Channel(_) | SlowModeLimit::Global if is_mod => return true,
-            SlowModeLimit::Unlimited if !is_mod => return true,
-            _ => {}
-        };
-        false
+        let has_non_privileged_bucket = limits.limit_buckets.contains(&"privmsg");
+        has_non_privileged_bucket == privilege.has_moderator_limits()
+            || privilege != limits.privilege
+            || privilege.slow_mode(limits.channel_slow) != limits.slow_mode
     }
 
     /// Update the rate limiting buckets if necessary, when the user gains or loses mod status in
     /// a channel
     pub fn update_mod_status(&self, channel: &str, is_mod: bool) {
+        let privilege = if is_mod {
+            ChannelPrivilege::Moderator
+        } else {
+            ChannelPrivilege::None
+        };
+        self.update_privilege(channel, privilege)
+    }
+
+    /// Update the rate limiting buckets and slow mode if necessary, when the user's privilege in
+    /// a channel changes
+    pub fn update_privilege(&self, channel: &str, privilege: ChannelPrivilege) {
         self.init_channel(channel);
-        if !self.limit_update_required(channel, is_mod) {
+        if !self.limit_update_required(channel, privilege) {
             return;
         }
-        if is_mod {
-            info!("Applying moderator rate limits in channel {}.", channel);
-        } else {
-            info!("Applying non-moderator rate limits in channel {}.", channel);
-        }
+        info!(
+            "Applying {} rate limits in channel {}.",
+            privilege.description(),
+            channel
+        );
 
         let limits_map = self.limits_map.read();
         let mut limits = limits_map.get(channel).unwrap().write();
@@ -344,33 +608,191 @@ impl RateLimiter {
             .enumerate()
             .find_map(|(idx, b)| if *b == "privmsg" { Some(idx) } else { None });
         match non_privileged_bucket {
-            Some(non_privileged_bucket) if is_mod => {
+            Some(non_privileged_bucket) if privilege.has_moderator_limits() => {
                 limits.limit_buckets.swap_remove(non_privileged_bucket);
             }
-            None if !is_mod => {
+            None if !privilege.has_moderator_limits() => {
                 limits.limit_buckets.push("privmsg");
             }
             _ => {}
         };
-        match limits.slow_mode {
-            SlowModeLimit::Channel(_) | SlowModeLimit::Global if is_mod => {
-                limits.slow_mode = SlowModeLimit::Unlimited;
+        limits.privilege = privilege;
+        limits.slow_mode = privilege.slow_mode(limits.channel_slow);
+    }
+
+    /// Update the slow mode setting of a channel, as announced by ROOMSTATE. Unlike
+    /// [`set_slow_mode`](#method.set_slow_mode), the user's privilege in the channel still
+    /// applies, so moderators and VIPs are not held back by the channel's slow mode.
+    pub fn update_slow_mode(&self, channel: &str, slow: SlowModeLimit) {
+        self.init_channel(channel);
+        let limits_map = self.limits_map.read();
+        let mut limits = limits_map.get(channel).unwrap().write();
+        limits.channel_slow = slow;
+        let slow_mode = limits.privilege.slow_mode(slow);
+        if slow_mode != limits.slow_mode {
+            info!("Applying slow mode {:?} in channel {}.", slow_mode, channel);
+            limits.slow_mode = slow_mode;
+            limits.slow_mode_delay.write().take();
+        }
+    }
+
+    /// Slow mode currently applied to messages to a channel, `None` if no limits are stored for
+    /// the channel yet
+    pub fn slow_mode(&self, channel: &str) -> Option<SlowModeLimit> {
+        self.limits_map
+            .read()
+            .get(channel)
+            .map(|limits| limits.read().slow_mode)
+    }
+
+    /// Slow mode applied to channels that don't have slow mode enabled
+    pub fn default_slow(&self) -> SlowModeLimit {
+        self.default_slow
+    }
+
+    /// Remove the stored limits of a channel, e.g. after leaving it. The limits are initialized
+    /// with the defaults and the configured privilege again when the channel is used next time.
+    pub fn forget_channel(&self, channel: &str) {
+        self.limits_map.write().remove(channel);
+        self.delay_reasons.write().remove(channel);
+    }
+
+    /// Reset the limits of a channel to the defaults, for example after reconnecting when the
+    /// privileges and slow mode in the channel are not known until Twitch sends USERSTATE and
+    /// ROOMSTATE again. A privilege configured in
+    /// [`RateLimiterConfig::channel_privileges`](struct.RateLimiterConfig.html#structfield.channel_privileges)
+    /// is applied again.
+    pub fn reset_channel(&self, channel: &str) {
+        self.insert_default_limits(channel);
+        self.delay_reasons.write().remove(channel);
+    }
+
+    /// Why messages to a channel are currently held back, `None` if the last message to the
+    /// channel could be sent right away. Useful to debug rate limit settings.
+    pub fn delay_reason(&self, channel: &str) -> Option<DelayReason> {
+        self.delay_reasons.read().get(channel).copied()
+    }
+
+    fn set_delay_reason(&self, channel: &str, reason: Option<DelayReason>) {
+        // polled for every message, only take the write lock if the reason changed
+        if self.delay_reason(channel) == reason {
+            return;
+        }
+        match reason {
+            Some(reason) => {
+                debug!("Messages to {} are delayed: {:?}", channel, reason);
+                self.delay_reasons
+                    .write()
+                    .insert(channel.to_owned(), reason);
             }
-            SlowModeLimit::Unlimited if !is_mod => {
-                limits.slow_mode = SlowModeLimit::Global;
+            None => {
+                self.delay_reasons.write().remove(channel);
             }
-            _ => {}
-        };
+        }
+    }
+
+    fn poll_global_buckets(
+        &self,
+        names: &[&'static str],
+        tokens: usize,
+    ) -> Result<(), DelayReason> {
+        let buckets = self.buckets.read();
+        match exhausted_bucket(&buckets, names, tokens) {
+            Some(bucket_name) => Err(DelayReason::Bucket(bucket_name)),
+            None => {
+                take_tokens(&buckets, names, tokens);
+                Ok(())
+            }
+        }
+    }
+
+    /// When the named bucket has enough tokens again for an item taking `tokens` from it
+    fn refill_at(&self, bucket_name: &str, tokens: usize) -> Option<Instant> {
+        self.buckets
+            .read()
+            .get(bucket_name)
+            .and_then(|bucket| bucket.refill_at(tokens))
+    }
+
+    /// Whether limits are currently stored for a channel
+    pub fn tracks_channel(&self, channel: &str) -> bool {
+        self.limits_map.read().contains_key(channel)
     }
 
     fn init_channel(&self, channel: &str) {
         // if the channel was never queried before, insert the default setting
         let channel_exists = self.limits_map.read().contains_key(channel);
         if !channel_exists {
-            self.limits_map.write().insert(
-                channel.to_owned(),
-                ChannelLimits::new(self.default_slow, self.default_buckets.iter().cloned()).into(),
-            );
+            self.insert_default_limits(channel);
+        }
+    }
+
+    /// Replace the limits of a channel with the defaults and its configured privilege
+    fn insert_default_limits(&self, channel: &str) {
+        self.limits_map.write().insert(
+            channel.to_owned(),
+            ChannelLimits::new(self.default_slow, self.default_buckets.iter().cloned()).into(),
+        );
+        if let Some(&privilege) = self.channel_privileges.get(channel) {
+            self.update_privilege(channel, privilege);
+        }
+    }
+}
+
+/// Privilege of the user in a channel, determines which rate limits apply
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelPrivilege {
+    /// Regular user
+    None,
+    /// VIPs are exempt from channel slow mode, but are still limited to the regular message rate
+    Vip,
+    /// Moderators have the higher moderator message rate and no slow mode
+    Moderator,
+    /// Same limits as moderators
+    Broadcaster,
+}
+
+impl ChannelPrivilege {
+    /// Determine the highest privilege from a list of badge names, as found in the `badges` tag
+    /// of USERSTATE events
+    pub fn from_badges<'a>(badges: impl IntoIterator<Item = &'a str>) -> Self {
+        badges
+            .into_iter()
+            .fold(ChannelPrivilege::None, |privilege, badge| {
+                match (privilege, badge) {
+                    (_, "broadcaster") => ChannelPrivilege::Broadcaster,
+                    (ChannelPrivilege::Broadcaster, _) => privilege,
+                    (_, "moderator") => ChannelPrivilege::Moderator,
+                    (ChannelPrivilege::Moderator, _) => privilege,
+                    (_, "vip") => ChannelPrivilege::Vip,
+                    _ => privilege,
+                }
+            })
+    }
+
+    /// Whether the moderator message rate applies
+    fn has_moderator_limits(self) -> bool {
+        match self {
+            ChannelPrivilege::Moderator | ChannelPrivilege::Broadcaster => true,
+            ChannelPrivilege::None | ChannelPrivilege::Vip => false,
+        }
+    }
+
+    /// Slow mode that applies with this privilege, given the currently configured slow mode
+    fn slow_mode(self, current: SlowModeLimit) -> SlowModeLimit {
+        match self {
+            ChannelPrivilege::Moderator | ChannelPrivilege::Broadcaster => SlowModeLimit::Unlimited,
+            ChannelPrivilege::Vip => SlowModeLimit::Global,
+            ChannelPrivilege::None => current.max(SlowModeLimit::Global),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            ChannelPrivilege::None => "non-moderator",
+            ChannelPrivilege::Vip => "VIP",
+            ChannelPrivilege::Moderator => "moderator",
+            ChannelPrivilege::Broadcaster => "broadcaster",
         }
     }
 }
@@ -381,6 +803,9 @@ pub struct ChannelLimits {
     slow_mode: SlowModeLimit,
     slow_mode_delay: RwLock<Option<Delay>>,
     limit_buckets: Vec<&'static str>,
+    /// Slow mode setting of the channel, before applying the privilege
+    channel_slow: SlowModeLimit,
+    privilege: ChannelPrivilege,
 }
 
 impl ChannelLimits {
@@ -390,6 +815,8 @@ impl ChannelLimits {
             slow_mode: slow,
             slow_mode_delay: RwLock::new(None),
             limit_buckets: Vec::from_iter(limit_buckets),
+            channel_slow: slow,
+            privilege: ChannelPrivilege::None,
         }
     }
 
@@ -397,6 +824,7 @@ impl ChannelLimits {
     pub fn set_slow_mode(&mut self, slow: SlowModeLimit) {
         let mut delay = self.slow_mode_delay.write();
         self.slow_mode = slow;
+        self.channel_slow = slow;
         delay.take();
     }
 
@@ -408,39 +836,38 @@ impl ChannelLimits {
 
     #[inline]
     fn reset_slow_mode(&self) {
-        if let Some(delay) = self.slow_mode.next_delay() {
+        if let Some(delay) = self.slow_mode.interval().map(delay_for) {
             self.slow_mode_delay.write().replace(delay);
         } else {
             self.slow_mode_delay.write().take();
         }
     }
 
+    /// Whether the slow mode interval has passed. Doesn't start the next interval, see
+    /// `start_slow_mode_interval`.
     fn poll_slow_mode(&self, cx: &mut Context<'_>) -> Poll<()> {
-        if self.slow_mode == SlowModeLimit::Unlimited {
+        if self.slow_mode == SlowModeLimit::Unlimited || self.slow_mode_delay.read().is_none() {
             return Poll::Ready(());
         }
-        if self.slow_mode_delay.read().is_some() {
-            if let Some(delay) = self.slow_mode_delay.write().as_mut() {
-                match delay.poll_unpin(cx) {
-                    Poll::Ready(_) => {
-                        if let Some(next_delay) = self.slow_mode.next_delay() {
-                            delay.reset(next_delay.deadline());
-                        }
-                        Poll::Ready(())
-                    }
-                    Poll::Pending => Poll::Pending,
-                }
-            } else {
-                unreachable!()
-            }
-        } else {
+        match self.slow_mode_delay.write().as_mut() {
+            Some(delay) => delay.poll_unpin(cx),
+            None => Poll::Ready(()),
+        }
+    }
+
+    /// Start the next slow mode interval after a message was let through
+    fn start_slow_mode_interval(&self) {
+        if self.slow_mode != SlowModeLimit::Unlimited {
             self.reset_slow_mode();
-            Poll::Ready(())
         }
     }
 }
 
 /// Slow mode configuration for a channel
+///
+/// Limits are ordered by strictness: `Unlimited < Global < Channel(n)`, with channel limits
+/// ordered by their duration. `max` and `min` therefore pick the stricter or looser of two limits.
+/// Note that `Channel(0)` is ordered above `Global` even though it doesn't delay messages.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SlowModeLimit {
     /// limit to an amount of seconds per message
@@ -453,13 +880,47 @@ pub enum SlowModeLimit {
 
 impl SlowModeLimit {
     /// Get the next time when a message can be posted within the limits
-    pub fn next_delay(&self) -> Option<Delay> {
+    pub fn next_delay(&self) -> Option<tokio::time::Delay> {
+        self.interval().map(tokio::time::delay_for)
+    }
+
+    /// Minimum time between two messages, `None` if unlimited
+    fn interval(&self) -> Option<Duration> {
         match self {
-            SlowModeLimit::Channel(secs) => Some(delay_for(Duration::from_secs(*secs as u64))),
-            SlowModeLimit::Global => Some(delay_for(Duration::from_secs(1))),
+            SlowModeLimit::Channel(secs) => Some(Duration::from_secs(*secs as u64)),
+            SlowModeLimit::Global => Some(Duration::from_secs(1)),
             SlowModeLimit::Unlimited => None,
         }
     }
+
+    /// Whether this limit is stricter than `other`
+    pub fn is_stricter_than(&self, other: &SlowModeLimit) -> bool {
+        self > other
+    }
+
+    /// Strictness rank of the variant, channel limits are further ordered by duration
+    fn rank(&self) -> u8 {
+        match self {
+            SlowModeLimit::Unlimited => 0,
+            SlowModeLimit::Global => 1,
+            SlowModeLimit::Channel(_) => 2,
+        }
+    }
+}
+
+impl PartialOrd for SlowModeLimit {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SlowModeLimit {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SlowModeLimit::Channel(a), SlowModeLimit::Channel(b)) => a.cmp(b),
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
 }
 
 /// Semaphore based rate limit bucket with configurable refill delay and capacity. The semaphore is
@@ -510,6 +971,64 @@ impl RateLimitBucket {
         *self.counter.lock() > 0
     }
 
+    /// Whether the given number of tokens can be taken. Items needing more tokens than the
+    /// capacity wait for a full bucket.
+    fn has_tokens(&self, tokens: usize) -> bool {
+        self.refill();
+        *self.counter.lock() >= tokens.min(self.cfg.capacity.max(1))
+    }
+
+    /// Take tokens after checking `has_tokens`. Tokens beyond the capacity are taken from the
+    /// following refill periods, as if the item had been split into chunks of the capacity that
+    /// were sent one period apart.
+    fn take_tokens(&self, tokens: usize) {
+        let mut count = self.counter.lock();
+        let now = Instant::now();
+        let mut queue = self.refill_queue.write();
+        let mut refill_at = |time: Instant, tokens: usize| {
+            for _ in 0..tokens {
+                queue.push_back(time);
+            }
+        };
+        let delay = self.cfg.refill_delay;
+        if tokens <= *count {
+            *count -= tokens;
+            refill_at(now + delay, tokens);
+        } else {
+            // the bucket is full, see `has_tokens`
+            let capacity = self.cfg.capacity;
+            let periods = (tokens - 1) / capacity;
+            let last_chunk = tokens - periods * capacity;
+            *count = 0;
+            refill_at(now + delay * periods as u32, capacity - last_chunk);
+            refill_at(now + delay * (periods as u32 + 1), last_chunk);
+        }
+    }
+
+    /// When the bucket has enough tokens again for `has_tokens(tokens)`, `None` if it has them
+    /// already
+    fn refill_at(&self, tokens: usize) -> Option<Instant> {
+        let needed = tokens.min(self.cfg.capacity.max(1));
+        let count = *self.counter.lock();
+        if count >= needed {
+            return None;
+        }
+        self.refill_queue.read().get(needed - count - 1).copied()
+    }
+
+    fn take_token(&self) -> bool {
+        let mut count = self.counter.lock();
+        if *count > 0 {
+            *count -= 1;
+            self.refill_queue
+                .write()
+                .push_back(Instant::now() + self.cfg.refill_delay);
+            true
+        } else {
+            false
+        }
+    }
+
     fn refill(&self) {
         let time = Instant::now();
         let mut queue = self.refill_queue.write();
@@ -526,12 +1045,7 @@ impl Stream for &RateLimitBucket {
     fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         self.refill();
 
-        let mut count = self.counter.lock();
-        if *count > 0 {
-            *count -= 1;
-            self.refill_queue
-                .write()
-                .push_back(Instant::now() + self.cfg.refill_delay);
+        if self.take_token() {
             Poll::Ready(Some(()))
         } else {
             Poll::Pending
@@ -551,8 +1065,8 @@ mod test {
     use tokio_test::{assert_pending, assert_ready, assert_ready_eq};
 
     use crate::stream::rate_limits::{
-        RateLimitBucket, RateLimitBucketConfig, RateLimitable, RateLimiter, RateLimiterConfig,
-        SlowModeLimit,
+        AccountType, ChannelPrivilege, DelayReason, RateLimitBucket, RateLimitBucketConfig,
+        RateLimitable, RateLimiter, RateLimiterBuilder, RateLimiterConfig, SlowModeLimit,
     };
     use crate::stream::{message_responder_channel, SendStreamExt, SentClientMessage};
     use crate::ClientMessage;
@@ -573,6 +1087,8 @@ mod test {
         }
     }
 
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
     #[tokio::test]
     async fn test_default_slowmode() {
         let cx = &mut noop_context();
@@ -651,6 +1167,8 @@ mod test {
         );
     }
 
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
     #[tokio::test]
     async fn test_custom_slowmode() {
         let cx = &mut noop_context();
@@ -709,4 +1227,353 @@ mod test {
         assert_ready!(b.poll_next_unpin(cx));
         assert_pending!(b.poll_next_unpin(cx));
     }
+
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
+    #[tokio::test]
+    async fn test_delay_reason() {
+        let cx = &mut noop_context();
+        pause();
+        let mut cfg = RateLimiterConfig::default();
+        cfg.buckets.insert(
+            "tiny",
+            RateLimitBucketConfig::new(1, Duration::from_secs(30)),
+        );
+        cfg.default_buckets = vec!["tiny"];
+        let rate_limiter: RateLimiter = (&cfg).into();
+        let message = example_message().message;
+
+        assert_eq!(message.poll_limits(&rate_limiter, cx), Ok(()));
+        assert_eq!(rate_limiter.delay_reason("#channel"), None);
+        assert_eq!(
+            message.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::SlowMode)
+        );
+        assert_eq!(
+            rate_limiter.delay_reason("#channel"),
+            Some(DelayReason::SlowMode)
+        );
+
+        advance(Duration::from_millis(1100)).await;
+        assert_eq!(
+            message.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("tiny"))
+        );
+        assert_eq!(
+            rate_limiter.delay_reason("#channel"),
+            Some(DelayReason::Bucket("tiny"))
+        );
+    }
+
+    #[test]
+    fn test_vip_privilege() {
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+        let privilege = ChannelPrivilege::from_badges(vec!["vip", "subscriber"]);
+        assert_eq!(privilege, ChannelPrivilege::Vip);
+        rate_limiter.set_slow_mode("#channel", SlowModeLimit::Channel(30));
+        rate_limiter.update_privilege("#channel", privilege);
+        {
+            let limits_map = rate_limiter.limits_map.read();
+            let limits = limits_map.get("#channel").unwrap().read();
+            assert!(limits.limit_buckets.contains(&"privmsg"));
+            assert_eq!(limits.slow_mode, SlowModeLimit::Global);
+        }
+
+        let privilege = ChannelPrivilege::from_badges(vec!["vip", "moderator"]);
+        assert_eq!(privilege, ChannelPrivilege::Moderator);
+        rate_limiter.update_privilege("#channel", privilege);
+        let limits_map = rate_limiter.limits_map.read();
+        let limits = limits_map.get("#channel").unwrap().read();
+        assert!(!limits.limit_buckets.contains(&"privmsg"));
+        assert_eq!(limits.slow_mode, SlowModeLimit::Unlimited);
+    }
+
+    #[test]
+    fn test_reset_channel() {
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+        rate_limiter.set_slow_mode("#channel", SlowModeLimit::Channel(30));
+        rate_limiter.update_privilege("#channel", ChannelPrivilege::Moderator);
+        rate_limiter.reset_channel("#channel");
+        {
+            let limits_map = rate_limiter.limits_map.read();
+            let limits = limits_map.get("#channel").unwrap().read();
+            assert_eq!(limits.limit_buckets, vec!["privmsg", "privmsg-moderator"]);
+            assert_eq!(limits.slow_mode, SlowModeLimit::Global);
+        }
+
+        // relearned from the next USERSTATE
+        rate_limiter.update_privilege("#channel", ChannelPrivilege::Moderator);
+        let limits_map = rate_limiter.limits_map.read();
+        let limits = limits_map.get("#channel").unwrap().read();
+        assert_eq!(limits.limit_buckets, vec!["privmsg-moderator"]);
+        assert_eq!(limits.slow_mode, SlowModeLimit::Unlimited);
+    }
+
+    #[test]
+    fn test_reset_keeps_configured_privilege() {
+        let rate_limiter = RateLimiterBuilder::new(AccountType::Normal)
+            .moderator_in(vec!["#modded"])
+            .build();
+        for _ in 0..2 {
+            let limits_map = rate_limiter.limits_map.read();
+            let limits = limits_map.get("#modded").unwrap().read();
+            assert_eq!(limits.privilege, ChannelPrivilege::Moderator);
+            assert_eq!(limits.limit_buckets, vec!["privmsg-moderator"]);
+            drop(limits);
+            drop(limits_map);
+            rate_limiter.reset_channel("#modded");
+        }
+
+        // forgotten channels get the configured privilege again when used next time
+        rate_limiter.forget_channel("#modded");
+        rate_limiter.update_slow_mode("#modded", SlowModeLimit::Channel(30));
+        assert_eq!(
+            rate_limiter.slow_mode("#modded"),
+            Some(SlowModeLimit::Unlimited)
+        );
+    }
+
+    #[test]
+    fn test_update_slow_mode() {
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+        rate_limiter.update_slow_mode("#channel", SlowModeLimit::Channel(30));
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Channel(30))
+        );
+
+        // privileges take precedence over the channel's slow mode, in either order
+        rate_limiter.update_privilege("#channel", ChannelPrivilege::Moderator);
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Unlimited)
+        );
+        rate_limiter.update_slow_mode("#channel", SlowModeLimit::Channel(60));
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Unlimited)
+        );
+        rate_limiter.update_privilege("#channel", ChannelPrivilege::Vip);
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Global)
+        );
+
+        // losing the privilege applies the channel's slow mode again
+        rate_limiter.update_privilege("#channel", ChannelPrivilege::None);
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Channel(60))
+        );
+        rate_limiter.update_slow_mode("#channel", SlowModeLimit::Global);
+        assert_eq!(
+            rate_limiter.slow_mode("#channel"),
+            Some(SlowModeLimit::Global)
+        );
+    }
+
+    #[test]
+    fn test_builder_privileges() {
+        let rate_limiter = RateLimiterBuilder::new(AccountType::KnownBot)
+            .moderator_in(vec!["#modded"])
+            .broadcaster_in("#own")
+            .build();
+        for channel in &["#modded", "#own"] {
+            let limits_map = rate_limiter.limits_map.read();
+            let limits = limits_map.get(*channel).unwrap().read();
+            assert_eq!(limits.limit_buckets, vec!["privmsg-moderator"]);
+            assert_eq!(limits.slow_mode, SlowModeLimit::Unlimited);
+        }
+        assert!(!rate_limiter.tracks_channel("#other"));
+        assert_eq!(
+            rate_limiter
+                .buckets
+                .read()
+                .get("privmsg")
+                .unwrap()
+                .cfg
+                .capacity,
+            50
+        );
+    }
+
+    #[tokio::test]
+    async fn test_whisper_buckets() {
+        let cx = &mut noop_context();
+        pause();
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+        let whisper = ClientMessage::whisper("someone", "hi");
+
+        for _ in 0..3 {
+            assert_eq!(whisper.poll_limits(&rate_limiter, cx), Ok(()));
+        }
+        assert_eq!(
+            whisper.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("whisper"))
+        );
+        // channel messages aren't affected
+        assert_eq!(example_message().poll_limits(&rate_limiter, cx), Ok(()));
+
+        for _ in 0..32 {
+            advance(Duration::from_millis(1001)).await;
+            for _ in 0..3 {
+                assert_eq!(whisper.poll_limits(&rate_limiter, cx), Ok(()));
+            }
+        }
+        // 99 whispers were sent within the minute
+        advance(Duration::from_millis(1001)).await;
+        assert_eq!(whisper.poll_limits(&rate_limiter, cx), Ok(()));
+        assert_eq!(
+            whisper.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("whisper-minute"))
+        );
+        // the held back whisper didn't use up the per second bucket
+        assert_eq!(*rate_limiter.buckets.read()["whisper"].counter.lock(), 2);
+    }
+
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
+    #[tokio::test]
+    async fn test_pending_keeps_tokens() {
+        let cx = &mut noop_context();
+        pause();
+        let mut cfg = RateLimiterConfig::default();
+        cfg.buckets.insert(
+            "tiny",
+            RateLimitBucketConfig::new(1, Duration::from_secs(30)),
+        );
+        cfg.default_buckets = vec!["privmsg", "tiny"];
+        let rate_limiter: RateLimiter = (&cfg).into();
+        let message = example_message().message;
+
+        assert_eq!(message.poll_limits(&rate_limiter, cx), Ok(()));
+        for _ in 0..5 {
+            advance(Duration::from_millis(1100)).await;
+            assert_eq!(
+                message.poll_limits(&rate_limiter, cx),
+                Err(DelayReason::Bucket("tiny"))
+            );
+        }
+        assert_eq!(*rate_limiter.buckets.read()["privmsg"].counter.lock(), 19);
+
+        advance(Duration::from_secs(30)).await;
+        assert_eq!(message.poll_limits(&rate_limiter, cx), Ok(()));
+    }
+
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
+    #[tokio::test]
+    async fn test_bucket_refill_wakes_stream() {
+        use futures::channel::mpsc::unbounded;
+        use tokio::time::{timeout, Instant};
+
+        pause();
+        let rate_limiter = Arc::new((&RateLimiterConfig::default()).into());
+        // the sender stays open, so nothing but the refill can wake the stream
+        let (sender, receiver) = unbounded();
+        let mut stream = receiver.rate_limited(10, rate_limiter);
+        for _ in 0..4 {
+            sender
+                .unbounded_send(SentClientMessage {
+                    message: ClientMessage::whisper("someone", "hi"),
+                    responder: message_responder_channel().0,
+                })
+                .unwrap();
+        }
+        for _ in 0..3 {
+            stream.next().await.unwrap();
+        }
+
+        let start = Instant::now();
+        let held_back = timeout(Duration::from_secs(5), stream.next())
+            .await
+            .expect("sent after the whisper bucket is refilled");
+        assert_eq!(
+            held_back.unwrap().message,
+            ClientMessage::whisper("someone", "hi")
+        );
+        // the timer rounds the deadline up to the next millisecond
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(1));
+        assert!(elapsed < Duration::from_millis(1001));
+    }
+
+    #[tokio::test]
+    async fn test_join_bucket() {
+        let cx = &mut noop_context();
+        pause();
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+
+        for i in 0..20 {
+            let join = ClientMessage::join(format!("#channel{}", i));
+            assert_eq!(join.poll_limits(&rate_limiter, cx), Ok(()));
+        }
+        let join = ClientMessage::join("#one_too_many");
+        assert_eq!(
+            join.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("join"))
+        );
+        // channel messages aren't affected
+        assert_eq!(example_message().poll_limits(&rate_limiter, cx), Ok(()));
+
+        advance(Duration::from_millis(10001)).await;
+        assert_eq!(join.poll_limits(&rate_limiter, cx), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_join_all_bucket() {
+        let cx = &mut noop_context();
+        pause();
+        let rate_limiter: RateLimiter = (&RateLimiterConfig::default()).into();
+        let channels = |count: usize| (0..count).map(|i| format!("#channel{}", i));
+
+        for _ in 0..3 {
+            let join_all = ClientMessage::join_all(channels(5));
+            assert_eq!(join_all.poll_limits(&rate_limiter, cx), Ok(()));
+        }
+        let join_all = ClientMessage::join_all(channels(6));
+        assert_eq!(
+            join_all.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("join"))
+        );
+        assert_eq!(*rate_limiter.buckets.read()["join"].counter.lock(), 5);
+
+        // more channels than the bucket's capacity wait for a full bucket and use up the
+        // following periods as well
+        let join_all = ClientMessage::join_all(channels(30));
+        assert_eq!(
+            join_all.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("join"))
+        );
+        advance(Duration::from_millis(10001)).await;
+        assert_eq!(join_all.poll_limits(&rate_limiter, cx), Ok(()));
+        let join = ClientMessage::join("#one_too_many");
+        advance(Duration::from_millis(10001)).await;
+        for _ in 0..10 {
+            assert_eq!(join.poll_limits(&rate_limiter, cx), Ok(()));
+        }
+        assert_eq!(
+            join.poll_limits(&rate_limiter, cx),
+            Err(DelayReason::Bucket("join"))
+        );
+        advance(Duration::from_millis(10001)).await;
+        assert_eq!(join.poll_limits(&rate_limiter, cx), Ok(()));
+    }
+
+    #[test]
+    fn test_slow_mode_ordering() {
+        use SlowModeLimit::*;
+        assert!(Global.is_stricter_than(&Unlimited));
+        assert!(Channel(0).is_stricter_than(&Global));
+        assert!(Channel(30).is_stricter_than(&Channel(10)));
+        assert!(!Channel(10).is_stricter_than(&Channel(10)));
+        assert!(!Unlimited.is_stricter_than(&Channel(120)));
+
+        let mut limits = vec![Channel(30), Unlimited, Channel(5), Global];
+        limits.sort();
+        assert_eq!(limits, [Unlimited, Global, Channel(5), Channel(30)]);
+        assert_eq!(Channel(5).max(Channel(30)), Channel(30));
+        assert_eq!(Channel(5).min(Global), Global);
+        assert_eq!(Unlimited.max(Global), Global);
+    }
 }
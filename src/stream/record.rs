@@ -0,0 +1,123 @@
+//! Keeps the most recent events of a stream, e.g. to attach them to crash reports
+
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+
+use crate::event::Event;
+
+/// Shared access to the events kept by [`record_last`](../trait.ReceiveStreamExt.html#method.record_last)
+#[derive(Debug, Clone)]
+pub struct RecentEvents {
+    capacity: usize,
+    events: Arc<Mutex<VecDeque<Event<String>>>>,
+}
+
+impl RecentEvents {
+    fn new(capacity: usize) -> Self {
+        RecentEvents {
+            capacity,
+            events: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    fn push(&self, event: Event<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.lock();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(event);
+    }
+
+    /// Copy of the recorded events, oldest first
+    pub fn events(&self) -> Vec<Event<String>> {
+        self.events.lock().iter().cloned().collect()
+    }
+}
+
+/// Passes on all events unchanged while keeping a copy of the most recent ones. See
+/// [`record_last`](../trait.ReceiveStreamExt.html#method.record_last).
+#[derive(Debug)]
+pub struct RecordLast<St> {
+    stream: St,
+    recent: RecentEvents,
+}
+
+impl<St> RecordLast<St> {
+    pub(crate) fn new(stream: St, capacity: usize) -> Self {
+        RecordLast {
+            stream,
+            recent: RecentEvents::new(capacity),
+        }
+    }
+
+    /// Handle to read the recorded events, stays usable after the stream is dropped
+    pub fn recent_events(&self) -> RecentEvents {
+        self.recent.clone()
+    }
+}
+
+impl<St, E> Stream for RecordLast<St>
+where
+    St: Stream<Item = E> + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.stream.poll_next_unpin(cx);
+        if let Poll::Ready(Some(event)) = &poll {
+            self.recent.push(event.deref().clone());
+        }
+        poll
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    use futures::{stream, StreamExt};
+
+    use crate::event::Event;
+    use crate::irc::IrcMessage;
+    use crate::stream::ReceiveStreamExt;
+
+    #[tokio::test]
+    async fn test_record_last() {
+        let events = (0..5)
+            .map(|i| {
+                let line = format!(
+                    ":someone!someone@someone.tmi.twitch.tv PRIVMSG #channel :{}",
+                    i
+                );
+                let event = Event::try_from(IrcMessage::parse(&line).unwrap().1).unwrap();
+                Arc::new(Event::<String>::from(&event))
+            })
+            .collect::<Vec<_>>();
+        let recorded = stream::iter(events.clone()).record_last(3);
+        let recent = recorded.recent_events();
+        assert_eq!(recorded.collect::<Vec<_>>().await, events);
+        assert_eq!(
+            recent.events(),
+            events[2..]
+                .iter()
+                .map(|event| Event::clone(event))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -7,8 +7,14 @@ use futures_core::task::{Context, Poll};
 use futures_core::Stream;
 use futures_util::stream::FuturesUnordered;
 
+use crate::client_messages::{action_text, announce_prefix, ACTION_PREFIX, ACTION_SUFFIX};
+use crate::runtime;
 use crate::stream::{message_responder_channel, MessageResponder, SentClientMessage};
-use crate::ClientMessage;
+use crate::{ClientMessage, MessageResponse};
+
+/// Default for the maximum number of chunks a single message is split into, see
+/// [`SplitOversize::max_chunks`](struct.SplitOversize.html#method.max_chunks)
+pub const DEFAULT_MAX_CHUNKS: usize = 100;
 
 /// Splits oversize messages into multiple messages. See [`split_oversize`](../trait.SendStreamExt.html#method.split_oversize)
 pub struct SplitOversize<St>
@@ -18,6 +24,7 @@ where
     stream: St,
     pending_messages: VecDeque<SentClientMessage>,
     max_len: usize,
+    max_chunks: usize,
 }
 
 impl<St> Stream for SplitOversize<St>
@@ -46,11 +53,57 @@ where
                         ref message,
                         ref channel,
                     } => {
-                        if message.len() > self.max_len {
+                        if message.len() > max_len {
+                            self.queue_split_channel_message(message, responder, |text| {
+                                ClientMessage::PrivMsg {
+                                    channel: channel.to_string(),
+                                    message: text,
+                                }
+                            });
+                            Poll::Ready(self.pop_queue())
+                        } else {
+                            Poll::Ready(Some(SentClientMessage {
+                                message: msg,
+                                responder,
+                            }))
+                        }
+                    }
+                    ClientMessage::CustomPrivMsg {
+                        ref command,
+                        ref channel,
+                        ref message,
+                    } => {
+                        if message.len() > max_len {
+                            // every chunk is sent with the same command
+                            self.queue_split_channel_message(message, responder, |text| {
+                                ClientMessage::CustomPrivMsg {
+                                    command: command.to_string(),
+                                    channel: channel.to_string(),
+                                    message: text,
+                                }
+                            });
+                            Poll::Ready(self.pop_queue())
+                        } else {
+                            Poll::Ready(Some(SentClientMessage {
+                                message: msg,
+                                responder,
+                            }))
+                        }
+                    }
+                    ClientMessage::Reply {
+                        ref channel,
+                        ref parent_msg_id,
+                        ref message,
+                    } => {
+                        if message.len() > max_len {
+                            // every chunk replies to the same parent message
                             self.queue_split_message(message, max_len, responder, |chunk| {
-                                ClientMessage::message(channel.to_string(), chunk)
+                                ClientMessage::reply(
+                                    channel.to_string(),
+                                    parent_msg_id.to_string(),
+                                    chunk,
+                                )
                             });
-
                             Poll::Ready(self.pop_queue())
                         } else {
                             Poll::Ready(Some(SentClientMessage {
@@ -64,7 +117,7 @@ where
                         ref message,
                     } => {
                         // subtract the length of "/w username " from the length limit
-                        let whisper_max_len = max_len - 4 - recipient.len();
+                        let whisper_max_len = max_len.saturating_sub(4 + recipient.len()).max(1);
                         self.queue_split_message(message, whisper_max_len, responder, |chunk| {
                             ClientMessage::whisper(recipient.to_string(), chunk)
                         });
@@ -93,6 +146,47 @@ where
             stream,
             pending_messages: VecDeque::new(),
             max_len,
+            max_chunks: DEFAULT_MAX_CHUNKS,
+        }
+    }
+
+    /// Set the maximum number of chunks a single message may be split into. Text beyond that is
+    /// dropped with a warning, so that a pathologically long message can't fill up the queue. The
+    /// message is then reported as sent with `MessageResponse::Truncated`. Values below 1 are
+    /// treated as 1. Defaults to [`DEFAULT_MAX_CHUNKS`](constant.DEFAULT_MAX_CHUNKS.html).
+    pub fn max_chunks(mut self, max_chunks: usize) -> Self {
+        self.max_chunks = max_chunks.max(1);
+        self
+    }
+
+    /// Splits the text of a channel message, keeping an action wrapper or announce command on
+    /// every chunk. `map_to_message` creates a message from the full text of a chunk.
+    fn queue_split_channel_message(
+        &mut self,
+        message: &str,
+        responder: MessageResponder,
+        map_to_message: impl Fn(String) -> ClientMessage,
+    ) {
+        let max_len = self.max_len;
+        if let Some(text) = action_text(message) {
+            // every chunk is wrapped as an action again
+            let action_max_len = max_len
+                .saturating_sub(ACTION_PREFIX.len() + ACTION_SUFFIX.len())
+                .max(1);
+            self.queue_split_message(text, action_max_len, responder, |chunk| {
+                map_to_message(format!("{}{}{}", ACTION_PREFIX, chunk, ACTION_SUFFIX))
+            });
+        } else if let Some(prefix) = announce_prefix(message) {
+            // every chunk is announced again
+            let announce_max_len = max_len.saturating_sub(prefix.len()).max(1);
+            self.queue_split_message(
+                &message[prefix.len()..],
+                announce_max_len,
+                responder,
+                |chunk| map_to_message(format!("{}{}", prefix, chunk)),
+            );
+        } else {
+            self.queue_split_message(message, max_len, responder, map_to_message);
         }
     }
 
@@ -108,26 +202,36 @@ where
         // receivers for send results from the individual chunks of the message
         let chunk_results = FuturesUnordered::new();
 
-        self.pending_messages.extend(
-            string_chunks(message, max_len)
-                .into_iter()
-                .map(map_to_message)
-                .map(|message| {
-                    let (tx, rx) = message_responder_channel();
-                    chunk_results.push(rx);
-                    SentClientMessage {
-                        message,
-                        responder: tx,
-                    }
-                }),
-        );
+        let chunks = string_chunks(message, max_len, self.max_chunks);
+        let split_len: usize = chunks.iter().map(String::len).sum();
+        let dropped_bytes = message.len() - split_len;
+        if dropped_bytes > 0 {
+            warn!(
+                "Message would be split into more than {} chunks, dropping the last {} bytes",
+                self.max_chunks, dropped_bytes
+            );
+        }
+
+        self.pending_messages
+            .extend(chunks.into_iter().map(map_to_message).map(|message| {
+                let (tx, rx) = message_responder_channel();
+                chunk_results.push(rx);
+                SentClientMessage {
+                    message,
+                    responder: tx,
+                }
+            }));
 
-        tokio::spawn(async {
+        runtime::spawn(async move {
             if let Some(Ok(result)) = chunk_results
                 .take_while(|res| res.is_ok())
                 .fold(None, |_, res| Some(res))
                 .await
             {
+                let result = match result {
+                    Ok(_) if dropped_bytes > 0 => Ok(MessageResponse::Truncated { dropped_bytes }),
+                    result => result,
+                };
                 responder.send(result).ok();
             }
         });
@@ -138,12 +242,12 @@ where
     }
 }
 
-fn string_chunks(string: &str, sub_len: usize) -> Vec<String> {
-    let mut subs = Vec::with_capacity(string.len() / sub_len);
+fn string_chunks(string: &str, sub_len: usize, max_chunks: usize) -> Vec<String> {
+    let mut subs = Vec::with_capacity(max_chunks.min(string.len() / sub_len + 1));
     let mut iter = string.chars();
     let mut pos = 0;
 
-    while pos < string.len() {
+    while pos < string.len() && subs.len() < max_chunks {
         let mut len = 0;
         for ch in iter.by_ref().take(sub_len) {
             len += ch.len_utf8();
@@ -160,8 +264,9 @@ mod test {
 
     use futures::{stream, StreamExt};
 
+    use crate::client_messages::action_text;
     use crate::stream::{message_responder_channel, SendStreamExt, SentClientMessage};
-    use crate::ClientMessage;
+    use crate::{AnnounceColor, ClientMessage, MessageResponse};
 
     #[tokio::test]
     async fn test_splitting() {
@@ -196,4 +301,168 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_reply_splitting() {
+        let message = SentClientMessage {
+            message: ClientMessage::reply("#channel", "parent-id", "a".repeat(550)),
+            responder: message_responder_channel().0,
+        };
+        let mut stream = stream::iter(vec![message]).split_oversize(500);
+        for len in &[500, 50] {
+            match stream.next().await.unwrap().message {
+                ClientMessage::Reply {
+                    parent_msg_id,
+                    message,
+                    ..
+                } => {
+                    assert_eq!(parent_msg_id, "parent-id");
+                    assert_eq!(message.len(), *len);
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_action_splitting() {
+        let message = SentClientMessage {
+            message: ClientMessage::me("#channel", "a".repeat(550)),
+            responder: message_responder_channel().0,
+        };
+        let mut stream = stream::iter(vec![message]).split_oversize(500);
+        for len in &[491, 59] {
+            match stream.next().await.unwrap().message {
+                ClientMessage::PrivMsg { message, .. } => {
+                    assert!(message.len() <= 500);
+                    assert_eq!(action_text(&message).unwrap().len(), *len);
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_announce_splitting() {
+        let message = SentClientMessage {
+            message: ClientMessage::announce(
+                "#channel",
+                Some(AnnounceColor::Blue),
+                "a".repeat(550),
+            ),
+            responder: message_responder_channel().0,
+        };
+        let mut stream = stream::iter(vec![message]).split_oversize(500);
+        for len in &[486, 64] {
+            match stream.next().await.unwrap().message {
+                ClientMessage::PrivMsg { message, .. } => {
+                    assert!(message.len() <= 500);
+                    assert_eq!(message.strip_prefix("/announceblue ").unwrap().len(), *len);
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_custom_command_splitting() {
+        let message = SentClientMessage {
+            message: ClientMessage::with_command("NOTICE", "#channel", "a".repeat(550)).unwrap(),
+            responder: message_responder_channel().0,
+        };
+        let mut stream = stream::iter(vec![message]).split_oversize(500);
+        for len in &[500, 50] {
+            match stream.next().await.unwrap().message {
+                ClientMessage::CustomPrivMsg {
+                    command, message, ..
+                } => {
+                    assert_eq!(command, "NOTICE");
+                    assert_eq!(message.len(), *len);
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tiny_max_len() {
+        let messages = vec![
+            ClientMessage::me("#channel", "abc"),
+            ClientMessage::whisper("joey", "abc"),
+        ];
+        let stream = stream::iter(messages.into_iter().map(|message| SentClientMessage {
+            message,
+            responder: message_responder_channel().0,
+        }))
+        .split_oversize(5);
+        let texts = stream
+            .map(|sent| match sent.message {
+                ClientMessage::PrivMsg { message, .. } => {
+                    action_text(&message).unwrap().to_string()
+                }
+                ClientMessage::Whisper { message, .. } => message,
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(texts, vec!["a", "b", "c", "a", "b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_max_chunks() {
+        let (responder, response) = message_responder_channel();
+        let message = SentClientMessage {
+            message: ClientMessage::message("#channel", "a".repeat(10_000_000)),
+            responder,
+        };
+        let mut stream = stream::iter(vec![message])
+            .split_oversize(500)
+            .max_chunks(3);
+        for _ in 0..3 {
+            match stream.next().await.unwrap() {
+                SentClientMessage {
+                    message: ClientMessage::PrivMsg { message, .. },
+                    responder,
+                } => {
+                    assert_eq!(message.len(), 500);
+                    responder.send(Ok(MessageResponse::Ok)).ok();
+                }
+                _ => unreachable!(),
+            }
+        }
+        assert!(stream.next().await.is_none());
+        assert_eq!(
+            response.await.unwrap().unwrap(),
+            MessageResponse::Truncated {
+                dropped_bytes: 10_000_000 - 1500
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_max_chunks() {
+        let (responder, response) = message_responder_channel();
+        let message = SentClientMessage {
+            message: ClientMessage::message("#channel", "a".repeat(600)),
+            responder,
+        };
+        let mut stream = stream::iter(vec![message])
+            .split_oversize(500)
+            .max_chunks(0);
+        let chunk = stream.next().await.unwrap();
+        assert!(matches!(
+            &chunk.message,
+            ClientMessage::PrivMsg { message, .. } if message.len() == 500
+        ));
+        chunk.responder.send(Ok(MessageResponse::Ok)).ok();
+        assert!(stream.next().await.is_none());
+        assert_eq!(
+            response.await.unwrap().unwrap(),
+            MessageResponse::Truncated { dropped_bytes: 100 }
+        );
+    }
 }
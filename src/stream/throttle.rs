@@ -0,0 +1,280 @@
+//! Limits the rate of events per channel
+
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::time::Duration;
+
+use fnv::FnvHashMap;
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::{FutureExt, StreamExt};
+
+use crate::event::{Event, HasChannel};
+use crate::runtime::{delay_until, Delay, Instant};
+
+/// Default for the maximum number of events held back in delay mode, see
+/// [`ThrottlePerChannel::max_delayed`](struct.ThrottlePerChannel.html#method.max_delayed)
+pub const DEFAULT_MAX_DELAYED: usize = 1000;
+
+/// What happens to events that arrive before the interval of their channel has passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThrottleMode {
+    /// Drop the event
+    Drop,
+    /// Hold the event back until the interval has passed. Events of the same channel stay in
+    /// order, events of other channels are not held up by it.
+    Delay,
+}
+
+/// Passes on at most one event per channel and interval. See
+/// [`throttle_per_channel`](../trait.ReceiveStreamExt.html#method.throttle_per_channel).
+#[derive(Debug)]
+pub struct ThrottlePerChannel<St, E> {
+    stream: St,
+    min_interval: Duration,
+    mode: ThrottleMode,
+    /// Time each channel last emitted an event, or is scheduled to in delay mode
+    last_emit: FnvHashMap<String, Instant>,
+    /// When channels whose interval has passed were last removed from `last_emit`
+    last_pruned: Instant,
+    /// Delayed events, ordered by the time they are due and then by arrival
+    delayed: BTreeMap<(Instant, u64), E>,
+    max_delayed: usize,
+    /// Arrival counter that keeps delayed events with the same due time in order
+    delayed_count: u64,
+    timer: Option<Delay>,
+    stream_ended: bool,
+}
+
+impl<St: Unpin, E> Unpin for ThrottlePerChannel<St, E> {}
+
+impl<St, E> ThrottlePerChannel<St, E> {
+    pub(crate) fn new(stream: St, min_interval: Duration, mode: ThrottleMode) -> Self {
+        ThrottlePerChannel {
+            stream,
+            min_interval,
+            mode,
+            last_emit: Default::default(),
+            last_pruned: Instant::now(),
+            delayed: BTreeMap::new(),
+            max_delayed: DEFAULT_MAX_DELAYED,
+            delayed_count: 0,
+            timer: None,
+            stream_ended: false,
+        }
+    }
+
+    /// Set the maximum number of events held back in delay mode. Events arriving while that many
+    /// are waiting are dropped with a warning, so that a busy channel can't fill up memory.
+    /// Defaults to [`DEFAULT_MAX_DELAYED`](constant.DEFAULT_MAX_DELAYED.html).
+    pub fn max_delayed(mut self, max_delayed: usize) -> Self {
+        self.max_delayed = max_delayed;
+        self
+    }
+}
+
+impl<St, E> ThrottlePerChannel<St, E>
+where
+    E: Deref<Target = Event<String>>,
+{
+    /// Time at which the event may be passed on, `None` if it is dropped
+    fn schedule(&mut self, event: &E, now: Instant) -> Option<Instant> {
+        let channel = match event.channel() {
+            Some(channel) => channel,
+            None => return Some(now),
+        };
+        let due = match self.last_emit.get(channel) {
+            Some(last) if *last + self.min_interval > now => match self.mode {
+                ThrottleMode::Drop => return None,
+                ThrottleMode::Delay if self.delayed.len() >= self.max_delayed => {
+                    warn!(
+                        "More than {} events delayed, dropping event for {}",
+                        self.max_delayed, channel
+                    );
+                    return None;
+                }
+                ThrottleMode::Delay => *last + self.min_interval,
+            },
+            _ => now,
+        };
+        self.last_emit.insert(channel.to_string(), due);
+        self.prune(now);
+        Some(due)
+    }
+
+    /// Forget channels whose interval has passed, they are treated like unknown channels anyway.
+    /// Runs at most once per interval.
+    fn prune(&mut self, now: Instant) {
+        if self.last_pruned + self.min_interval > now {
+            return;
+        }
+        let min_interval = self.min_interval;
+        self.last_emit.retain(|_, last| *last + min_interval > now);
+        self.last_pruned = now;
+    }
+
+    fn push_delayed(&mut self, due: Instant, event: E) {
+        self.delayed.insert((due, self.delayed_count), event);
+        self.delayed_count += 1;
+    }
+}
+
+impl<St, E> Stream for ThrottlePerChannel<St, E>
+where
+    St: Stream<Item = E> + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Item = E;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        while !self.stream_ended {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => {
+                    let now = Instant::now();
+                    match self.schedule(&event, now) {
+                        Some(due) if due <= now => return Poll::Ready(Some(event)),
+                        Some(due) => self.push_delayed(due, event),
+                        None => {}
+                    }
+                }
+                Poll::Ready(None) => self.stream_ended = true,
+                Poll::Pending => break,
+            }
+        }
+
+        let key = match self.delayed.keys().next() {
+            Some(key) => *key,
+            None if self.stream_ended => return Poll::Ready(None),
+            None => return Poll::Pending,
+        };
+        let due = key.0;
+        if due <= Instant::now() {
+            self.timer = None;
+            return Poll::Ready(self.delayed.remove(&key));
+        }
+        let timer = self.timer.get_or_insert_with(|| delay_until(due));
+        if timer.deadline() != due {
+            timer.reset(due);
+        }
+        match timer.poll_unpin(cx) {
+            Poll::Ready(()) => {
+                self.timer = None;
+                Poll::Ready(self.delayed.remove(&key))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use futures::channel::mpsc::unbounded;
+    use futures::StreamExt;
+    use tokio::time::{advance, pause, Duration};
+
+    use crate::event::Event;
+    use crate::stream::throttle::ThrottleMode;
+    use crate::stream::ReceiveStreamExt;
+    use crate::util::test_events::event;
+
+    fn privmsg(channel: &str, text: &str) -> Arc<Event<String>> {
+        event(&format!(
+            ":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG {} :{}",
+            channel, text
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_throttle_drop() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut stream = recv.throttle_per_channel(Duration::from_secs(1), ThrottleMode::Drop);
+
+        snd.unbounded_send(privmsg("#dallas", "1")).unwrap();
+        snd.unbounded_send(privmsg("#dallas", "dropped")).unwrap();
+        snd.unbounded_send(event("PING :tmi.twitch.tv")).unwrap();
+        snd.unbounded_send(privmsg("#ronni", "2")).unwrap();
+        assert_eq!(stream.next().await.unwrap().message().unwrap(), "1");
+        assert!(matches!(*stream.next().await.unwrap(), Event::Ping(_)));
+        assert_eq!(stream.next().await.unwrap().message().unwrap(), "2");
+
+        advance(Duration::from_secs(1)).await;
+        snd.unbounded_send(privmsg("#dallas", "3")).unwrap();
+        drop(snd);
+        assert_eq!(stream.next().await.unwrap().message().unwrap(), "3");
+        assert!(stream.next().await.is_none());
+    }
+
+    // the paused clock doesn't apply to async-std timers
+    #[cfg(not(feature = "async-std"))]
+    #[tokio::test]
+    async fn test_throttle_delay() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut stream = recv.throttle_per_channel(Duration::from_secs(1), ThrottleMode::Delay);
+
+        for text in &["1", "2", "3"] {
+            snd.unbounded_send(privmsg("#dallas", text)).unwrap();
+        }
+        snd.unbounded_send(privmsg("#ronni", "other")).unwrap();
+        drop(snd);
+
+        let start = tokio::time::Instant::now();
+        let mut received = vec![];
+        while let Some(event) = stream.next().await {
+            received.push((
+                event.message().unwrap().to_string(),
+                (tokio::time::Instant::now() - start).as_secs(),
+            ));
+        }
+        assert_eq!(
+            received,
+            vec![
+                ("1".to_string(), 0),
+                ("other".to_string(), 0),
+                ("2".to_string(), 1),
+                ("3".to_string(), 2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttle_max_delayed() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut stream = recv
+            .throttle_per_channel(Duration::from_secs(1), ThrottleMode::Delay)
+            .max_delayed(2);
+
+        for text in &["1", "2", "3", "dropped"] {
+            snd.unbounded_send(privmsg("#dallas", text)).unwrap();
+        }
+        drop(snd);
+        let mut received = vec![];
+        while let Some(event) = stream.next().await {
+            received.push(event.message().unwrap().to_string());
+        }
+        assert_eq!(received, vec!["1", "2", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_throttle_forgets_channels() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut stream = recv.throttle_per_channel(Duration::from_secs(1), ThrottleMode::Drop);
+
+        for channel in &["#a", "#b", "#c"] {
+            snd.unbounded_send(privmsg(channel, "hi")).unwrap();
+            stream.next().await.unwrap();
+        }
+        assert_eq!(stream.last_emit.len(), 3);
+
+        advance(Duration::from_secs(2)).await;
+        snd.unbounded_send(privmsg("#d", "hi")).unwrap();
+        stream.next().await.unwrap();
+        assert_eq!(stream.last_emit.keys().collect::<Vec<_>>(), vec!["#d"]);
+    }
+}
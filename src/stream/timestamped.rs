@@ -0,0 +1,71 @@
+//! Tags events with the time they were received
+
+use std::ops::Deref;
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::StreamExt;
+
+use crate::event::Event;
+use crate::runtime::Instant;
+
+/// Pairs events with the local time they were received. See
+/// [`timestamped`](../trait.ReceiveStreamExt.html#method.timestamped).
+#[derive(Debug)]
+pub struct Timestamped<St> {
+    stream: St,
+}
+
+impl<St> Timestamped<St> {
+    pub(crate) fn new(stream: St) -> Self {
+        Timestamped { stream }
+    }
+}
+
+impl<St, E> Stream for Timestamped<St>
+where
+    St: Stream<Item = E> + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Item = (Instant, E);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.stream
+            .poll_next_unpin(cx)
+            .map(|event| event.map(|event| (Instant::now(), event)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::channel::mpsc::unbounded;
+    use futures::StreamExt;
+    use tokio::time::{advance, pause, Duration};
+
+    use crate::stream::ReceiveStreamExt;
+    use crate::util::test_events::event;
+
+    #[tokio::test]
+    async fn test_timestamped() {
+        pause();
+        let (snd, recv) = unbounded();
+        let mut stream = recv.timestamped();
+
+        let mut timestamps = vec![];
+        for (i, delay) in [0, 10, 0, 250].iter().enumerate() {
+            advance(Duration::from_millis(*delay)).await;
+            let line = format!(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :{}", i);
+            snd.unbounded_send(event(&line)).unwrap();
+            let (received_at, event) = stream.next().await.unwrap();
+            assert_eq!(event.message().unwrap(), &i.to_string());
+            timestamps.push(received_at);
+        }
+        assert!(timestamps.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(timestamps[3] - timestamps[0], Duration::from_millis(260));
+    }
+}
@@ -0,0 +1,113 @@
+//! Waits for the first event matching a predicate
+
+use std::future::Future;
+use std::ops::Deref;
+use std::pin::Pin;
+
+use futures_core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+
+use crate::event::Event;
+
+/// Future resolving with the first event that matches a predicate, or `None` if the stream ends
+/// first. See [`wait_for`](../trait.ReceiveStreamExt.html#method.wait_for).
+#[derive(Debug)]
+pub struct WaitFor<'a, St, F, E> {
+    stream: &'a mut St,
+    predicate: F,
+    forward: Option<mpsc::UnboundedSender<E>>,
+}
+
+impl<'a, St, F, E> WaitFor<'a, St, F, E> {
+    pub(crate) fn new(stream: &'a mut St, predicate: F) -> Self {
+        WaitFor {
+            stream,
+            predicate,
+            forward: None,
+        }
+    }
+
+    /// Send the events that don't match to `sender` instead of dropping them
+    pub fn forward_skipped(mut self, sender: mpsc::UnboundedSender<E>) -> Self {
+        self.forward = Some(sender);
+        self
+    }
+}
+
+impl<'a, St, F, E> Future for WaitFor<'a, St, F, E>
+where
+    St: Stream<Item = E> + Unpin,
+    F: FnMut(&Event<String>) -> bool + Unpin,
+    E: Deref<Target = Event<String>>,
+{
+    type Output = Option<E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match this.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(event)) => {
+                    if (this.predicate)(&event) {
+                        return Poll::Ready(Some(event));
+                    }
+                    if let Some(forward) = &this.forward {
+                        // the receiver may not be interested anymore, keep waiting regardless
+                        forward.send(event).ok();
+                    }
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::stream;
+    use tokio::sync::mpsc;
+
+    use crate::event::Event;
+    use crate::stream::ReceiveStreamExt;
+    use crate::util::test_events::event;
+
+    fn is_privmsg_from(event: &Event<String>, user: &str) -> bool {
+        match event {
+            Event::PrivMsg(data) => data.sender().as_deref() == Some(user),
+            _ => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_wait_for() {
+        let mut events = stream::iter(vec![
+            event(":ronni!ronni@ronni.tmi.twitch.tv JOIN #dallas"),
+            event(":ronni!ronni@ronni.tmi.twitch.tv PRIVMSG #dallas :hello"),
+            event("PING :tmi.twitch.tv"),
+            event(":fred!fred@fred.tmi.twitch.tv PRIVMSG #dallas :first"),
+            event(":fred!fred@fred.tmi.twitch.tv PRIVMSG #dallas :second"),
+        ]);
+        let (sender, mut skipped) = mpsc::unbounded_channel();
+
+        let found = events
+            .wait_for(|event| is_privmsg_from(event, "fred"))
+            .forward_skipped(sender)
+            .await
+            .unwrap();
+        assert_eq!(found.message(), Some(&"first".to_string()));
+
+        let mut skipped_count = 0;
+        while let Ok(event) = skipped.try_recv() {
+            assert!(!is_privmsg_from(&event, "fred"));
+            skipped_count += 1;
+        }
+        assert_eq!(skipped_count, 3);
+
+        // later events are still available from the stream
+        let next = events.wait_for(|_| true).await.unwrap();
+        assert_eq!(next.message(), Some(&"second".to_string()));
+        assert!(events.wait_for(|_| true).await.is_none());
+    }
+}
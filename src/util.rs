@@ -6,6 +6,81 @@ use tokio::sync::{broadcast, mpsc, mpsc::error::TrySendError};
 
 use crate::EventChannelError;
 
+/// Dispatches a log line to either `tracing` or `log`, depending on the `tracing` feature.
+///
+/// Leading `name = value;` pairs are recorded as structured fields by `tracing` and ignored
+/// by `log`, so the message itself should still contain everything that is relevant.
+#[cfg(feature = "tracing")]
+macro_rules! log_event {
+    ($level:ident, $($field:ident = $value:expr),+; $($arg:tt)+) => {
+        tracing::$level!($($field = %$value),+, $($arg)+)
+    };
+    ($level:ident, $($arg:tt)+) => {
+        tracing::$level!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_event {
+    ($level:ident, $($field:ident = $value:expr),+; $($arg:tt)+) => {
+        log::$level!($($arg)+)
+    };
+    ($level:ident, $($arg:tt)+) => {
+        log::$level!($($arg)+)
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)+) => { log_event!(error, $($arg)+) };
+}
+
+macro_rules! warn {
+    ($($arg:tt)+) => { log_event!(warn, $($arg)+) };
+}
+
+macro_rules! info {
+    ($($arg:tt)+) => { log_event!(info, $($arg)+) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { log_event!(debug, $($arg)+) };
+}
+
+/// Increments a counter through the `metrics` facade if the `metrics` feature is enabled.
+/// Otherwise the value and labels are evaluated and discarded.
+#[cfg(feature = "metrics")]
+macro_rules! record_counter {
+    ($name:expr, $value:expr) => {
+        metrics::counter!($name, $value)
+    };
+    ($name:expr, $value:expr, $($label:expr => $label_value:expr),+) => {
+        metrics::counter!($name, $value, $($label => $label_value),+)
+    };
+}
+
+#[cfg(not(feature = "metrics"))]
+macro_rules! record_counter {
+    ($name:expr, $value:expr $(, $label:expr => $label_value:expr)*) => {{
+        let _ = $value;
+        $(let _ = $label_value;)*
+    }};
+}
+
+/// Records a duration in a histogram through the `metrics` facade, see `record_counter`
+#[cfg(feature = "metrics")]
+macro_rules! record_duration {
+    ($name:expr, $value:expr) => {
+        metrics::timing!($name, $value)
+    };
+}
+
+#[cfg(not(feature = "metrics"))]
+macro_rules! record_duration {
+    ($name:expr, $value:expr) => {{
+        let _ = $value;
+    }};
+}
+
 pub(crate) trait RefToString {
     fn ref_to_string(&self) -> String;
 }
@@ -81,3 +156,167 @@ impl<T> Sink<T> for InternalSender<broadcast::Sender<T>> {
         Poll::Ready(Ok(()))
     }
 }
+
+/// Logger for tests that records all log lines so they can be checked in assertions
+#[cfg(test)]
+pub(crate) mod test_logger {
+    use log::{Metadata, Record};
+    use parking_lot::Mutex;
+
+    struct CapturingLogger;
+
+    static LOGGER: CapturingLogger = CapturingLogger;
+    static LINES: Mutex<Vec<String>> = parking_lot::const_mutex(Vec::new());
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            LINES.lock().push(format!("{}", record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Install the capturing logger, can be called any number of times
+    pub(crate) fn init() {
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(log::LevelFilter::Trace);
+        }
+    }
+
+    /// All lines logged so far, by any test
+    pub(crate) fn lines() -> Vec<String> {
+        LINES.lock().clone()
+    }
+}
+
+/// Metrics recorder for tests that records all values so they can be checked in assertions
+#[cfg(all(test, feature = "metrics"))]
+pub(crate) mod test_metrics {
+    use metrics::{Key, Recorder};
+    use parking_lot::Mutex;
+
+    struct CapturingRecorder;
+
+    static RECORDER: CapturingRecorder = CapturingRecorder;
+    type Labels = Vec<(String, String)>;
+
+    static VALUES: Mutex<Vec<(String, Labels, u64)>> = parking_lot::const_mutex(Vec::new());
+
+    impl CapturingRecorder {
+        fn record(&self, key: Key, value: u64) {
+            let labels = key
+                .labels()
+                .map(|label| (label.key().to_string(), label.value().to_string()))
+                .collect();
+            VALUES.lock().push((key.name().to_string(), labels, value));
+        }
+    }
+
+    impl Recorder for CapturingRecorder {
+        fn increment_counter(&self, key: Key, value: u64) {
+            self.record(key, value);
+        }
+
+        fn update_gauge(&self, key: Key, value: i64) {
+            self.record(key, value as u64);
+        }
+
+        fn record_histogram(&self, key: Key, value: u64) {
+            self.record(key, value);
+        }
+    }
+
+    /// Install the capturing recorder, can be called any number of times
+    pub(crate) fn init() {
+        metrics::set_recorder(&RECORDER).ok();
+    }
+
+    /// All values recorded for a metric so far, by any test
+    pub(crate) fn values(name: &str) -> Vec<u64> {
+        VALUES
+            .lock()
+            .iter()
+            .filter(|(key, _, _)| key == name)
+            .map(|(_, _, value)| *value)
+            .collect()
+    }
+
+    /// Values recorded for a metric with the given label, by any test
+    pub(crate) fn labeled_values(name: &str, label: (&str, &str)) -> Vec<u64> {
+        VALUES
+            .lock()
+            .iter()
+            .filter(|(key, labels, _)| {
+                key == name
+                    && labels
+                        .iter()
+                        .any(|(key, value)| (key.as_str(), value.as_str()) == label)
+            })
+            .map(|(_, _, value)| *value)
+            .collect()
+    }
+}
+
+/// Helpers to parse IRC lines into events in tests
+#[cfg(test)]
+pub(crate) mod test_events {
+    use std::convert::TryFrom;
+    use std::sync::Arc;
+
+    use crate::event::*;
+    use crate::irc::IrcMessage;
+
+    /// Parse an IRC line into an event borrowing from the line
+    pub(crate) fn parse(line: &str) -> Event<&str> {
+        Event::try_from(IrcMessage::parse(line).unwrap().1).unwrap()
+    }
+
+    /// Parse an IRC line into an owned event, like the ones passed through the event streams
+    pub(crate) fn event(line: &str) -> Arc<Event<String>> {
+        Arc::new(Event::<String>::from(&parse(line)))
+    }
+
+    /// Parse an IRC line that has to be a PRIVMSG
+    pub(crate) fn privmsg(line: &str) -> EventData<&str, PrivMsgEvent<&str>> {
+        match parse(line) {
+            Event::PrivMsg(event) => event,
+            other => panic!("expected privmsg, got {:?}", other),
+        }
+    }
+
+    /// Parse an IRC line that has to be a NOTICE
+    pub(crate) fn notice(line: &str) -> EventData<&str, NoticeEvent<&str>> {
+        match parse(line) {
+            Event::Notice(event) => event,
+            other => panic!("expected notice, got {:?}", other),
+        }
+    }
+
+    /// Parse an IRC line that has to be a USERNOTICE
+    pub(crate) fn user_notice(line: &str) -> EventData<&str, UserNoticeEvent<&str>> {
+        match parse(line) {
+            Event::UserNotice(event) => event,
+            other => panic!("expected usernotice, got {:?}", other),
+        }
+    }
+
+    /// Parse an IRC line that has to be a ROOMSTATE
+    pub(crate) fn room_state(line: &str) -> EventData<&str, RoomStateEvent<&str>> {
+        match parse(line) {
+            Event::RoomState(event) => event,
+            other => panic!("expected roomstate, got {:?}", other),
+        }
+    }
+
+    /// Parse an IRC line that has to be a GLOBALUSERSTATE
+    pub(crate) fn global_user_state(line: &str) -> EventData<&str, GlobalUserStateEvent> {
+        match parse(line) {
+            Event::GlobalUserState(event) => event,
+            other => panic!("expected globaluserstate, got {:?}", other),
+        }
+    }
+}